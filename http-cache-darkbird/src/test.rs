@@ -57,7 +57,9 @@ async fn darkbird() -> Result<()> {
     assert_eq!(data.unwrap().0.body, TEST_BODY);
     assert!(manager.cache.lookup(&format!("{}:{}", GET, &url)).is_some());
     assert!(!manager.cache.lookup_by_tag(http_res.url.as_str()).is_empty());
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
     manager.delete(&format!("{}:{}", GET, &url)).await?;
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
 
@@ -148,6 +150,7 @@ async fn default_mode_with_options() -> Result<()> {
                     shared: false,
                     ..Default::default()
                 }),
+                ..Default::default()
             },
         }))
         .build();
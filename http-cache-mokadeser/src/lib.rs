@@ -64,11 +64,41 @@ impl CacheManager for MokaManager {
         Ok(response)
     }
 
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.contains_key(cache_key))
+    }
+
     async fn delete(&self, cache_key: &str) -> Result<()> {
         self.cache.invalidate(cache_key).await;
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+
+    async fn clear(&self) -> Result<()> {
+        MokaManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let cache_keys = self
+            .cache
+            .iter()
+            .filter(|(cache_key, _)| predicate(cache_key))
+            .map(|(cache_key, _)| cache_key)
+            .collect::<Vec<_>>();
+        for cache_key in cache_keys {
+            self.cache.invalidate(cache_key.as_str()).await;
+        }
+        self.cache.run_pending_tasks().await;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.cache.run_pending_tasks().await;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
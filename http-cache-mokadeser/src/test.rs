@@ -52,9 +52,24 @@ async fn moka() -> Result<()> {
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_some());
     assert_eq!(data.unwrap().0.body, TEST_BODY);
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
     manager.delete(&format!("{}:{}", GET, &url)).await?;
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
+
+    let other_key = format!("{}:http://other.com/", GET);
+    manager
+        .put(format!("{}:{}", GET, &url), http_res.clone(), policy.clone())
+        .await?;
+    manager.put(other_key.clone(), http_res, policy).await?;
+    manager
+        .delete_matching(&|cache_key: &str| {
+            cache_key.contains("://example.com/")
+        })
+        .await?;
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
+    assert!(manager.contains(&other_key).await?);
     Ok(())
 }
 
@@ -107,6 +122,7 @@ async fn default_mode_with_options() -> Result<()> {
                     shared: false,
                     ..Default::default()
                 }),
+                ..Default::default()
             },
         }))
         .build();
@@ -64,10 +64,19 @@ impl CacheManager for QuickManager {
         Ok(response)
     }
 
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.peek(cache_key).is_some())
+    }
+
     async fn delete(&self, cache_key: &str) -> Result<()> {
         self.cache.remove(cache_key);
         Ok(())
     }
+
+    async fn clear(&self) -> Result<()> {
+        self.cache.clear();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -52,7 +52,9 @@ async fn quickcache() -> Result<()> {
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_some());
     assert_eq!(data.unwrap().0.body, TEST_BODY);
+    assert!(manager.contains(&format!("{}:{}", GET, &url)).await?);
     manager.delete(&format!("{}:{}", GET, &url)).await?;
+    assert!(!manager.contains(&format!("{}:{}", GET, &url)).await?);
     let data = manager.get(&format!("{}:{}", GET, &url)).await?;
     assert!(data.is_none());
     Ok(())
@@ -107,6 +109,7 @@ async fn default_mode_with_options() -> Result<()> {
                     shared: false,
                     ..Default::default()
                 }),
+                ..Default::default()
             },
         }))
         .build();
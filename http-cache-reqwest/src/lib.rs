@@ -40,29 +40,34 @@ use anyhow::anyhow;
 pub use error::BadRequest;
 
 use std::{
-    collections::HashMap,
     convert::{TryFrom, TryInto},
     str::FromStr,
-    time::SystemTime,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 pub use http::request::Parts;
 use http::{
-    header::{HeaderName, CACHE_CONTROL},
+    header::{HeaderName, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH},
     HeaderValue, Method,
 };
 use http_cache::{
-    BoxError, HitOrMiss, Middleware, Result, XCACHE, XCACHELOOKUP,
+    BoxError, Middleware, Result, CACHESTATUS, XCACHE, XCACHELOOKUP,
 };
 use http_cache_semantics::CachePolicy;
-use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest::{Client, Request, Response, ResponseBuilderExt};
 use reqwest_middleware::{Error, Next};
 use task_local_extensions::Extensions;
 use url::Url;
 
 pub use http_cache::{
-    CacheManager, CacheMode, CacheOptions, HttpCache, HttpCacheOptions,
-    HttpResponse,
+    BodyStream, CacheDecision, CacheEntry, CacheEvent, CacheEventHook,
+    CacheKey, CacheManager, CacheMode, CacheOptions, CachePartition, CacheRule,
+    CacheStats, CacheStatus, Cacheable, Clock, Coalescer, ContentTypeFreshness,
+    HitOrMiss, HttpCache, HttpCacheBuilder, HttpCacheOptions, HttpHeaders,
+    HttpResponse, InMemoryManager, ModifyUpstreamRequest, NegativeCaching,
+    NoopManager, PruneReport, QuotaManager, Revalidate, Spawn, SystemClock,
+    TieredManager, TransformResponse,
 };
 
 #[cfg(feature = "manager-cacache")]
@@ -73,15 +78,496 @@ pub use http_cache::CACacheManager;
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
 pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
 
+#[cfg(feature = "manager-redis")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-redis")))]
+pub use http_cache::RedisManager;
+
+#[cfg(feature = "manager-postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-postgres")))]
+pub use http_cache::PostgresManager;
+
+#[cfg(feature = "manager-rocksdb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-rocksdb")))]
+pub use http_cache::RocksDbManager;
+
+#[cfg(feature = "manager-sled")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-sled")))]
+pub use http_cache::SledManager;
+
+#[cfg(feature = "manager-dynamodb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-dynamodb")))]
+pub use http_cache::DynamoDbManager;
+
+#[cfg(feature = "manager-compressed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-compressed")))]
+pub use http_cache::CompressedManager;
+
+#[cfg(feature = "manager-instrumented")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-instrumented")))]
+pub use http_cache::InstrumentedManager;
+
+#[cfg(feature = "manager-replay")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-replay")))]
+pub use http_cache::{ReplayManager, ReplayMode};
+
+#[cfg(feature = "manager-encrypted")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-encrypted")))]
+pub use http_cache::EncryptedManager;
+
+#[cfg(feature = "manager-filesystem")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-filesystem")))]
+pub use http_cache::FileSystemManager;
+
+#[cfg(feature = "manager-foyer")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-foyer")))]
+pub use http_cache::FoyerManager;
+
+#[cfg(feature = "manager-lmdb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-lmdb")))]
+pub use http_cache::LmdbManager;
+
+#[cfg(feature = "manager-memcached")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-memcached")))]
+pub use http_cache::MemcachedManager;
+
+#[cfg(feature = "manager-mongodb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-mongodb")))]
+pub use http_cache::MongoManager;
+
+#[cfg(feature = "manager-object-store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-object-store")))]
+pub use http_cache::ObjectStoreManager;
+
 /// Wrapper for [`HttpCache`]
 #[derive(Debug)]
 pub struct Cache<T: CacheManager>(pub HttpCache<T>);
 
+impl<T: CacheManager> Cache<T> {
+    /// Creates a new [`Cache`] with the default mode and options
+    pub fn with_manager(manager: T) -> Self {
+        Self(HttpCache::with_manager(manager))
+    }
+
+    /// Returns the cache manager, see [`HttpCache::manager`]
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+
+    /// Removes the cached response for the given method and url,
+    /// see [`HttpCache::invalidate`]
+    pub async fn invalidate(&self, method: &str, url: &Url) -> Result<()> {
+        self.0.invalidate(method, url).await
+    }
+
+    /// Marks the cached response for the given method and url stale,
+    /// see [`HttpCache::soft_purge`]
+    pub async fn soft_purge(&self, method: &str, url: &Url) -> Result<()> {
+        self.0.soft_purge(method, url).await
+    }
+
+    /// Removes every cached response, see [`HttpCache::clear`]
+    pub async fn clear(&self) -> Result<()> {
+        self.0.clear().await
+    }
+
+    /// Waits for every write made so far to be persisted,
+    /// see [`HttpCache::flush`]
+    pub async fn flush(&self) -> Result<()> {
+        self.0.flush().await
+    }
+
+    /// Removes every cached response whose cache key matches the predicate,
+    /// see [`HttpCache::clear_matching`]
+    pub async fn clear_matching(
+        &self,
+        predicate: impl Fn(&str) -> bool + Send + Sync,
+    ) -> Result<()> {
+        self.0.clear_matching(predicate).await
+    }
+
+    /// Removes every cached response whose cache key starts with the prefix,
+    /// see [`HttpCache::clear_prefix`]
+    pub async fn clear_prefix(&self, prefix: &str) -> Result<()> {
+        self.0.clear_prefix(prefix).await
+    }
+
+    /// Describes every cached response, see [`HttpCache::entries`]
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        self.0.entries().await
+    }
+
+    /// Removes every cached response that has been stale for at least the
+    /// grace period, see [`HttpCache::prune_expired`]
+    pub async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        self.0.prune_expired(grace).await
+    }
+
+    /// Counts the cached responses and the bytes they take up,
+    /// see [`HttpCache::stats`]
+    pub async fn stats(&self) -> Result<CacheStats> {
+        self.0.stats().await
+    }
+
+    /// Checks if a response for the given method and url is cached,
+    /// see [`HttpCache::contains`]
+    pub async fn contains(&self, method: &str, url: &Url) -> Result<bool> {
+        self.0.contains(method, url).await
+    }
+
+    /// Returns whether the cached response for the given method and url is
+    /// fresh, see [`HttpCache::is_fresh`]
+    pub async fn is_fresh(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<bool>> {
+        self.0.is_fresh(method, url).await
+    }
+
+    /// Describes the cached response for the given method and url,
+    /// see [`HttpCache::lookup`]
+    pub async fn lookup(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<CacheEntry>> {
+        self.0.lookup(method, url).await
+    }
+
+    /// Populates the cache with the responses to `GET` requests for the
+    /// urls, sending at most `concurrency` at a time with the given client,
+    /// see [`HttpCache::prime`]. The requests skip the rest of the middleware
+    /// stack.
+    pub async fn prime(
+        &self,
+        client: &Client,
+        urls: impl IntoIterator<Item = Url>,
+        concurrency: usize,
+    ) -> Result<()> {
+        self.0
+            .prime(urls, concurrency, |req| {
+                Some(ReqwestMiddleware {
+                    req: Request::try_from(req).ok()?,
+                    upstream: Upstream::Client(client.clone()),
+                })
+            })
+            .await
+    }
+}
+
+impl<T: CacheManager + Clone> Cache<T> {
+    /// Revalidates stale responses within their `stale-while-revalidate`
+    /// window in the background, sending the requests with the given client,
+    /// see [`HttpCache::revalidate_in_background`]. The requests skip the
+    /// rest of the middleware stack.
+    pub fn revalidate_in_background(
+        self,
+        client: Client,
+        spawn: Spawn,
+    ) -> Self {
+        Self(self.0.revalidate_in_background(spawn, move |req| {
+            Some(ReqwestMiddleware {
+                req: Request::try_from(req).ok()?,
+                upstream: Upstream::Client(client.clone()),
+            })
+        }))
+    }
+}
+
+impl<T: CacheManager> From<HttpCache<T>> for Cache<T> {
+    fn from(cache: HttpCache<T>) -> Self {
+        Self(cache)
+    }
+}
+
+/// Builder for [`Cache`], see [`HttpCacheBuilder`] for the defaults
+#[derive(Debug, Clone)]
+pub struct CacheBuilder<T = ()>(HttpCacheBuilder<T>);
+
+impl Default for CacheBuilder {
+    fn default() -> Self {
+        Self(HttpCacheBuilder::new())
+    }
+}
+
+impl CacheBuilder {
+    /// Creates a new builder with the default mode and options
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> CacheBuilder<T> {
+    /// Sets the [`CacheMode`]
+    #[must_use]
+    pub fn mode(self, mode: CacheMode) -> Self {
+        Self(self.0.mode(mode))
+    }
+
+    /// Sets the manager instance that implements the [`CacheManager`] trait
+    pub fn manager<M: CacheManager>(self, manager: M) -> CacheBuilder<M> {
+        CacheBuilder(self.0.manager(manager))
+    }
+
+    /// Sets the [`HttpCacheOptions`], replacing any previously set options
+    #[must_use]
+    pub fn options(self, options: HttpCacheOptions) -> Self {
+        Self(self.0.options(options))
+    }
+
+    /// Overrides the default [`CacheOptions`]
+    #[must_use]
+    pub fn cache_options(self, cache_options: CacheOptions) -> Self {
+        Self(self.0.cache_options(cache_options))
+    }
+
+    /// Sets the rules that override the mode and cache options per request,
+    /// see [`CacheRule`]
+    #[must_use]
+    pub fn cache_rules(self, cache_rules: Vec<CacheRule>) -> Self {
+        Self(self.0.cache_rules(cache_rules))
+    }
+
+    /// Sets whether the cache is shared, the default, or private,
+    /// see [`HttpCacheBuilder::shared`]
+    #[must_use]
+    pub fn shared(self, shared: bool) -> Self {
+        Self(self.0.shared(shared))
+    }
+
+    /// Overrides the default cache key generator
+    #[must_use]
+    pub fn cache_key(self, cache_key: CacheKey) -> Self {
+        Self(self.0.cache_key(cache_key))
+    }
+
+    /// Sets the closure that partitions the cache by request
+    #[must_use]
+    pub fn cache_partition(self, cache_partition: CachePartition) -> Self {
+        Self(self.0.cache_partition(cache_partition))
+    }
+
+    /// Normalizes the url before computing the default cache key,
+    /// see [`HttpCacheOptions::normalize_url`]
+    #[must_use]
+    pub fn normalize_url(self, normalize_url: bool) -> Self {
+        Self(self.0.normalize_url(normalize_url))
+    }
+
+    /// Sets the query parameters left out of the default cache key,
+    /// see [`HttpCacheOptions::ignored_query_params`]
+    #[must_use]
+    pub fn ignored_query_params(
+        self,
+        ignored_query_params: Vec<String>,
+    ) -> Self {
+        Self(self.0.ignored_query_params(ignored_query_params))
+    }
+
+    /// Sets the closure that modifies the requests sent upstream
+    #[must_use]
+    pub fn modify_upstream_request(
+        self,
+        modify_upstream_request: ModifyUpstreamRequest,
+    ) -> Self {
+        Self(self.0.modify_upstream_request(modify_upstream_request))
+    }
+
+    /// Sets the closure that receives the events emitted by the cache
+    #[must_use]
+    pub fn on_event(self, on_event: CacheEventHook) -> Self {
+        Self(self.0.on_event(on_event))
+    }
+
+    /// Prevents the cache from storing responses or removing entries
+    #[must_use]
+    pub fn read_only(self, read_only: bool) -> Self {
+        Self(self.0.read_only(read_only))
+    }
+
+    /// Folds a hash of the request body into the cache key,
+    /// see [`HttpCacheOptions::cache_request_body`]
+    #[must_use]
+    pub fn cache_request_body(self, cache_request_body: bool) -> Self {
+        Self(self.0.cache_request_body(cache_request_body))
+    }
+
+    /// Serves every request from the cache without going to the network,
+    /// see [`HttpCacheOptions::offline`]
+    #[must_use]
+    pub fn offline(self, offline: bool) -> Self {
+        Self(self.0.offline(offline))
+    }
+
+    /// Sets the freshness bounds of responses by their `Content-Type`,
+    /// see [`ContentTypeFreshness`]
+    #[must_use]
+    pub fn content_type_freshness(
+        self,
+        content_type_freshness: Vec<ContentTypeFreshness>,
+    ) -> Self {
+        Self(self.0.content_type_freshness(content_type_freshness))
+    }
+
+    /// Sets the lower bound of the freshness lifetime of responses,
+    /// see [`HttpCacheOptions::min_freshness`]
+    #[must_use]
+    pub fn min_freshness(self, min_freshness: Duration) -> Self {
+        Self(self.0.min_freshness(min_freshness))
+    }
+
+    /// Sets the upper bound of the freshness lifetime of responses,
+    /// see [`HttpCacheOptions::max_freshness`]
+    #[must_use]
+    pub fn max_freshness(self, max_freshness: Duration) -> Self {
+        Self(self.0.max_freshness(max_freshness))
+    }
+
+    /// Sets the fraction of the time since a response was last modified that
+    /// it's considered fresh for, see [`HttpCacheBuilder::cache_heuristic`]
+    #[must_use]
+    pub fn cache_heuristic(self, cache_heuristic: f32) -> Self {
+        Self(self.0.cache_heuristic(cache_heuristic))
+    }
+
+    /// Caps the heuristic freshness lifetime of responses,
+    /// see [`HttpCacheOptions::max_heuristic_freshness`]
+    #[must_use]
+    pub fn max_heuristic_freshness(
+        self,
+        max_heuristic_freshness: Duration,
+    ) -> Self {
+        Self(self.0.max_heuristic_freshness(max_heuristic_freshness))
+    }
+
+    /// Sets the size in bytes above which response bodies aren't stored,
+    /// see [`HttpCacheOptions::max_body_size`]
+    #[must_use]
+    pub fn max_body_size(self, max_body_size: u64) -> Self {
+        Self(self.0.max_body_size(max_body_size))
+    }
+
+    /// Caches error responses with the listed statuses for a bounded time,
+    /// see [`HttpCacheOptions::negative_caching`]
+    #[must_use]
+    pub fn negative_caching(self, negative_caching: NegativeCaching) -> Self {
+        Self(self.0.negative_caching(negative_caching))
+    }
+
+    /// Purges the cached responses of an origin on `Clear-Site-Data: "cache"`,
+    /// see [`HttpCacheOptions::clear_site_data`]
+    #[must_use]
+    pub fn clear_site_data(self, clear_site_data: bool) -> Self {
+        Self(self.0.clear_site_data(clear_site_data))
+    }
+
+    /// Sets the closure that transforms responses before they're stored
+    #[must_use]
+    pub fn on_store(self, on_store: TransformResponse) -> Self {
+        Self(self.0.on_store(on_store))
+    }
+
+    /// Sets the closure that transforms responses loaded from the cache
+    #[must_use]
+    pub fn on_load(self, on_load: TransformResponse) -> Self {
+        Self(self.0.on_load(on_load))
+    }
+
+    /// Sets the closure that decides whether responses may be stored,
+    /// see [`HttpCacheOptions::cacheable`]
+    #[must_use]
+    pub fn cacheable(self, cacheable: Cacheable) -> Self {
+        Self(self.0.cacheable(cacheable))
+    }
+
+    /// Refuses to store responses with a `Set-Cookie` header,
+    /// see [`HttpCacheOptions::refuse_set_cookie`]
+    #[must_use]
+    pub fn refuse_set_cookie(self, refuse_set_cookie: bool) -> Self {
+        Self(self.0.refuse_set_cookie(refuse_set_cookie))
+    }
+
+    /// Ignores errors of the cache manager reading or storing a response,
+    /// see [`HttpCacheOptions::fail_open`]
+    #[must_use]
+    pub fn fail_open(self, fail_open: bool) -> Self {
+        Self(self.0.fail_open(fail_open))
+    }
+
+    /// Sets how long past its freshness lifetime a stale response stands in
+    /// for a failed revalidation, see [`HttpCacheOptions::stale_if_error`]
+    #[must_use]
+    pub fn stale_if_error(self, stale_if_error: Duration) -> Self {
+        Self(self.0.stale_if_error(stale_if_error))
+    }
+
+    /// Appends an RFC 9211 `Cache-Status` header to every response, see
+    /// [`HttpCacheOptions::cache_status_header`]
+    #[must_use]
+    pub fn cache_status_header(self, cache_status_header: bool) -> Self {
+        Self(self.0.cache_status_header(cache_status_header))
+    }
+
+    /// Sets the targeted cache control headers that take precedence over
+    /// `Cache-Control`, see [`HttpCacheOptions::targeted_cache_control`]
+    #[must_use]
+    pub fn targeted_cache_control(
+        self,
+        targeted_cache_control: Vec<String>,
+    ) -> Self {
+        Self(self.0.targeted_cache_control(targeted_cache_control))
+    }
+
+    /// Sets whether concurrent misses for a cache key share a single
+    /// upstream fetch, see [`HttpCacheOptions::coalescer`]
+    #[must_use]
+    pub fn coalesce_requests(self, coalesce_requests: bool) -> Self {
+        Self(self.0.coalesce_requests(coalesce_requests))
+    }
+
+    /// Sets the source of the current time the freshness and age of
+    /// responses are computed with, see [`HttpCacheOptions::clock`]
+    #[must_use]
+    pub fn clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self(self.0.clock(clock))
+    }
+}
+
+impl<T: CacheManager> CacheBuilder<T> {
+    /// Builds the [`Cache`] middleware
+    pub fn build(self) -> Cache<T> {
+        Cache(self.0.build())
+    }
+}
+
 /// Implements ['Middleware'] for reqwest
 pub(crate) struct ReqwestMiddleware<'a> {
     pub req: Request,
-    pub next: Next<'a>,
-    pub extensions: &'a mut Extensions,
+    pub upstream: Upstream<'a>,
+}
+
+// Where requests are sent, the rest of the middleware stack or, once detached
+// from it to revalidate in the background, a client of their own
+pub(crate) enum Upstream<'a> {
+    Next(Next<'a>, &'a mut Extensions),
+    Client(Client),
+}
+
+impl Upstream<'_> {
+    async fn send(
+        &mut self,
+        req: Request,
+    ) -> std::result::Result<Response, Error> {
+        match self {
+            Upstream::Next(next, extensions) => {
+                next.clone().run(req, extensions).await
+            }
+            Upstream::Client(client) => {
+                client.execute(req).await.map_err(Error::from)
+            }
+        }
+    }
 }
 
 fn clone_req(request: &Request) -> std::result::Result<Request, Error> {
@@ -103,11 +589,19 @@ impl Middleware for ReqwestMiddleware<'_> {
         &self,
         response: &HttpResponse,
         options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        self.policy_with_options_at(response, options, SystemTime::now())
+    }
+    fn policy_with_options_at(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+        now: SystemTime,
     ) -> Result<CachePolicy> {
         Ok(CachePolicy::new_options(
             &self.parts()?,
             &response.parts()?,
-            SystemTime::now(),
+            now,
             options,
         ))
     }
@@ -137,16 +631,21 @@ impl Middleware for ReqwestMiddleware<'_> {
     fn method(&self) -> Result<String> {
         Ok(self.req.method().as_ref().to_string())
     }
+    fn body(&self) -> Option<&[u8]> {
+        match self.req.body() {
+            Some(body) => body.as_bytes(),
+            None => Some(&[]),
+        }
+    }
     async fn remote_fetch(&mut self) -> Result<HttpResponse> {
         let copied_req = clone_req(&self.req)?;
-        let res = match self.next.clone().run(copied_req, self.extensions).await
-        {
+        let res = match self.upstream.send(copied_req).await {
             Ok(r) => r,
             Err(e) => return Err(Box::new(e)),
         };
-        let mut headers = HashMap::new();
+        let mut headers = HttpHeaders::new();
         for header in res.headers() {
-            headers.insert(
+            headers.append(
                 header.0.as_str().to_owned(),
                 header.1.to_str()?.to_owned(),
             );
@@ -159,6 +658,9 @@ impl Middleware for ReqwestMiddleware<'_> {
             Err(e) => return Err(Box::new(e)),
         }
         .to_vec();
+        if self.req.method() != Method::HEAD {
+            strip_decoded_encoding(&mut headers, body.len());
+        }
         Ok(HttpResponse {
             body,
             headers,
@@ -169,17 +671,32 @@ impl Middleware for ReqwestMiddleware<'_> {
     }
 }
 
+// reqwest removes the `content-encoding` and `content-length` headers when it
+// decompresses a body, but a decoding layer that doesn't would leave headers
+// describing the encoded representation next to the decoded body. When the
+// length doesn't match the body we have, the body is no longer encoded, so
+// the headers are dropped to keep replays from the cache valid.
+fn strip_decoded_encoding(headers: &mut HttpHeaders, len: usize) {
+    let content_length = headers
+        .get(CONTENT_LENGTH.as_str())
+        .and_then(|v| v.trim().parse::<usize>().ok());
+    if matches!(content_length, Some(content_length) if content_length != len) {
+        headers.remove(CONTENT_LENGTH.as_str());
+        headers.remove(CONTENT_ENCODING.as_str());
+    }
+}
+
 // Converts an [`HttpResponse`] to a reqwest [`Response`]
 fn convert_response(response: HttpResponse) -> anyhow::Result<Response> {
     let mut ret_res = http::Response::builder()
         .status(response.status)
         .url(response.url)
-        .version(response.version.try_into()?)
+        .version(response.version.into())
         .body(response.body)?;
     for header in response.headers {
-        ret_res.headers_mut().insert(
-            HeaderName::from_str(header.0.clone().as_str())?,
-            HeaderValue::from_str(header.1.clone().as_str())?,
+        ret_res.headers_mut().append(
+            HeaderName::from_str(header.0.as_str())?,
+            HeaderValue::from_str(header.1.as_str())?,
         );
     }
     Ok(Response::from(ret_res))
@@ -201,26 +718,62 @@ impl<T: CacheManager> reqwest_middleware::Middleware for Cache<T> {
         extensions: &mut Extensions,
         next: Next<'_>,
     ) -> std::result::Result<Response, Error> {
-        let mut middleware = ReqwestMiddleware { req, next, extensions };
+        let mut middleware = ReqwestMiddleware {
+            req,
+            upstream: Upstream::Next(next, extensions),
+        };
         if self.0.can_cache_request(&middleware) {
             let res = self.0.run(middleware).await.map_err(from_box_error)?;
-            let converted = convert_response(res)?;
+            let status = res.served_status();
+            let mut converted = convert_response(res)?;
+            converted.extensions_mut().insert(status);
             Ok(converted)
         } else {
             self.0
                 .run_no_cache(&mut middleware)
                 .await
                 .map_err(from_box_error)?;
-            let mut res = middleware
-                .next
-                .run(middleware.req, middleware.extensions)
-                .await?;
+            let url = middleware.req.url().clone();
+            let method = middleware.req.method().to_string();
+            let mut res = middleware.upstream.send(middleware.req).await?;
+            let header_values = |name| {
+                res.headers()
+                    .get_all(name)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .collect::<Vec<_>>()
+            };
+            self.0
+                .clear_site_data(&url, &header_values("clear-site-data"))
+                .await
+                .map_err(from_box_error)?;
+            let mut locations = header_values("location");
+            locations.extend(header_values("content-location"));
+            self.0
+                .invalidate_unsafe(
+                    &method,
+                    &url,
+                    res.status().as_u16(),
+                    &locations,
+                )
+                .await
+                .map_err(from_box_error)?;
 
             let miss =
                 HeaderValue::from_str(HitOrMiss::MISS.to_string().as_ref())
                     .map_err(bad_header)?;
             res.headers_mut().insert(XCACHE, miss.clone());
             res.headers_mut().insert(XCACHELOOKUP, miss);
+            if let Some(status) =
+                self.0.passthrough_cache_status(res.status().as_u16())
+            {
+                res.headers_mut().append(
+                    CACHESTATUS,
+                    HeaderValue::from_str(&status).map_err(bad_header)?,
+                );
+            }
+            res.extensions_mut()
+                .insert(CacheStatus { hit: HitOrMiss::MISS, stale: None });
             Ok(res)
         }
     }
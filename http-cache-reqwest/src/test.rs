@@ -1,11 +1,14 @@
-use crate::{error, Cache};
-use std::sync::Arc;
+use crate::{error, Cache, CacheBuilder};
+use std::sync::{Arc, Mutex};
 
 use http_cache::*;
 use reqwest::Client;
 use reqwest_middleware::ClientBuilder;
 use url::Url;
-use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+use wiremock::{
+    matchers::{body_string, header, header_exists, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 
 pub(crate) fn build_mock(
     cache_control_val: &str,
@@ -91,6 +94,7 @@ async fn default_mode_with_options() -> Result<()> {
                     shared: false,
                     ..Default::default()
                 }),
+                ..Default::default()
             },
         }))
         .build();
@@ -134,24 +138,19 @@ async fn no_cache_mode() -> Result<()> {
 }
 
 #[tokio::test]
-async fn custom_cache_key() -> Result<()> {
+async fn reload_mode() -> Result<()> {
     let mock_server = MockServer::start().await;
-    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
     let manager = MokaManager::default();
 
-    // Construct reqwest client with cache defaults and custom cache key
+    // Construct reqwest client with cache defaults
     let client = ClientBuilder::new(Client::new())
         .with(Cache(HttpCache {
-            mode: CacheMode::Default,
+            mode: CacheMode::Reload,
             manager: manager.clone(),
-            options: HttpCacheOptions {
-                cache_key: Some(Arc::new(|req: &http::request::Parts| {
-                    format!("{}:{}:{:?}:test", req.method, req.uri, req.version)
-                })),
-                cache_options: None,
-            },
+            options: HttpCacheOptions::default(),
         }))
         .build();
 
@@ -159,18 +158,57 @@ async fn custom_cache_key() -> Result<()> {
     client.get(url.clone()).send().await?;
 
     // Try to load cached object
-    let data = manager
-        .get(&format!("{}:{}:{:?}:test", GET, &url, http::Version::HTTP_11))
-        .await?;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    client.get(url).send().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_only_cache() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with a read only cache
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(manager.clone())
+                .read_only(true)
+                .build(),
+        )
+        .build();
+
+    // Remote request and should not cache
+    client.get(url.clone()).send().await?;
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let data = manager.get(&cache_key).await?;
+    assert!(data.is_none());
+
+    // Populate the cache from elsewhere and make sure it is served
+    let writer = ClientBuilder::new(Client::new())
+        .with(CacheBuilder::new().manager(manager.clone()).build())
+        .build();
+    writer.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
 
+    // Non GET/HEAD requests should not remove the entry
+    let _ = client.post(url).send().await;
+    let data = manager.get(&cache_key).await?;
     assert!(data.is_some());
     Ok(())
 }
 
 #[tokio::test]
-async fn delete_after_non_get_head_method_request() -> Result<()> {
+async fn immutable_response_not_revalidated() -> Result<()> {
     let mock_server = MockServer::start().await;
-    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m = build_mock("max-age=86400, public, immutable", TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
     let manager = MokaManager::default();
@@ -187,15 +225,2516 @@ async fn delete_after_non_get_head_method_request() -> Result<()> {
     // Cold pass to load cache
     client.get(url.clone()).send().await?;
 
-    // Try to load cached object
-    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
-    assert!(data.is_some());
+    // A no-cache request should still be served from the cache
+    let res = client
+        .get(url.clone())
+        .header("cache-control", "no-cache")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
 
-    // Post request to make sure the cache object at the same resource was deleted
-    client.post(url.clone()).send().await?;
+    // As should a request made in no cache mode
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::NoCache,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
 
-    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
-    assert!(data.is_none());
+#[tokio::test]
+async fn no_cache_fields() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "cache-control",
+                    "max-age=86400, public, no-cache=\"set-cookie\"",
+                )
+                .insert_header("set-cookie", "session=1")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get("set-cookie").unwrap(), "session=1");
+
+    // The fresh response is served without revalidating it, but without
+    // the header listed by the no-cache directive
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("set-cookie").is_none());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_stale_mode() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=0, public", TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with the refresh stale mode
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::RefreshStale,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // The stale response is served without revalidating it
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("warning").is_some());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // The mark is kept in the policy, the stored response is left as is
+    let (stored, _) =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    assert_eq!(
+        stored.headers.get("cache-control").unwrap(),
+        "max-age=0, public"
+    );
+
+    // The next request revalidates the marked entry
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // Which can be served stale again
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // A no-cache request is always revalidated
+    let res =
+        client.get(url).header("cache-control", "no-cache").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_stale_mode_not_modified() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let _first = mock_server
+        .register_as_scoped(
+            Mock::given(method(GET))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("cache-control", "max-age=0, public")
+                        .insert_header("etag", "\"v1\"")
+                        .set_body_bytes(TEST_BODY),
+                )
+                .up_to_n_times(1)
+                .expect(1),
+        )
+        .await;
+    let _revalidated = mock_server
+        .register_as_scoped(
+            Mock::given(method(GET))
+                .and(header("if-none-match", "\"v1\""))
+                .respond_with(
+                    ResponseTemplate::new(304).insert_header("etag", "\"v1\""),
+                )
+                .expect(1),
+        )
+        .await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client with the refresh stale mode
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::RefreshStale,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // Served stale, which marks the entry
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("warning").is_some());
+
+    // The marked entry is revalidated with a 304 that has no Cache-Control
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert_eq!(
+        res.headers().get("cache-control").unwrap(),
+        "max-age=0, public"
+    );
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // The mark doesn't survive the revalidation, so it's served stale again
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("warning").is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_status_extension() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let stale = Mock::given(method(GET))
+        .and(path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=0, public")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    // The first matching mock responds, so the more specific one goes first
+    mock_server.register(stale).await;
+    mock_server.register(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1)).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client serving stale responses
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .mode(CacheMode::RefreshStale)
+                .build(),
+        )
+        .build();
+
+    // Fresh responses, from the origin or the cache, aren't stale
+    for hit in [HitOrMiss::MISS, HitOrMiss::HIT] {
+        let res = client.get(url.clone()).send().await?;
+        let status = res.extensions().get::<CacheStatus>().unwrap();
+        assert_eq!(*status, CacheStatus { hit, stale: None });
+        assert!(res.headers().get(XCACHESTALE).is_none());
+    }
+
+    // Cold pass to load cache
+    client.get(format!("{url}stale")).send().await?;
+
+    // The stale response says how stale it is
+    let res = client.get(format!("{url}stale")).send().await?;
+    let status = res.extensions().get::<CacheStatus>().unwrap();
+    assert_eq!(status.hit, HitOrMiss::HIT);
+    assert!(status.stale.is_some());
+    assert!(res.headers().get(XCACHESTALE).is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_request_body() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    for (query, data) in [("{ a }", "a"), ("{ b }", "b")] {
+        let m = Mock::given(method("POST"))
+            .and(body_string(query))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_string(data),
+            )
+            .expect(1);
+        mock_server.register(m).await;
+    }
+    let url = format!("{}/graphql", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with the request body in the cache key
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(manager.clone())
+                .cache_request_body(true)
+                .build(),
+        )
+        .build();
+
+    // Each query gets its own entry
+    for (query, data) in [("{ a }", "a"), ("{ b }", "b")] {
+        let res = client.post(url.clone()).body(query).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.text().await?, data);
+        let res = client.post(url.clone()).body(query).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.text().await?, data);
+    }
+    manager.cache.run_pending_tasks().await;
+    assert_eq!(manager.cache.entry_count(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=0, public", TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(CacheBuilder::new().manager(manager.clone()).build())
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Construct reqwest client that never goes to the network
+    let client = ClientBuilder::new(Client::new())
+        .with(CacheBuilder::new().manager(manager).offline(true).build())
+        .build();
+
+    // The stale response is served as is
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // Anything else fails with an offline error
+    for req in [client.get(format!("{url}missing")), client.post(url)] {
+        match req.send().await {
+            Err(reqwest_middleware::Error::Middleware(e)) => {
+                let e = e.downcast_ref::<BoxError>().unwrap();
+                assert!(e.is::<Offline>());
+            }
+            _ => panic!("expected an offline error"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-replay")]
+#[tokio::test]
+async fn replay_fixture() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("no-store", TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let path = std::env::temp_dir().join("http-cache-reqwest-replay-test.json");
+    let _ = std::fs::remove_file(&path);
+
+    // The first run records, even responses that aren't cacheable
+    let manager = ReplayManager::new(&path)?;
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .mode(manager.mode.cache_mode())
+                .manager(manager)
+                .build(),
+        )
+        .build();
+    client.get(url.clone()).send().await?;
+
+    // Later runs replay without reaching the server
+    let manager = ReplayManager::new(&path)?;
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .mode(manager.mode.cache_mode())
+                .manager(manager)
+                .build(),
+        )
+        .build();
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // Requests that weren't recorded fail
+    match client.get(format!("{url}missing")).send().await {
+        Err(reqwest_middleware::Error::Middleware(e)) => {
+            let e = e.downcast_ref::<BoxError>().unwrap();
+            assert!(e.is::<NotRecorded>());
+        }
+        _ => panic!("expected a not recorded error"),
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn clear_site_data() -> Result<()> {
+    let auth_server = MockServer::start().await;
+    let other_server = MockServer::start().await;
+    let logout = Mock::given(method("POST"))
+        .and(path("/logout"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("clear-site-data", "\"cache\", \"cookies\""),
+        )
+        .expect(1);
+    auth_server.register(logout).await;
+    auth_server.register(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1)).await;
+    other_server
+        .register(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1))
+        .await;
+    let auth_url = Url::parse(&format!("{}/profile", &auth_server.uri()))?;
+    let other_url = Url::parse(&format!("{}/", &other_server.uri()))?;
+    let cache = Arc::new(
+        CacheBuilder::new()
+            .manager(MokaManager::default())
+            .clear_site_data(true)
+            .build(),
+    );
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache for both origins
+    client.get(auth_url.clone()).send().await?;
+    client.get(other_url.clone()).send().await?;
+    assert!(cache.contains(GET, &auth_url).await?);
+
+    // Logging out purges the entries of that origin only
+    client.post(format!("{}/logout", &auth_server.uri())).send().await?;
+    assert!(!cache.contains(GET, &auth_url).await?);
+    assert!(cache.contains(GET, &other_url).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalidate_unsafe() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let other_server = MockServer::start().await;
+    let failed = Mock::given(method("PUT"))
+        .and(path("/failed"))
+        .respond_with(
+            ResponseTemplate::new(500).insert_header("content-location", "/b"),
+        )
+        .expect(1);
+    let updated = Mock::given(method("PUT"))
+        .and(path("/a"))
+        .respond_with(
+            ResponseTemplate::new(204)
+                .append_header("content-location", "/b")
+                .append_header("location", other_server.uri().as_str()),
+        )
+        .expect(1);
+    mock_server.register(failed).await;
+    mock_server.register(updated).await;
+    mock_server.register(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 5)).await;
+    other_server
+        .register(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1))
+        .await;
+    let a = Url::parse(&format!("{}/a", &mock_server.uri()))?;
+    let b = Url::parse(&format!("{}/b", &mock_server.uri()))?;
+    let failed = Url::parse(&format!("{}/failed", &mock_server.uri()))?;
+    let other = Url::parse(&format!("{}/", &other_server.uri()))?;
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let recorded = evicted.clone();
+    let cache = Arc::new(
+        CacheBuilder::new()
+            .manager(MokaManager::default())
+            .on_event(Arc::new(move |event| {
+                if let CacheEvent::Evicted(cache_key) = event {
+                    recorded.lock().unwrap().push(cache_key);
+                }
+            }))
+            .build(),
+    );
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache
+    for url in [&a, &b, &failed, &other] {
+        client.get(url.clone()).send().await?;
+    }
+
+    // A failed request doesn't invalidate its url or content location
+    client.put(failed.clone()).send().await?;
+    assert!(cache.contains(GET, &failed).await?);
+    assert!(cache.contains(GET, &b).await?);
+    assert!(evicted.lock().unwrap().is_empty());
+
+    // A successful one invalidates the url and its locations of the same
+    // origin, each once
+    client.put(a.clone()).send().await?;
+    assert!(!cache.contains(GET, &a).await?);
+    assert!(!cache.contains(GET, &b).await?);
+    assert!(cache.contains(GET, &other).await?);
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![format!("{}:{}", GET, &a), format!("{}:{}", GET, &b)]
+    );
+
+    // The invalidated urls are fetched again
+    for url in [&a, &b] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_store_removes_stored_response() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+
+    // Construct reqwest clients with the default and the no store modes
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+    let no_store = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::NoStore,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
 
+    // A request that isn't cached removes the stored response, which would
+    // be older than the one it fetched
+    no_store.get(url.clone()).send().await?;
+    assert!(manager.get(&format!("{}:{}", GET, &url)).await?.is_none());
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
     Ok(())
 }
+
+#[tokio::test]
+async fn transform_responses() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, b"see http://internal/a", 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let rewritten = "see https://example.com/a";
+
+    // Construct reqwest client rewriting urls before storing
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .on_store(Arc::new(|res: &mut HttpResponse| {
+                    let body = String::from_utf8_lossy(&res.body)
+                        .replace("http://internal", "https://example.com");
+                    res.body = body.into_bytes();
+                }))
+                .on_load(Arc::new(|res: &mut HttpResponse| {
+                    res.headers.insert("x-loaded".into(), "1".into());
+                }))
+                .build(),
+        )
+        .build();
+
+    // Both the stored and the cached response are transformed
+    for x_cache in ["MISS", "HIT"] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), x_cache);
+        assert_eq!(res.headers().get("x-loaded").unwrap(), "1");
+        assert_eq!(
+            res.headers().get("content-length").unwrap(),
+            rewritten.len().to_string().as_str()
+        );
+        assert_eq!(res.text().await?, rewritten);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn proxy_revalidate() -> Result<()> {
+    let cache_control = "max-age=0, public, proxy-revalidate";
+    for (shared, fetches) in [(true, 2), (false, 1)] {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(cache_control, TEST_BODY, 200, fetches);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client serving stale responses
+        let client = ClientBuilder::new(Client::new())
+            .with(
+                CacheBuilder::new()
+                    .manager(MokaManager::default())
+                    .mode(CacheMode::RefreshStale)
+                    .shared(shared)
+                    .build(),
+            )
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // Only a shared cache has to revalidate the stale response
+        let res = client.get(url).send().await?;
+        let x_cache = if shared { "MISS" } else { "HIT" };
+        assert_eq!(res.headers().get(XCACHE).unwrap(), x_cache);
+    }
+    Ok(())
+}
+
+// A manager whose backend is unavailable
+#[derive(Debug, Clone)]
+struct UnavailableManager;
+
+#[async_trait::async_trait]
+impl CacheManager for UnavailableManager {
+    async fn get(
+        &self,
+        _cache_key: &str,
+    ) -> Result<Option<(HttpResponse, http_cache_semantics::CachePolicy)>> {
+        Err(Box::new(std::io::Error::from(std::io::ErrorKind::NotConnected)))
+    }
+
+    async fn put(
+        &self,
+        _cache_key: String,
+        _response: HttpResponse,
+        _policy: http_cache_semantics::CachePolicy,
+    ) -> Result<HttpResponse> {
+        Err(Box::new(std::io::Error::from(std::io::ErrorKind::NotConnected)))
+    }
+
+    async fn delete(&self, _cache_key: &str) -> Result<()> {
+        Err(Box::new(std::io::Error::from(std::io::ErrorKind::NotConnected)))
+    }
+}
+
+#[tokio::test]
+async fn fail_open() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{GET}:{url}");
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+
+    // By default the errors of the manager fail the request
+    let client = ClientBuilder::new(Client::new())
+        .with(CacheBuilder::new().manager(UnavailableManager).build())
+        .build();
+    assert!(client.get(url.clone()).send().await.is_err());
+
+    // Construct reqwest client that fails open
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(UnavailableManager)
+                .fail_open(true)
+                .on_event(Arc::new(move |event| {
+                    recorded.lock().unwrap().push(event)
+                }))
+                .build(),
+        )
+        .build();
+
+    // Every request goes to the origin
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+
+    let error = std::io::Error::from(std::io::ErrorKind::NotConnected);
+    let failed = CacheEvent::Failed(cache_key.clone(), error.to_string());
+    let miss = CacheEvent::Miss(cache_key);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            failed.clone(),
+            miss.clone(),
+            failed.clone(),
+            failed.clone(),
+            miss,
+            failed
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalidate_and_clear() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 5);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let other_url = url.join("/other/a")?;
+    let cache = Arc::new(Cache::with_manager(MokaManager::default()));
+    let cache_key = format!("{}:{}", GET, &url);
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+    assert!(cache.manager().get(&cache_key).await?.is_some());
+
+    // Invalidate the entry through the middleware
+    cache.invalidate(GET, &url).await?;
+    assert!(cache.manager().get(&cache_key).await?.is_none());
+
+    // Clear the entries under a path prefix only
+    client.get(url.clone()).send().await?;
+    client.get(other_url.clone()).send().await?;
+    let prefix = format!("{}:{}", GET, url.join("/other/")?);
+    cache.clear_matching(|cache_key| cache_key.starts_with(&prefix)).await?;
+    assert!(!cache.contains(GET, &other_url).await?);
+    assert!(cache.contains(GET, &url).await?);
+    client.get(other_url.clone()).send().await?;
+    cache.clear_prefix(&prefix).await?;
+    assert!(!cache.contains(GET, &other_url).await?);
+    assert!(cache.contains(GET, &url).await?);
+
+    // Reload the entry and clear the entire cache
+    cache.invalidate(GET, &url).await?;
+    client.get(url).send().await?;
+    assert!(cache.manager().get(&cache_key).await?.is_some());
+    cache.clear().await?;
+    assert!(cache.manager().get(&cache_key).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn multi_value_headers() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .append_header("set-cookie", "a=1")
+                .append_header("set-cookie", "b=2")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Both cookies should be stored and replayed
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert_eq!(data.unwrap().0.headers.get_all("set-cookie").count(), 2);
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    let cookies: Vec<_> = res.headers().get_all("set-cookie").iter().collect();
+    assert_eq!(cookies, ["a=1", "b=2"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn authorized_request_not_stored_when_shared() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=86400", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // The response doesn't explicitly allow storing an authorized request
+    client.get(url.clone()).header("authorization", "token").send().await?;
+    assert!(manager.get(&cache_key).await?.is_none());
+
+    // A private cache stores it
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new().manager(manager.clone()).shared(false).build(),
+        )
+        .build();
+    client.get(url).header("authorization", "token").send().await?;
+    assert!(manager.get(&cache_key).await?.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn authorized_request_stored_when_public() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // The public directive allows a shared cache to store it
+    client.get(url.clone()).header("authorization", "token").send().await?;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_partition() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client partitioned by the X-Partition header
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(manager.clone())
+                .cache_partition(Arc::new(|req: &http::request::Parts| {
+                    req.headers
+                        .get("x-partition")
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string)
+                }))
+                .build(),
+        )
+        .build();
+
+    // Cold pass to load partition a
+    client.get(url.clone()).header("x-partition", "a").send().await?;
+    let res = client.get(url.clone()).header("x-partition", "a").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // Partition b never sees the entry of partition a
+    let res = client.get(url.clone()).header("x-partition", "b").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn normalize_url() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let manager = MokaManager::default();
+
+    // Construct reqwest client normalizing urls
+    let client = ClientBuilder::new(Client::new())
+        .with(CacheBuilder::new().manager(manager).normalize_url(true).build())
+        .build();
+
+    // Cold pass to load cache
+    client.get(format!("{}/?a=1&b=2", &mock_server.uri())).send().await?;
+
+    // The reordered query hits the same entry
+    let res =
+        client.get(format!("{}/?b=2&a=1", &mock_server.uri())).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn modify_upstream_request() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .and(header("x-trace-id", "42"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let m_post = Mock::given(method("POST"))
+        .and(header("x-trace-id", "42"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1);
+    let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client adding a header to upstream requests
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(manager.clone())
+                .modify_upstream_request(Arc::new(
+                    |parts: &mut http::request::Parts| {
+                        parts.headers.insert(
+                            "x-trace-id",
+                            http::HeaderValue::from_static("42"),
+                        );
+                    },
+                ))
+                .build(),
+        )
+        .build();
+
+    // The header reaches the origin without changing the cache key
+    client.get(url.clone()).send().await?;
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // Requests passed through without the cache get it as well
+    let res = client.post(url).send().await?;
+    assert_eq!(res.status(), 201);
+    Ok(())
+}
+
+#[tokio::test]
+async fn contains_and_is_fresh() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let stale_url = Url::parse(&format!("{}/stale", &mock_server.uri()))?;
+    let m_stale = Mock::given(method(GET))
+        .and(path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "public, no-cache")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard_stale = mock_server.register_as_scoped(m_stale).await;
+    // Registered after the more specific mock so it only matches the rest
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = Arc::new(Cache::with_manager(MokaManager::default()));
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Nothing is cached yet
+    assert!(!cache.contains(GET, &url).await?);
+    assert_eq!(cache.is_fresh(GET, &url).await?, None);
+
+    // Cold passes to load cache
+    client.get(url.clone()).send().await?;
+    client.get(stale_url.clone()).send().await?;
+    assert!(cache.contains(GET, &url).await?);
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+    assert!(cache.contains(GET, &stale_url).await?);
+    assert_eq!(cache.is_fresh(GET, &stale_url).await?, Some(false));
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_cache_key() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults and custom cache key
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                cache_key: Some(Arc::new(|req: &http::request::Parts| {
+                    format!("{}:{}:{:?}:test", req.method, req.uri, req.version)
+                })),
+                cache_options: None,
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Remote request and should cache
+    client.get(url.clone()).send().await?;
+
+    // Try to load cached object
+    let data = manager
+        .get(&format!("{}:{}:{:?}:test", GET, &url, http::Version::HTTP_11))
+        .await?;
+
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_after_non_get_head_method_request() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let m_post = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1);
+    let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Try to load cached object
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Post request to make sure the cache object at the same resource was deleted
+    client.post(url.clone()).send().await?;
+
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_builder() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with the cache builder
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .mode(CacheMode::Default)
+                .manager(manager.clone())
+                .build(),
+        )
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Try to load cached object
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = client.get(url).send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_with_manager() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Try to load cached object
+    let data = manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn range_request_served_from_cache() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Hot pass with a range should be served from the complete cached body
+    let res =
+        client.get(url.clone()).header("range", "bytes=1-2").send().await?;
+    assert_eq!(res.status(), 206);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get("content-range").unwrap(), "bytes 1-2/4");
+    assert_eq!(res.bytes().await?, &TEST_BODY[1..=2]);
+
+    // The complete response is still served for a request without a range
+    let res = client.get(url).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn range_request_passthrough() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let partial = Mock::given(method(GET))
+        .and(header_exists("range"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("content-range", "bytes 0-0/4")
+                .set_body_bytes(&TEST_BODY[0..1]),
+        )
+        .with_priority(1)
+        .expect(2);
+    let _partial_guard = mock_server.register_as_scoped(partial).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Multiple ranges can't be served from cache and are passed through
+    let res =
+        client.get(url.clone()).header("range", "bytes=0-0,2-3").send().await?;
+    assert_eq!(res.status(), 206);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+
+    // Unsatisfiable ranges are left for the origin to answer
+    let res =
+        client.get(url.clone()).header("range", "bytes=10-").send().await?;
+    assert_eq!(res.status(), 206);
+
+    // The partial responses did not replace the complete cached response
+    let res = client.get(url).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn range_request_not_stored() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let partial = Mock::given(method(GET))
+        .and(header_exists("range"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("content-range", "bytes 0-0/4")
+                .set_body_bytes(&TEST_BODY[0..1]),
+        )
+        .with_priority(1)
+        .expect(2);
+    let _partial_guard = mock_server.register_as_scoped(partial).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // A partial response is passed through without being stored, even when
+    // nothing is cached for the url, so the same range goes to the origin
+    for _ in 0..2 {
+        let res =
+            client.get(url.clone()).header("range", "bytes=0-0").send().await?;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    assert!(manager
+        .get(&format!("{}:{}", GET, &Url::parse(&url)?))
+        .await?
+        .is_none());
+
+    // A request for the complete body gets it from the origin
+    let res = client.get(url).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_events() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let post = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1);
+    let _post_guard = mock_server.register_as_scoped(post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+
+    // Construct reqwest client with an event hook
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .on_event(Arc::new(move |event| {
+                    recorded.lock().unwrap().push(event)
+                }))
+                .build(),
+        )
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // Hot pass should be served from cache
+    client.get(url.clone()).send().await?;
+
+    // Post request removes the cached object
+    client.post(url).send().await?;
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            CacheEvent::Miss(cache_key.clone()),
+            CacheEvent::Stored(cache_key.clone()),
+            CacheEvent::Hit(cache_key.clone()),
+            CacheEvent::Evicted(cache_key),
+        ]
+    );
+    Ok(())
+}
+
+// A manager whose lookups always miss, as if the response was stored by
+// another request after the lookup
+#[derive(Debug, Clone, Default)]
+struct RacingManager(MokaManager);
+
+#[async_trait::async_trait]
+impl CacheManager for RacingManager {
+    async fn get(
+        &self,
+        _cache_key: &str,
+    ) -> Result<Option<(HttpResponse, http_cache_semantics::CachePolicy)>> {
+        Ok(None)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: http_cache_semantics::CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.0.put(cache_key, response, policy).await
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: http_cache_semantics::CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        self.0.put_if_absent(cache_key, response, policy).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.0.delete(cache_key).await
+    }
+}
+
+#[tokio::test]
+async fn miss_does_not_overwrite_concurrent_store() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1).up_to_n_times(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let m = build_mock(CACHEABLE_PUBLIC, b"updated", 200, 1);
+    let _updated_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = RacingManager::default();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+
+    // Construct reqwest client with an event hook
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(manager.clone())
+                .on_event(Arc::new(move |event| {
+                    recorded.lock().unwrap().push(event)
+                }))
+                .build(),
+        )
+        .build();
+
+    // The first miss stores the response
+    client.get(url.clone()).send().await?;
+
+    // The second one is served, but leaves the stored response in place
+    let res = client.get(url).send().await?;
+    assert_eq!(res.bytes().await?, &b"updated"[..]);
+    let (stored, _) = manager.0.get(&cache_key).await?.unwrap();
+    assert_eq!(stored.body, TEST_BODY);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            CacheEvent::Miss(cache_key.clone()),
+            CacheEvent::Stored(cache_key.clone()),
+            CacheEvent::Miss(cache_key),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn permanent_redirects() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, status, expect) in
+        [("/moved", 301, 1), ("/relocated", 308, 1), ("/found", 302, 2)]
+    {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("location", "/target"),
+            )
+            .expect(expect);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client that doesn't follow redirects
+    let client = ClientBuilder::new(
+        Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?,
+    )
+    .with(Cache::with_manager(MokaManager::default()))
+    .build();
+
+    // The permanent redirects are stored and replayed
+    for (route, status) in [("/moved", 301), ("/relocated", 308)] {
+        for expected in ["MISS", "HIT"] {
+            let res = client.get(format!("{uri}{route}")).send().await?;
+            assert_eq!(res.status(), status);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+            assert_eq!(res.headers().get("location").unwrap(), "/target");
+        }
+    }
+
+    // Temporary redirects are fetched every time
+    for _ in 0..2 {
+        let res = client.get(format!("{uri}/found")).send().await?;
+        assert_eq!(res.status(), 302);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn followed_redirects() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, status) in [("/moved", 301), ("/relocated", 308)] {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("location", "/target"),
+            )
+            .expect(1);
+        mock_server.register(m).await;
+    }
+    let m = Mock::given(method(GET))
+        .and(path("/target"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    mock_server.register(m).await;
+
+    // Construct reqwest client that follows redirects, below the middleware
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+
+    // The response at the end of each chain is stored for its first url,
+    // later requests skip the whole chain
+    for route in ["/moved", "/relocated"] {
+        for expected in ["MISS", "HIT"] {
+            let res = client.get(format!("{uri}{route}")).send().await?;
+            assert_eq!(res.status(), 200);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn storable_statuses() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for status in [203, 300, 410] {
+        let m = Mock::given(method(GET))
+            .and(path(format!("/{status}")))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(1);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+
+    // Each response is stored, then replayed with its status and body
+    for status in [203, 300, 410] {
+        for expected in ["MISS", "HIT"] {
+            let res = client.get(format!("{uri}/{status}")).send().await?;
+            assert_eq!(res.status(), status);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn hop_by_hop_headers() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("connection", "keep-alive, x-trace")
+                .insert_header("keep-alive", "timeout=5")
+                .insert_header("proxy-authenticate", "Basic")
+                .insert_header("trailer", "expires")
+                .insert_header("x-trace", "abc")
+                .insert_header("x-kept", "1")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    mock_server.register(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+
+    // The response is served without them from the cache, end-to-end
+    // headers included
+    client.get(&url).send().await?;
+    let res = client.get(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    for name in [
+        "connection",
+        "keep-alive",
+        "proxy-authenticate",
+        "trailer",
+        "transfer-encoding",
+        "x-trace",
+    ] {
+        assert!(res.headers().get(name).is_none(), "{name}");
+    }
+    assert_eq!(res.headers().get("x-kept").unwrap(), "1");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn vary_selects_stored_response() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let json = Mock::given(method(GET))
+        .and(header("accept", "application/json"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("vary", "Accept")
+                .set_body_bytes(b"json"),
+        )
+        .expect(2);
+    let _json_guard = mock_server.register_as_scoped(json).await;
+    let html = Mock::given(method(GET))
+        .and(header("accept", "text/html"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("vary", "Accept")
+                .set_body_bytes(b"html"),
+        )
+        .expect(1);
+    let _html_guard = mock_server.register_as_scoped(html).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+    let get = |accept: &'static str| {
+        client.get(url.clone()).header("accept", accept).send()
+    };
+
+    // Cold pass to load cache
+    get("application/json").await?;
+
+    // The same request is served from cache
+    let res = get("application/json").await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, &b"json"[..]);
+
+    // A request the stored response doesn't vary for is a miss
+    let res = get("text/html").await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "MISS");
+    assert_eq!(res.bytes().await?, &b"html"[..]);
+
+    // And replaces the stored response
+    let res = get("text/html").await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, &b"html"[..]);
+    let res = get("application/json").await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    assert_eq!(res.bytes().await?, &b"json"[..]);
+
+    // The request headers it varies on are kept in the policy, the stored
+    // response only has the ones the origin sent
+    let (stored, _) =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    let mut names =
+        stored.headers.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "cache-control",
+            "content-length",
+            "content-type",
+            "date",
+            "vary",
+            "x-cache",
+            "x-cache-lookup"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_while_revalidate() -> Result<()> {
+    use std::time::Duration;
+
+    let mock_server = MockServer::start().await;
+    let m =
+        build_mock("max-age=0, stale-while-revalidate=60", TEST_BODY, 200, 1)
+            .up_to_n_times(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let m = Mock::given(method(GET))
+        .and(header("cache-control", "no-cache"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(b"updated")
+                .set_delay(Duration::from_millis(100)),
+        )
+        .expect(1);
+    let _updated_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that revalidates in the background
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(manager.clone()).revalidate_in_background(
+            Client::new(),
+            Arc::new(|revalidation| {
+                tokio::spawn(revalidation);
+            }),
+        ))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // The stale response is served while it's revalidated, concurrent
+    // requests don't start another revalidation
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert!(res.headers().get(XCACHESTALE).is_some());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+
+    // The revalidated response is stored once it arrives
+    for _ in 0..50 {
+        let (cached, _) = manager.get(&cache_key).await?.unwrap();
+        if cached.body == b"updated" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get(XCACHESTALE).is_none());
+    assert_eq!(res.bytes().await?, &b"updated"[..]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_if_error() -> Result<()> {
+    use std::time::Duration;
+
+    // Each response is stale as soon as it's stored
+    let cases = [
+        ("max-age=0, stale-if-error=60", None, 200),
+        ("max-age=0, stale-if-error=0", None, 500),
+        ("max-age=0", Some(Duration::from_secs(60)), 200),
+        ("max-age=0", Some(Duration::ZERO), 500),
+        ("max-age=0, stale-if-error=60", Some(Duration::ZERO), 200),
+    ];
+    for (cache_control, window, status) in cases {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(cache_control, TEST_BODY, 200, 1).up_to_n_times(1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let m = build_mock(cache_control, b"error", 500, 1);
+        let _error_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let mut builder = CacheBuilder::new().manager(MokaManager::default());
+        if let Some(window) = window {
+            builder = builder.stale_if_error(window);
+        }
+
+        // Construct reqwest client with the window
+        let client =
+            ClientBuilder::new(Client::new()).with(builder.build()).build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // The failed revalidation is answered with the stale response only
+        // within the window
+        let res = client.get(url).send().await?;
+        assert_eq!(res.status().as_u16(), status, "{cache_control}");
+        if status == 200 {
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        } else {
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_error_when_revalidation_required() -> Result<()> {
+    // Each response is stale as soon as it's stored
+    let cases = [
+        ("max-age=0, must-revalidate", true, 500),
+        ("max-age=0, proxy-revalidate", true, 500),
+        ("max-age=0, proxy-revalidate", false, 200),
+        ("max-age=0, no-cache=\"set-cookie, proxy-revalidate, x\"", true, 200),
+        ("max-age=0", true, 200),
+    ];
+    for (cache_control, shared, status) in cases {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(cache_control, TEST_BODY, 200, 1).up_to_n_times(1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let m = build_mock(cache_control, b"error", 500, 1);
+        let _error_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client with a shared or a private cache
+        let client = ClientBuilder::new(Client::new())
+            .with(
+                CacheBuilder::new()
+                    .manager(MokaManager::default())
+                    .shared(shared)
+                    .build(),
+            )
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // The failed revalidation is only answered with the stale response
+        // when it doesn't have to be revalidated
+        let res = client.get(url).send().await?;
+        assert_eq!(res.status().as_u16(), status, "{cache_control}");
+        if status == 200 {
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+            assert!(res.headers().get("warning").is_some());
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        } else {
+            assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+            assert!(res.headers().get("warning").is_none());
+            assert_eq!(res.bytes().await?, &b"error"[..]);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn request_cache_control() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=60")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let m = Mock::given(method(GET))
+        .and(path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=0")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _stale_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let stale_url = format!("{}/stale", &mock_server.uri());
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+    let get = |url: &str, cache_control: &'static str| {
+        client.get(url).header("cache-control", cache_control).send()
+    };
+
+    // A request that only accepts a stored response can't be answered yet
+    let res = get(&url, "only-if-cached").await?;
+    assert_eq!(res.status().as_u16(), 504);
+
+    // Cold passes to load cache
+    client.get(url.clone()).send().await?;
+    client.get(stale_url.clone()).send().await?;
+
+    // The stored response is served to requests it's fresh enough for
+    for cache_control in ["only-if-cached", "max-age=120", "min-fresh=30"] {
+        let res = get(&url, cache_control).await?;
+        assert_eq!(
+            res.headers().get(XCACHE).unwrap(),
+            "HIT",
+            "{cache_control}"
+        );
+    }
+    let res = get(&stale_url, "max-stale=60").await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // And fetched again for the others
+    for cache_control in ["max-age=0", "min-fresh=120"] {
+        let res = get(&url, cache_control).await?;
+        assert_eq!(
+            res.headers().get(XCACHE).unwrap(),
+            "MISS",
+            "{cache_control}"
+        );
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn age_header() -> Result<()> {
+    for (cache_control, mode) in [
+        (CACHEABLE_PUBLIC, CacheMode::Default),
+        ("max-age=0", CacheMode::ForceCache),
+    ] {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(cache_control, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client with the mode
+        let client = ClientBuilder::new(Client::new())
+            .with(
+                CacheBuilder::new()
+                    .mode(mode)
+                    .manager(MokaManager::default())
+                    .build(),
+            )
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // Responses served from cache, stale or not, carry their age
+        let res = client.get(url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        let age = res.headers().get("age").expect("age header");
+        assert_eq!(age, "0", "{mode:?}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_status_header() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let fresh = Mock::given(method(GET))
+        .and(path("/fresh"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let stale = Mock::given(method(GET))
+        .and(path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=0, must-revalidate")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let post =
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(201));
+    let _fresh_guard = mock_server.register_as_scoped(fresh).await;
+    let _stale_guard = mock_server.register_as_scoped(stale).await;
+    let _post_guard = mock_server.register_as_scoped(post).await;
+    let uri = mock_server.uri();
+
+    // Construct reqwest client with the Cache-Status header enabled
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .cache_status_header(true)
+                .build(),
+        )
+        .build();
+    let cache_status = |res: &reqwest::Response| {
+        res.headers().get("cache-status").unwrap().to_str().unwrap().to_string()
+    };
+
+    let res = client.get(format!("{uri}/fresh")).send().await?;
+    assert_eq!(cache_status(&res), "http-cache; fwd=miss; fwd-status=200");
+    let res = client.get(format!("{uri}/fresh")).send().await?;
+    assert_eq!(cache_status(&res), "http-cache; hit");
+
+    client.get(format!("{uri}/stale")).send().await?;
+    let res = client.get(format!("{uri}/stale")).send().await?;
+    assert_eq!(cache_status(&res), "http-cache; fwd=stale; fwd-status=200");
+
+    let res = client.post(format!("{uri}/fresh")).send().await?;
+    assert_eq!(cache_status(&res), "http-cache; fwd=miss; fwd-status=201");
+    Ok(())
+}
+
+#[tokio::test]
+async fn targeted_cache_control() -> Result<()> {
+    for (targeted, hit) in [
+        (vec![], "MISS"),
+        (vec!["CDN-Cache-Control".to_string()], "HIT"),
+        (vec!["Surrogate-Control".to_string()], "MISS"),
+    ] {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", "no-store")
+                    .insert_header("cdn-cache-control", "max-age=60")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(if hit == "HIT" { 1 } else { 2 });
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client with the targeted headers
+        let client = ClientBuilder::new(Client::new())
+            .with(
+                CacheBuilder::new()
+                    .manager(MokaManager::default())
+                    .targeted_cache_control(targeted.clone())
+                    .build(),
+            )
+            .build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // Only the first present targeted header overrides Cache-Control,
+        // which is still passed on as it was
+        let res = client.get(url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), hit, "{targeted:?}");
+        assert_eq!(res.headers().get("cache-control").unwrap(), "no-store");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn heuristic_freshness() -> Result<()> {
+    use std::time::Duration;
+
+    for (heuristic, max, hit) in [
+        (None, None, "HIT"),
+        (Some(0.0), None, "MISS"),
+        (None, Some(Duration::ZERO), "MISS"),
+        (None, Some(Duration::from_secs(60)), "HIT"),
+    ] {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "last-modified",
+                        "Wed, 21 Oct 2015 07:28:00 GMT",
+                    )
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(if hit == "HIT" { 1 } else { 2 });
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        // Construct reqwest client with the heuristic settings
+        let mut builder = CacheBuilder::new().manager(MokaManager::default());
+        if let Some(heuristic) = heuristic {
+            builder = builder.cache_heuristic(heuristic);
+        }
+        if let Some(max) = max {
+            builder = builder.max_heuristic_freshness(max);
+        }
+        let client =
+            ClientBuilder::new(Client::new()).with(builder.build()).build();
+
+        // Cold pass to load cache
+        client.get(url.clone()).send().await?;
+
+        // Responses are only fresh for as long as the heuristic allows
+        let res = client.get(url).send().await?;
+        assert_eq!(
+            res.headers().get(XCACHE).unwrap(),
+            hit,
+            "{heuristic:?} {max:?}"
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn max_body_size() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+
+    // Construct reqwest client with a limit below the size of the body
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .max_body_size(TEST_BODY.len() as u64 - 1)
+                .on_event(Arc::new(move |event| {
+                    recorded.lock().unwrap().push(event)
+                }))
+                .build(),
+        )
+        .build();
+
+    // Neither pass stores the response
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(
+        events.lock().unwrap()[..2],
+        [CacheEvent::Miss(cache_key.clone()), CacheEvent::Oversized(cache_key),]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn coalesce_requests() -> Result<()> {
+    use std::time::Duration;
+
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY)
+                .set_delay(Duration::from_millis(100)),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client that coalesces misses
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .coalesce_requests(true)
+                .build(),
+        )
+        .build();
+
+    // Concurrent cold requests share a single fetch
+    let requests = (0..10)
+        .map(|_| tokio::spawn(client.get(url.clone()).send()))
+        .collect::<Vec<_>>();
+    let mut hits = 0;
+    for request in requests {
+        let res = request.await??;
+        if res.headers().get(XCACHE).unwrap() == "HIT" {
+            hits += 1;
+        }
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(hits, 9);
+    Ok(())
+}
+
+#[tokio::test]
+async fn soft_purge() -> Result<()> {
+    use std::time::Duration;
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .up_to_n_times(1)
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let not_modified = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304).insert_header("etag", "\"v1\""),
+        )
+        .expect(1);
+    let _not_modified_guard =
+        mock_server.register_as_scoped(not_modified).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache_key = format!("{}:{}", GET, &url);
+    let manager = MokaManager::default();
+    let purged = Arc::new(Mutex::new(Vec::new()));
+    let recorded = purged.clone();
+    let cache = Arc::new(
+        CacheBuilder::new()
+            .manager(manager.clone())
+            .on_event(Arc::new(move |event| {
+                if let CacheEvent::Purged(cache_key) = event {
+                    recorded.lock().unwrap().push(cache_key);
+                }
+            }))
+            .build(),
+    );
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+
+    // A read only cache leaves the response as it is
+    let read_only =
+        CacheBuilder::new().manager(manager.clone()).read_only(true).build();
+    read_only.soft_purge(GET, &url).await?;
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+
+    // The purged response is kept but stale
+    cache.soft_purge(GET, &url).await?;
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(false));
+    assert_eq!(
+        cache.lookup(GET, &url).await?.unwrap().time_to_live,
+        Duration::ZERO
+    );
+    assert_eq!(*purged.lock().unwrap(), vec![cache_key]);
+
+    // So it's revalidated with a conditional request
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+    Ok(())
+}
+
+#[tokio::test]
+async fn soft_purge_vary() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("vary", "Accept")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache =
+        Arc::new(CacheBuilder::new().manager(MokaManager::default()).build());
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).header("accept", "text/html").send().await?;
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+
+    // The request it varies on isn't known, so the response is removed
+    cache.soft_purge(GET, &url).await?;
+    assert_eq!(cache.is_fresh(GET, &url).await?, None);
+    let res =
+        client.get(url.clone()).header("accept", "text/html").send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "MISS");
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_rules() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, cache_control, expect) in [
+        ("/static/a.css", "no-cache", 1),
+        ("/auth/token", CACHEABLE_PUBLIC, 2),
+        ("/other", CACHEABLE_PUBLIC, 1),
+    ] {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", cache_control)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client with rules for some of the urls
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .cache_rules(vec![
+                    CacheRule::url_prefix(format!("{uri}/static/"))
+                        .mode(CacheMode::ForceCache),
+                    CacheRule::url_prefix(format!("{uri}/auth/"))
+                        .mode(CacheMode::NoStore),
+                ])
+                .build(),
+        )
+        .build();
+
+    for (route, hit) in
+        [("/static/a.css", "HIT"), ("/auth/token", "MISS"), ("/other", "HIT")]
+    {
+        // Cold pass to load cache
+        client.get(format!("{uri}{route}")).send().await?;
+
+        let res = client.get(format!("{uri}{route}")).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), hit, "{route}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn cacheable_predicate() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, body, expect) in
+        [("/ok", r#"{"data":1}"#, 1), ("/error", r#"{"error":1}"#, 2)]
+    {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_string(body),
+            )
+            .expect(expect);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client that doesn't store error bodies
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .cacheable(Arc::new(|res: &HttpResponse| {
+                    !String::from_utf8_lossy(&res.body).contains("\"error\"")
+                }))
+                .build(),
+        )
+        .build();
+
+    for (route, hit) in [("/ok", "HIT"), ("/error", "MISS")] {
+        // Cold pass to load cache
+        client.get(format!("{uri}{route}")).send().await?;
+
+        let res = client.get(format!("{uri}{route}")).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), hit, "{route}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn negative_caching() -> Result<()> {
+    use std::time::Duration;
+
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, status, expect) in [("/missing", 404, 1), ("/failing", 500, 2)]
+    {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", "no-store"),
+            )
+            .expect(expect);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client that caches not found and server errors
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .negative_caching(NegativeCaching::new(
+                    [404, 500],
+                    Duration::from_secs(60),
+                ))
+                .build(),
+        )
+        .build();
+
+    // The not found response is stored despite no-store
+    let res = client.get(format!("{uri}/missing")).send().await?;
+    assert_eq!(res.status(), 404);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    let res = client.get(format!("{uri}/missing")).send().await?;
+    assert_eq!(res.status(), 404);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+
+    // Server errors can't be served from the cache
+    for _ in 0..2 {
+        let res = client.get(format!("{uri}/failing")).send().await?;
+        assert_eq!(res.status(), 500);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn prime() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for route in ["/a", "/b", "/c"] {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(1);
+        mock_server.register(m).await;
+    }
+    let urls = ["/a", "/b", "/c"]
+        .iter()
+        .map(|route| Url::parse(&format!("{uri}{route}")))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let cache = Arc::new(Cache::with_manager(MokaManager::default()));
+
+    // Warm the cache before any request goes through the middleware
+    cache.prime(&Client::new(), urls.clone(), 2).await?;
+    for url in &urls {
+        assert_eq!(cache.is_fresh(GET, url).await?, Some(true));
+    }
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Every request is answered from the cache
+    for url in urls {
+        let res = client.get(url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn lookup() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "max-age=60, public")
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = Arc::new(Cache::with_manager(MokaManager::default()));
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Nothing is cached yet
+    assert_eq!(cache.lookup(GET, &url).await?, None);
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+    let entry = cache.lookup(GET, &url).await?.unwrap();
+    assert_eq!(entry.status, 200);
+    assert_eq!(entry.body_size, TEST_BODY.len() as u64);
+    assert!(entry.time_to_live.as_secs() > 50);
+    assert!(entry.time_to_live.as_secs() <= 60);
+    assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(entry.last_modified, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn head_from_get() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // Construct reqwest client with cache defaults
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+
+    // The HEAD request is answered by the stored GET response
+    let res = client.head(url).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    assert!(res.bytes().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuse_set_cookie() -> Result<()> {
+    let mock_server = MockServer::start().await;
+    let uri = mock_server.uri();
+    for (route, cache_control, expect) in [
+        ("/session", "max-age=86400", 4),
+        ("/public", CACHEABLE_PUBLIC, 1),
+        ("/stripped", "max-age=86400, no-cache=\"set-cookie\"", 2),
+    ] {
+        let m = Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", cache_control)
+                    .insert_header("set-cookie", "session=1")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect);
+        mock_server.register(m).await;
+    }
+
+    // Construct reqwest client with cache defaults, a shared cache
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache::with_manager(MokaManager::default()))
+        .build();
+
+    // The response is stored by default, but stale
+    client.get(format!("{uri}/session")).send().await?;
+    let res = client.get(format!("{uri}/session")).send().await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+
+    // Construct reqwest client that refuses cookies
+    let client = ClientBuilder::new(Client::new())
+        .with(
+            CacheBuilder::new()
+                .manager(MokaManager::default())
+                .refuse_set_cookie(true)
+                .build(),
+        )
+        .build();
+
+    // Only responses whose Cache-Control allows it are stored, though the
+    // one with a no-cache directive is revalidated
+    for (route, expected) in
+        [("/session", "MISS"), ("/public", "HIT"), ("/stripped", "HIT")]
+    {
+        client.get(format!("{uri}{route}")).send().await?;
+        let res = client.get(format!("{uri}{route}")).send().await?;
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), expected);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn clock() -> Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    // A clock that only moves when told to
+    struct ManualClock(Mutex<SystemTime>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    let mock_server = MockServer::start().await;
+    let m = build_mock("max-age=60, public", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let clock = Arc::new(ManualClock(Mutex::new(SystemTime::now())));
+    let advance = |secs| *clock.0.lock().unwrap() += Duration::from_secs(secs);
+    let cache = Arc::new(
+        CacheBuilder::new()
+            .manager(MokaManager::default())
+            .clock(clock.clone())
+            .build(),
+    );
+    let time_to_live = || async {
+        Ok::<_, BoxError>(cache.lookup(GET, &url).await?.unwrap().time_to_live)
+    };
+
+    // Construct reqwest client sharing the cache handle
+    let client =
+        ClientBuilder::new(Client::new()).with_arc(cache.clone()).build();
+
+    // Cold pass to load cache
+    client.get(url.clone()).send().await?;
+    assert_eq!(time_to_live().await?, Duration::from_secs(60));
+
+    // The response ages with the clock
+    advance(30);
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert_eq!(res.headers().get("age").unwrap(), "30");
+    assert_eq!(time_to_live().await?, Duration::from_secs(30));
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(true));
+
+    // Past its freshness lifetime, the response is stale and fetched again
+    advance(31);
+    assert_eq!(time_to_live().await?, Duration::ZERO);
+    assert_eq!(cache.is_fresh(GET, &url).await?, Some(false));
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "MISS");
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), "HIT");
+    Ok(())
+}
+
+#[tokio::test]
+async fn decompressed_response_is_consistent() -> Result<()> {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(TEST_BODY)?;
+    let gzipped = encoder.finish()?;
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("content-encoding", "gzip")
+                .set_body_bytes(gzipped),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that decompresses responses
+    let client = ClientBuilder::new(Client::builder().gzip(true).build()?)
+        .with(Cache::with_manager(manager.clone()))
+        .build();
+
+    // Cold pass to load cache
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // The decoded body is stored without the encoding header
+    let (cached, _) =
+        manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?.unwrap();
+    assert_eq!(cached.body, TEST_BODY);
+    assert!(!cached.headers.contains_key("content-encoding"));
+
+    // Hot pass returns a consistent header and body
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), "HIT");
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[test]
+fn strip_decoded_encoding() {
+    let mut headers = HttpHeaders::new();
+    headers.insert("content-encoding".to_string(), "gzip".to_string());
+    headers.insert("content-length".to_string(), "24".to_string());
+    // Length matches the encoded body we hold, headers are kept
+    crate::strip_decoded_encoding(&mut headers, 24);
+    assert_eq!(headers.len(), 2);
+    // Length doesn't match, the body was decoded along the way
+    crate::strip_decoded_encoding(&mut headers, 4);
+    assert!(headers.is_empty());
+}
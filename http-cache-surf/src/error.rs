@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 /// Generic error type for the `HttpCache` Surf implementation.
+#[allow(dead_code)]
 #[derive(Error, Debug)]
 pub enum Error {
     /// There was a Surf client error
@@ -1,4 +1,4 @@
-use crate::{error, Cache};
+use crate::{error, Cache, CacheBuilder};
 
 use http_cache::*;
 use http_types::Method;
@@ -102,6 +102,7 @@ mod with_moka {
                     shared: false,
                     ..Default::default()
                 }),
+                ..Default::default()
             },
         }));
 
@@ -479,15 +480,122 @@ mod with_moka {
             manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
         assert!(data.is_some());
 
-        // Hot pass to make sure revalidation request was sent
+        // Hot pass to make sure revalidation request was sent, the stored
+        // response must be revalidated so the error is passed through
+        let res = client.send(req).await?;
+        assert_eq!(res.status(), 500);
+        assert!(res.header("warning").is_none());
+        assert_eq!(res.header(XCACHELOOKUP).unwrap(), HIT);
+        assert_eq!(res.header(XCACHE).unwrap(), MISS);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn cache_builder() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+
+        // Construct Surf client with the cache builder
+        let client = Client::new().with(
+            CacheBuilder::new()
+                .mode(CacheMode::Default)
+                .manager(manager.clone())
+                .build(),
+        );
+
+        // Cold pass to load cache
+        client.send(req.clone()).await?;
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Hot pass to make sure the expect response was returned
         let mut res = client.send(req).await?;
         assert_eq!(res.body_bytes().await?, TEST_BODY);
-        assert!(res.header("warning").is_some());
-        assert_eq!(res.header(XCACHELOOKUP).unwrap(), HIT);
         assert_eq!(res.header(XCACHE).unwrap(), HIT);
         Ok(())
     }
 
+    #[async_std::test]
+    async fn stale_while_revalidate() -> Result<()> {
+        use std::{sync::Arc, time::Duration};
+
+        let mock_server = MockServer::start().await;
+        let m = build_mock(
+            "max-age=0, stale-while-revalidate=60",
+            TEST_BODY,
+            200,
+            1,
+        )
+        .up_to_n_times(1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let m = build_mock(CACHEABLE_PUBLIC, b"updated", 200, 1);
+        let _updated_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let cache_key = format!("{}:{}", GET, &Url::parse(&url)?);
+        let manager = MokaManager::default();
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+
+        // Construct Surf client that revalidates in the background
+        let client = Client::new().with(
+            Cache::with_manager(manager.clone()).revalidate_in_background(
+                Client::new(),
+                Arc::new(|revalidation| {
+                    async_std::task::spawn(revalidation);
+                }),
+            ),
+        );
+
+        // Cold pass to load cache
+        client.send(req.clone()).await?;
+
+        // The stale response is served while it's revalidated
+        let mut res = client.send(req.clone()).await?;
+        assert_eq!(res.header(XCACHE).unwrap(), HIT);
+        assert_eq!(res.body_bytes().await?, TEST_BODY);
+
+        // The revalidated response is stored once it arrives
+        for _ in 0..50 {
+            let (cached, _) = manager.get(&cache_key).await?.unwrap();
+            if cached.body == b"updated" {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(20)).await;
+        }
+        let mut res = client.send(req).await?;
+        assert_eq!(res.header(XCACHE).unwrap(), HIT);
+        assert_eq!(res.body_bytes().await?, b"updated");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn cache_with_manager() -> Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+
+        // Construct Surf client with cache defaults
+        let client = Client::new().with(Cache::with_manager(manager.clone()));
+
+        // Cold pass to load cache
+        client.send(req).await?;
+
+        // Try to load cached object
+        let data =
+            manager.get(&format!("{}:{}", GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+        Ok(())
+    }
+
     #[cfg(test)]
     mod only_if_cached_mode {
         use super::*;
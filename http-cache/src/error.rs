@@ -29,3 +29,65 @@ impl fmt::Display for BadHeader {
 }
 
 impl std::error::Error for BadHeader {}
+
+/// Error type for operations a cache manager does not support
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Unsupported;
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Operation not supported by the cache manager")
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Error type for cache entries that can't be decrypted, because they were
+/// stored with another key or were tampered with
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DecryptionFailed;
+
+impl fmt::Display for DecryptionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Error decrypting cache entry")
+    }
+}
+
+impl std::error::Error for DecryptionFailed {}
+
+/// Error type for a cache store whose schema was written by a newer version of
+/// the cache manager, so its records can't be read safely
+#[derive(Debug, Default, Copy, Clone)]
+pub struct UnsupportedSchema;
+
+impl fmt::Display for UnsupportedSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Cache store schema is newer than this version supports")
+    }
+}
+
+impl std::error::Error for UnsupportedSchema {}
+
+/// Error type for requests missing from the fixture of a replaying cache manager
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NotRecorded;
+
+impl fmt::Display for NotRecorded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Request not found in the replay fixture")
+    }
+}
+
+impl std::error::Error for NotRecorded {}
+
+/// Error type for requests that can't be served from the cache while offline
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Offline;
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Offline and no cached response is available")
+    }
+}
+
+impl std::error::Error for Offline {}
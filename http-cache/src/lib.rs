@@ -20,38 +20,147 @@
 //! The following features are available. By default `manager-cacache` and `cacache-async-std` are enabled.
 //!
 //! - `manager-cacache` (default): enable [cacache](https://github.com/zkat/cacache-rs),
-//! a high-performance disk cache, backend manager.
+//!   a high-performance disk cache, backend manager.
 //! - `cacache-async-std` (default): enable [async-std](https://github.com/async-rs/async-std) runtime support for cacache.
 //! - `cacache-tokio` (disabled): enable [tokio](https://github.com/tokio-rs/tokio) runtime support for cacache.
 //! - `manager-moka` (disabled): enable [moka](https://github.com/moka-rs/moka),
-//! a high-performance in-memory cache, backend manager.
+//!   a high-performance in-memory cache, backend manager.
+//! - `manager-redis` (disabled): enable [redis](https://github.com/redis-rs/redis-rs),
+//!   a shared cache on a Redis server, backend manager. Requires the tokio runtime.
+//! - `manager-dynamodb` (disabled): enable [dynamodb](https://github.com/awslabs/aws-sdk-rust),
+//!   a shared cache in a DynamoDB table, backend manager. Requires the tokio runtime.
+//! - `manager-compressed` (disabled): enable `CompressedManager`, which compresses
+//!   the bodies stored by another backend manager with gzip.
+//! - `manager-encrypted` (disabled): enable `EncryptedManager`, which encrypts
+//!   the entries of another backend manager with XChaCha20-Poly1305.
+//! - `manager-filesystem` (disabled): enable a cache of plain files laid out by URL,
+//!   readable without any tools, backend manager.
+//! - `manager-foyer` (disabled): enable [foyer](https://github.com/foyer-rs/foyer),
+//!   a hybrid cache spilling over from memory to disk, backend manager. Requires the tokio runtime.
+//! - `manager-indexeddb` (disabled): enable [indexeddb](https://github.com/devashishdxt/idb),
+//!   a persistent cache in the browser, backend manager. Only available when compiling to `wasm32`.
+//! - `manager-instrumented` (disabled): enable `InstrumentedManager`, which records
+//!   metrics about the operations of another backend manager through the [metrics](https://github.com/metrics-rs/metrics) facade.
+//! - `manager-lmdb` (disabled): enable [lmdb](https://github.com/meilisearch/heed),
+//!   a memory mapped embedded database suited to read-heavy workloads, backend manager.
+//! - `manager-memcached` (disabled): enable [memcached](https://github.com/cobbinma/async-memcached),
+//!   a shared cache on a memcached server, backend manager. Requires the tokio runtime.
+//! - `manager-mongodb` (disabled): enable [mongodb](https://github.com/mongodb/mongo-rust-driver),
+//!   a shared cache in a MongoDB collection, backend manager. Requires the tokio runtime.
+//! - `manager-object-store` (disabled): enable [object_store](https://github.com/apache/arrow-rs/tree/master/object_store),
+//!   a cache in S3 compatible, Google Cloud or Azure object storage, backend manager. Requires the tokio runtime.
+//! - `manager-postgres` (disabled): enable [postgres](https://github.com/launchbadge/sqlx),
+//!   a shared cache in a PostgreSQL table, backend manager. Requires the tokio runtime.
+//! - `manager-replay` (disabled): enable `ReplayManager`, which records responses
+//!   to a fixture file and replays them, for deterministic tests.
+//! - `manager-rocksdb` (disabled): enable [rocksdb](https://github.com/rust-rocksdb/rust-rocksdb),
+//!   an embedded database built for high write throughput, backend manager. Requires a C++ toolchain and clang.
+//! - `manager-sled` (disabled): enable [sled](https://github.com/spacejam/sled),
+//!   an embedded database keeping the cache in a single directory, backend manager.
 //! - `with-http-types` (disabled): enable [http-types](https://github.com/http-rs/http-types)
-//! type conversion support
+//!   type conversion support
+//!
+//! [`InMemoryManager`], a backend manager keeping the cache in a `HashMap`,
+//! is always available without any features, and so are [`NoopManager`],
+//! which stores nothing, and [`QuotaManager`], which bounds the size of
+//! another backend manager.
 mod error;
 mod managers;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     fmt::{self, Debug},
+    pin::Pin,
     str::FromStr,
-    sync::Arc,
-    time::SystemTime,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use futures_util::{
+    future::BoxFuture,
+    lock::{Mutex as AsyncMutex, OwnedMutexGuard},
+    stream, Stream, StreamExt, TryStreamExt,
 };
 
-use http::{header::CACHE_CONTROL, request, response, StatusCode};
+use http::{
+    header::{
+        AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_LOCATION,
+        CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, LOCATION,
+        PRAGMA, RANGE,
+    },
+    request, response, Method, StatusCode,
+};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::Url;
 
-pub use error::{BadHeader, BadVersion, BoxError, Result};
+pub use error::{
+    BadHeader, BadVersion, BoxError, DecryptionFailed, NotRecorded, Offline,
+    Result, Unsupported, UnsupportedSchema,
+};
+
+pub use managers::{
+    memory::InMemoryManager, noop::NoopManager, quota::QuotaManager,
+    tiered::TieredManager,
+};
 
 #[cfg(feature = "manager-cacache")]
 pub use managers::cacache::CACacheManager;
 
+#[cfg(feature = "manager-compressed")]
+pub use managers::compressed::CompressedManager;
+
+#[cfg(feature = "manager-dynamodb")]
+pub use managers::dynamodb::DynamoDbManager;
+
+#[cfg(feature = "manager-encrypted")]
+pub use managers::encrypted::EncryptedManager;
+
+#[cfg(feature = "manager-filesystem")]
+pub use managers::filesystem::FileSystemManager;
+
+#[cfg(feature = "manager-foyer")]
+pub use managers::foyer::FoyerManager;
+
+#[cfg(all(feature = "manager-indexeddb", target_arch = "wasm32"))]
+pub use managers::indexeddb::IdbManager;
+
+#[cfg(feature = "manager-instrumented")]
+pub use managers::instrumented::InstrumentedManager;
+
+#[cfg(feature = "manager-lmdb")]
+pub use managers::lmdb::LmdbManager;
+
+#[cfg(feature = "manager-memcached")]
+pub use managers::memcached::MemcachedManager;
+
 #[cfg(feature = "manager-moka")]
 pub use managers::moka::MokaManager;
 
+#[cfg(feature = "manager-mongodb")]
+pub use managers::mongodb::MongoManager;
+
+#[cfg(feature = "manager-object-store")]
+pub use managers::object_store::ObjectStoreManager;
+
+#[cfg(feature = "manager-postgres")]
+pub use managers::postgres::PostgresManager;
+
+#[cfg(feature = "manager-replay")]
+pub use managers::replay::{ReplayManager, ReplayMode};
+
+#[cfg(feature = "manager-redis")]
+pub use managers::redis::RedisManager;
+
+#[cfg(feature = "manager-rocksdb")]
+pub use managers::rocksdb::RocksDbManager;
+
+#[cfg(feature = "manager-sled")]
+pub use managers::sled::SledManager;
+
 // Exposing the moka cache for convenience, renaming to avoid naming conflicts
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
@@ -62,10 +171,23 @@ pub use moka::future::{Cache as MokaCache, CacheBuilder as MokaCacheBuilder};
 pub const XCACHE: &str = "x-cache";
 /// `x-cache-lookup` header: Value will be HIT if a response existed in cache, MISS if not
 pub const XCACHELOOKUP: &str = "x-cache-lookup";
+/// `x-cache-stale` header: Value will be how many seconds past its freshness
+/// lifetime a response served from cache was, only present if it was stale
+pub const XCACHESTALE: &str = "x-cache-stale";
+/// `cache-status` header: The [RFC 9211](https://www.rfc-editor.org/rfc/rfc9211)
+/// status of the response, only present if enabled in [`HttpCacheOptions`]
+pub const CACHESTATUS: &str = "cache-status";
+
+// The name of the cache in the members of the `cache-status` header
+const CACHE_STATUS_NAME: &str = "http-cache";
+
+// Added to the policy of an entry served stale in the refresh stale mode, so
+// the next request for it is revalidated. The stored response is left as is.
+const REFRESH_DIRECTIVE: &str = "must-revalidate";
 
 /// Represents a basic cache status
 /// Used in the custom headers `x-cache` and `x-cache-lookup`
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HitOrMiss {
     /// Yes, there was a hit
     HIT,
@@ -82,6 +204,17 @@ impl fmt::Display for HitOrMiss {
     }
 }
 
+/// The cache status of a response, as read from the custom headers by
+/// [`HttpResponse::served_status`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStatus {
+    /// Whether the response was served from cache
+    pub hit: HitOrMiss,
+    /// How far past its freshness lifetime the response served from cache
+    /// was, `None` if it was fresh or not served from cache
+    pub stale: Option<Duration>,
+}
+
 /// Represents an HTTP version
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[non_exhaustive]
@@ -115,13 +248,112 @@ impl fmt::Display for HttpVersion {
     }
 }
 
+/// HTTP headers keyed by name, keeping every value of a repeated header
+/// such as `Set-Cookie` in the order it was received.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HttpHeaders(HashMap<String, Vec<String>>);
+
+impl HttpHeaders {
+    /// Creates an empty set of headers
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value of the header if present
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.0.get(name).and_then(|values| values.first())
+    }
+
+    /// Returns every value of the header
+    pub fn get_all(&self, name: &str) -> impl Iterator<Item = &String> {
+        self.0.get(name).into_iter().flatten()
+    }
+
+    /// Sets the header to a single value, returning the values it replaced
+    pub fn insert(
+        &mut self,
+        name: String,
+        value: String,
+    ) -> Option<Vec<String>> {
+        self.0.insert(name, vec![value])
+    }
+
+    /// Adds a value to the header, keeping any existing values
+    pub fn append(&mut self, name: String, value: String) {
+        self.0.entry(name).or_default().push(value);
+    }
+
+    /// Removes the header, returning its values
+    pub fn remove(&mut self, name: &str) -> Option<Vec<String>> {
+        self.0.remove(name)
+    }
+
+    /// Checks if the header is present
+    #[must_use]
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Returns the number of distinct header names
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Checks if there are no headers
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over every name and value pair, repeating the name for each
+    /// value of a repeated header
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0
+            .iter()
+            .flat_map(|(name, values)| values.iter().map(move |v| (name, v)))
+    }
+}
+
+impl From<HashMap<String, String>> for HttpHeaders {
+    fn from(headers: HashMap<String, String>) -> Self {
+        Self(
+            headers
+                .into_iter()
+                .map(|(name, value)| (name, vec![value]))
+                .collect(),
+        )
+    }
+}
+
+impl IntoIterator for HttpHeaders {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0
+            .into_iter()
+            .flat_map(|(name, values)| {
+                values.into_iter().map(move |v| (name.clone(), v))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
 /// A basic generic type that represents an HTTP response
+///
+/// Only the header section is captured, trailer fields are dropped as the
+/// supported clients don't expose them on a buffered body, so a replayed
+/// response never carries trailers.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HttpResponse {
     /// HTTP response body
     pub body: Vec<u8>,
     /// HTTP response headers
-    pub headers: HashMap<String, String>,
+    pub headers: HttpHeaders,
     /// HTTP response status code
     pub status: u16,
     /// HTTP response url
@@ -137,8 +369,8 @@ impl HttpResponse {
             response::Builder::new().status(self.status).body(())?;
         {
             let headers = converted.headers_mut();
-            for header in &self.headers {
-                headers.insert(
+            for header in self.headers.iter() {
+                headers.append(
                     http::header::HeaderName::from_str(header.0.as_str())?,
                     http::HeaderValue::from_str(header.1.as_str())?,
                 );
@@ -157,6 +389,17 @@ impl HttpResponse {
 
     /// Adds a warning header to a response
     pub fn add_warning(&mut self, url: &Url, code: usize, message: &str) {
+        self.add_warning_at(url, code, message, SystemTime::now());
+    }
+
+    /// Adds a warning header to a response, dated at the time
+    pub fn add_warning_at(
+        &mut self,
+        url: &Url,
+        code: usize,
+        message: &str,
+        now: SystemTime,
+    ) {
         // warning    = "warning" ":" 1#warning-value
         // warning-value = warn-code SP warn-agent SP warn-text [SP warn-date]
         // warn-code  = 3DIGIT
@@ -173,7 +416,7 @@ impl HttpResponse {
                 code,
                 url.host().expect("Invalid URL"),
                 message,
-                httpdate::fmt_http_date(SystemTime::now())
+                httpdate::fmt_http_date(now)
             ),
         );
     }
@@ -185,11 +428,21 @@ impl HttpResponse {
 
     /// Update the headers from `http::response::Parts`
     pub fn update_headers(&mut self, parts: &response::Parts) -> Result<()> {
-        for header in parts.headers.iter() {
-            self.headers.insert(
-                header.0.as_str().to_string(),
-                header.1.to_str()?.to_string(),
-            );
+        for name in parts.headers.keys() {
+            let values = parts
+                .headers
+                .get_all(name)
+                .iter()
+                .map(|value| Ok(value.to_str()?.to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            // `CachePolicy` keeps a single value per header name for the
+            // stored response, so don't let that collapse the values we hold.
+            if let [value] = values.as_slice() {
+                if self.headers.get_all(name.as_str()).any(|v| v == value) {
+                    continue;
+                }
+            }
+            self.headers.0.insert(name.as_str().to_string(), values);
         }
         Ok(())
     }
@@ -202,6 +455,32 @@ impl HttpResponse {
         })
     }
 
+    /// Checks if the Cache-Control header contains the immutable directive
+    #[must_use]
+    pub fn is_immutable(&self) -> bool {
+        self.headers.get(CACHE_CONTROL.as_str()).map_or(false, |val| {
+            val.as_str().to_lowercase().contains("immutable")
+        })
+    }
+
+    /// Returns the lowercased header names listed by `no-cache` directives
+    /// of the Cache-Control header, e.g. `no-cache="Set-Cookie"`. These headers
+    /// must not be served from the cache without a successful revalidation.
+    #[must_use]
+    pub fn no_cache_fields(&self) -> Vec<String> {
+        no_cache_fields(
+            self.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str),
+        )
+    }
+
+    /// Removes the headers listed by `no-cache` directives of the
+    /// Cache-Control header from a response served from the cache.
+    pub fn remove_no_cache_fields(&mut self) {
+        for name in self.no_cache_fields() {
+            self.headers.remove(&name);
+        }
+    }
+
     /// Adds the custom `x-cache` header to the response
     pub fn cache_status(&mut self, hit_or_miss: HitOrMiss) {
         self.headers.insert(XCACHE.to_string(), hit_or_miss.to_string());
@@ -211,191 +490,1650 @@ impl HttpResponse {
     pub fn cache_lookup_status(&mut self, hit_or_miss: HitOrMiss) {
         self.headers.insert(XCACHELOOKUP.to_string(), hit_or_miss.to_string());
     }
-}
 
-/// A trait providing methods for storing, reading, and removing cache records.
-#[async_trait::async_trait]
-pub trait CacheManager: Send + Sync + 'static {
-    /// Attempts to pull a cached response and related policy from cache.
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>>;
-    /// Attempts to cache a response and related policy.
-    async fn put(
-        &self,
-        cache_key: String,
-        res: HttpResponse,
-        policy: CachePolicy,
-    ) -> Result<HttpResponse>;
-    /// Attempts to remove a record from cache.
-    async fn delete(&self, cache_key: &str) -> Result<()>;
-}
+    /// Sets the `Age` header to how long the response has been held in caches
+    /// according to the policy
+    pub fn update_age(&mut self, policy: &CachePolicy) {
+        self.update_age_at(policy, SystemTime::now());
+    }
 
-/// Describes the functionality required for interfacing with HTTP client middleware
-#[async_trait::async_trait]
-pub trait Middleware: Send {
-    /// Determines if the request method is either GET or HEAD
-    fn is_method_get_head(&self) -> bool;
-    /// Returns a new cache policy with default options
-    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy>;
-    /// Returns a new cache policy with custom options
-    fn policy_with_options(
-        &self,
-        response: &HttpResponse,
-        options: CacheOptions,
-    ) -> Result<CachePolicy>;
-    /// Attempts to update the request headers with the passed `http::request::Parts`
-    fn update_headers(&mut self, parts: &request::Parts) -> Result<()>;
-    /// Attempts to force the "no-cache" directive on the request
-    fn force_no_cache(&mut self) -> Result<()>;
-    /// Attempts to construct `http::request::Parts` from the request
-    fn parts(&self) -> Result<request::Parts>;
-    /// Attempts to determine the requested url
-    fn url(&self) -> Result<Url>;
-    /// Attempts to determine the request method
-    fn method(&self) -> Result<String>;
-    /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
-    async fn remote_fetch(&mut self) -> Result<HttpResponse>;
-}
+    /// Sets the `Age` header to how long the response has been held in caches
+    /// as of the time
+    pub fn update_age_at(&mut self, policy: &CachePolicy, now: SystemTime) {
+        let age = policy.age(now);
+        self.headers.insert("age".to_string(), age.as_secs().to_string());
+    }
 
-/// Similar to [make-fetch-happen cache options](https://github.com/npm/make-fetch-happen#--optscache).
-/// Passed in when the [`HttpCache`] struct is being built.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum CacheMode {
-    /// Will inspect the HTTP cache on the way to the network.
-    /// If there is a fresh response it will be used.
-    /// If there is a stale response a conditional request will be created,
-    /// and a normal request otherwise.
-    /// It then updates the HTTP cache with the response.
-    /// If the revalidation request fails (for example, on a 500 or if you're offline),
-    /// the stale response will be returned.
-    Default,
-    /// Behaves as if there is no HTTP cache at all.
-    NoStore,
-    /// Behaves as if there is no HTTP cache on the way to the network.
-    /// Ergo, it creates a normal request and updates the HTTP cache with the response.
-    Reload,
-    /// Creates a conditional request if there is a response in the HTTP cache
-    /// and a normal request otherwise. It then updates the HTTP cache with the response.
-    NoCache,
-    /// Uses any response in the HTTP cache matching the request,
-    /// not paying attention to staleness. If there was no response,
-    /// it creates a normal request and updates the HTTP cache with the response.
-    ForceCache,
-    /// Uses any response in the HTTP cache matching the request,
-    /// not paying attention to staleness. If there was no response,
-    /// it returns a network error.
-    OnlyIfCached,
-    /// Overrides the check that determines if a response can be cached to always return true on 200.
-    /// Uses any response in the HTTP cache matching the request,
-    /// not paying attention to staleness. If there was no response,
-    /// it creates a normal request and updates the HTTP cache with the response.
-    IgnoreRules,
-}
+    /// Adds the custom `x-cache-stale` header to the response if the policy
+    /// is stale, with how far past its freshness lifetime it is. A response
+    /// that was already stale when it was received counts from then on.
+    pub fn cache_stale_status(&mut self, policy: &CachePolicy) {
+        self.cache_stale_status_at(policy, SystemTime::now());
+    }
 
-impl TryFrom<http::Version> for HttpVersion {
-    type Error = BoxError;
+    /// Adds the custom `x-cache-stale` header to the response if the policy
+    /// is stale as of the time
+    pub fn cache_stale_status_at(
+        &mut self,
+        policy: &CachePolicy,
+        now: SystemTime,
+    ) {
+        let Some(stale) = staleness(policy, now) else {
+            return;
+        };
+        self.headers
+            .insert(XCACHESTALE.to_string(), stale.as_secs().to_string());
+    }
 
-    fn try_from(value: http::Version) -> Result<Self> {
-        Ok(match value {
-            http::Version::HTTP_09 => Self::Http09,
-            http::Version::HTTP_10 => Self::Http10,
-            http::Version::HTTP_11 => Self::Http11,
-            http::Version::HTTP_2 => Self::H2,
-            http::Version::HTTP_3 => Self::H3,
-            _ => return Err(Box::new(BadVersion)),
-        })
+    /// Returns the cache status of the response read from the custom
+    /// `x-cache` and `x-cache-stale` headers
+    pub fn served_status(&self) -> CacheStatus {
+        let hit = match self.headers.get(XCACHE) {
+            Some(hit) if hit == "HIT" => HitOrMiss::HIT,
+            _ => HitOrMiss::MISS,
+        };
+        let stale = self
+            .headers
+            .get(XCACHESTALE)
+            .and_then(|stale| stale.parse().ok())
+            .map(Duration::from_secs);
+        CacheStatus { hit, stale }
+    }
+
+    /// Attempts to build a `206 Partial Content` response from a complete
+    /// `200` response for the value of a `Range` request header.
+    /// Returns `None` if the range is unsatisfiable, spans multiple ranges,
+    /// or can't be parsed, in which case the request should be passed through.
+    #[must_use]
+    pub fn partial_content(&self, range: &str) -> Option<HttpResponse> {
+        if self.status != 200 {
+            return None;
+        }
+        let len = self.body.len();
+        let (start, end) = parse_byte_range(range, len)?;
+        let mut res = self.clone();
+        res.body = self.body[start..=end].to_vec();
+        res.status = 206;
+        res.headers.insert(
+            CONTENT_RANGE.as_str().to_string(),
+            format!("bytes {start}-{end}/{len}"),
+        );
+        res.headers.insert(
+            CONTENT_LENGTH.as_str().to_string(),
+            res.body.len().to_string(),
+        );
+        Some(res)
     }
 }
 
-impl From<HttpVersion> for http::Version {
-    fn from(value: HttpVersion) -> Self {
-        match value {
-            HttpVersion::Http09 => Self::HTTP_09,
-            HttpVersion::Http10 => Self::HTTP_10,
-            HttpVersion::Http11 => Self::HTTP_11,
-            HttpVersion::H2 => Self::HTTP_2,
-            HttpVersion::H3 => Self::HTTP_3,
+// Splits a header value into its directives, commas within quoted strings
+// don't separate directives.
+fn split_directives(value: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                directives.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
         }
     }
+    directives.push(value[start..].trim());
+    directives.retain(|directive| !directive.is_empty());
+    directives
 }
 
-#[cfg(feature = "http-types")]
-impl TryFrom<http_types::Version> for HttpVersion {
-    type Error = BoxError;
+// Returns the field names of a `no-cache="..."` directive, `None` for any
+// other directive including a bare `no-cache`.
+fn no_cache_directive_fields(directive: &str) -> Option<&str> {
+    let (name, fields) = directive.split_once('=')?;
+    if !name.trim().eq_ignore_ascii_case("no-cache") {
+        return None;
+    }
+    Some(fields.trim().trim_matches('"'))
+}
 
-    fn try_from(value: http_types::Version) -> Result<Self> {
-        Ok(match value {
-            http_types::Version::Http0_9 => Self::Http09,
-            http_types::Version::Http1_0 => Self::Http10,
-            http_types::Version::Http1_1 => Self::Http11,
-            http_types::Version::Http2_0 => Self::H2,
-            http_types::Version::Http3_0 => Self::H3,
-            _ => return Err(Box::new(BadVersion)),
+fn no_cache_fields<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    values
+        .flat_map(split_directives)
+        .filter_map(no_cache_directive_fields)
+        .flat_map(|fields| fields.split(','))
+        .map(|field| field.trim().to_lowercase())
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+// http-cache-semantics treats a `no-cache` directive listing field names like
+// a bare one, which would revalidate the response on every request. Returns
+// the response without these directives to create the policy with, or `None`
+// when there are none. The headers they list are removed whenever the stored
+// response is served without revalidating it.
+fn without_no_cache_fields(res: &HttpResponse) -> Option<HttpResponse> {
+    if res.no_cache_fields().is_empty() {
+        return None;
+    }
+    let mut headers = res.headers.clone();
+    let cache_control = res
+        .headers
+        .get_all(CACHE_CONTROL.as_str())
+        .map(|value| {
+            split_directives(value)
+                .into_iter()
+                .filter(|d| no_cache_directive_fields(d).is_none())
+                .collect::<Vec<_>>()
+                .join(", ")
         })
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+    headers.remove(CACHE_CONTROL.as_str());
+    if !cache_control.is_empty() {
+        headers.0.insert(CACHE_CONTROL.as_str().to_string(), cache_control);
     }
+    Some(HttpResponse {
+        body: Vec::new(),
+        headers,
+        status: res.status,
+        url: res.url.clone(),
+        version: res.version,
+    })
 }
 
-#[cfg(feature = "http-types")]
-impl From<HttpVersion> for http_types::Version {
-    fn from(value: HttpVersion) -> Self {
-        match value {
-            HttpVersion::Http09 => Self::Http0_9,
-            HttpVersion::Http10 => Self::Http1_0,
-            HttpVersion::Http11 => Self::Http1_1,
-            HttpVersion::H2 => Self::Http2_0,
-            HttpVersion::H3 => Self::Http3_0,
-        }
+// Returns a copy of the response, without its body, whose `Cache-Control`
+// header is replaced by the first of the targeted headers it carries, if any.
+fn with_targeted_cache_control(
+    names: &[String],
+    res: &HttpResponse,
+) -> Option<HttpResponse> {
+    let cache_control = names.iter().find_map(|name| {
+        let values = res
+            .headers
+            .get_all(&name.to_ascii_lowercase())
+            .cloned()
+            .collect::<Vec<_>>();
+        (!values.is_empty()).then_some(values)
+    })?;
+    let mut headers = res.headers.clone();
+    headers.0.insert(CACHE_CONTROL.as_str().to_string(), cache_control);
+    Some(HttpResponse {
+        body: Vec::new(),
+        headers,
+        status: res.status,
+        url: res.url.clone(),
+        version: res.version,
+    })
+}
+
+// Checks the request selects the stored response under its `Vary` header,
+// with the headers of the request the policy was created for. A `Vary: *`
+// response never matches another request.
+fn vary_matches(
+    policy: &CachePolicy,
+    parts: &request::Parts,
+    now: SystemTime,
+) -> bool {
+    // A no-cache request is never answered right away, so the policy always
+    // hands back a request for its own url and method
+    let mut probe = http::Request::new(());
+    *probe.method_mut() = parts.method.clone();
+    *probe.uri_mut() = parts.uri.clone();
+    *probe.headers_mut() = parts.headers.clone();
+    probe
+        .headers_mut()
+        .insert(CACHE_CONTROL, http::HeaderValue::from_static("no-cache"));
+    let (mut probe, ()) = probe.into_parts();
+    if let BeforeRequest::Stale { request, .. } =
+        policy.before_request(&probe, now)
+    {
+        probe.method = request.method;
+        probe.uri = request.uri;
     }
+    matches!(
+        policy.before_request(&probe, now),
+        BeforeRequest::Stale { matches: true, .. }
+    )
 }
 
-/// Options struct provided by
-/// [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
-pub use http_cache_semantics::CacheOptions;
+// Returns the first rule matching the `Content-Type` of the response
+fn content_type_rule<'a>(
+    rules: &'a [ContentTypeFreshness],
+    res: &HttpResponse,
+) -> Option<&'a ContentTypeFreshness> {
+    let content_type = res.headers.get(CONTENT_TYPE.as_str())?;
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    rules.iter().find(|rule| rule.matches(media_type))
+}
 
-/// A closure that takes [`http::request::Parts`] and returns a [`String`].
-/// By default, the cache key is a combination of the request method and uri with a colon in between.
-pub type CacheKey = Arc<dyn Fn(&request::Parts) -> String + Send + Sync>;
+// Rewrites the freshness of a storable response to the bounds of the first
+// rule matching its `Content-Type`, or to the bounds of every response when
+// none does. Returns the response to create the policy with, or `None` when
+// it's already within the bounds.
+fn bounded_freshness(
+    options: &HttpCacheOptions,
+    res: &HttpResponse,
+    policy: &CachePolicy,
+) -> Option<HttpResponse> {
+    let (min, max) =
+        match content_type_rule(&options.content_type_freshness, res) {
+            Some(rule) => (rule.min, rule.max),
+            None => (options.min_freshness, options.max_freshness),
+        };
+    if min.is_none() && max.is_none() || !policy.is_storable() {
+        return None;
+    }
+    let now = options.now();
+    let lifetime = policy.time_to_live(now) + policy.age(now);
+    let mut bounded = lifetime;
+    if let Some(min) = min {
+        bounded = bounded.max(min);
+    }
+    if let Some(max) = max {
+        bounded = bounded.min(max);
+    }
+    if bounded == lifetime {
+        return None;
+    }
+    Some(with_max_age(res, bounded))
+}
 
-/// Can be used to override the default [`CacheOptions`] and cache key.
-/// The cache key is a closure that takes [`http::request::Parts`] and returns a [`String`].
-#[derive(Default, Clone)]
-pub struct HttpCacheOptions {
-    /// Override the default cache options.
-    pub cache_options: Option<CacheOptions>,
-    /// Override the default cache key generator.
-    pub cache_key: Option<CacheKey>,
+// Caps the heuristic freshness lifetime of a storable response without
+// explicit freshness. Returns the response to create the policy with, or
+// `None` when it's already within the cap.
+fn capped_heuristic_freshness(
+    max: Option<Duration>,
+    res: &HttpResponse,
+    policy: &CachePolicy,
+    now: SystemTime,
+) -> Option<HttpResponse> {
+    let max = max?;
+    let cache_control =
+        || res.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str);
+    if !policy.is_storable()
+        || res.headers.get("expires").is_some()
+        || has_directive(cache_control(), "max-age")
+        || has_directive(cache_control(), "s-maxage")
+    {
+        return None;
+    }
+    if policy.time_to_live(now) + policy.age(now) <= max {
+        return None;
+    }
+    Some(with_max_age(res, max))
 }
 
-impl Debug for HttpCacheOptions {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HttpCacheOptions")
-            .field("cache_options", &self.cache_options)
-            .field("cache_key", &"Fn(&request::Parts) -> String")
-            .finish()
+// Returns a copy of the response, without its body, whose freshness lifetime
+// is set to the given one with a `max-age` directive.
+fn with_max_age(res: &HttpResponse, lifetime: Duration) -> HttpResponse {
+    let mut directives = res
+        .headers
+        .get_all(CACHE_CONTROL.as_str())
+        .flat_map(|value| split_directives(value))
+        .filter(|directive| {
+            let name = directive.split('=').next().unwrap_or_default().trim();
+            !["max-age", "s-maxage", "no-cache"]
+                .iter()
+                .any(|d| name.eq_ignore_ascii_case(d))
+        })
+        .collect::<Vec<_>>();
+    let max_age = format!("max-age={}", lifetime.as_secs());
+    directives.push(&max_age);
+    let mut headers = res.headers.clone();
+    headers.remove("expires");
+    headers.insert(CACHE_CONTROL.as_str().to_string(), directives.join(", "));
+    HttpResponse {
+        body: Vec::new(),
+        headers,
+        status: res.status,
+        url: res.url.clone(),
+        version: res.version,
     }
 }
 
-impl HttpCacheOptions {
-    fn create_cache_key(
-        &self,
-        parts: &request::Parts,
-        override_method: Option<&str>,
-    ) -> String {
-        if let Some(cache_key) = &self.cache_key {
-            cache_key(parts)
-        } else {
-            format!(
-                "{}:{}",
-                override_method.unwrap_or_else(|| parts.method.as_str()),
-                parts.uri
-            )
+// Applies the transform, if any, keeping the `Content-Length` header in line
+// with the body.
+fn transform(transform: &Option<TransformResponse>, res: &mut HttpResponse) {
+    if let Some(transform) = transform {
+        let len = res.body.len();
+        transform(res);
+        if res.body.len() != len
+            && res.headers.contains_key(CONTENT_LENGTH.as_str())
+        {
+            res.headers.insert(
+                CONTENT_LENGTH.as_str().to_string(),
+                res.body.len().to_string(),
+            );
         }
     }
 }
 
-/// Caches requests according to http spec.
+// Updates the headers of a stored response from its policy. The policy is
+// created without the `no-cache` directives listing field names, and with the
+// targeted cache control header in place of Cache-Control, so keep the
+// Cache-Control values they came from for the next time it's served.
+fn update_cached_headers(
+    res: &mut HttpResponse,
+    parts: &response::Parts,
+    targeted: &[String],
+) -> Result<()> {
+    let cache_control = res
+        .headers
+        .get_all(CACHE_CONTROL.as_str())
+        .cloned()
+        .collect::<Vec<_>>();
+    let is_targeted = targeted
+        .iter()
+        .any(|name| res.headers.get(&name.to_ascii_lowercase()).is_some());
+    res.update_headers(parts)?;
+    if is_targeted
+        || !no_cache_fields(cache_control.iter().map(String::as_str)).is_empty()
+    {
+        res.headers.0.insert(CACHE_CONTROL.as_str().to_string(), cache_control);
+    }
+    Ok(())
+}
+
+// Parses a single `bytes` range (https://www.rfc-editor.org/rfc/rfc9110#section-14.1.2)
+// into inclusive start and end offsets within a body of the given length.
+fn parse_byte_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let spec = range.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // suffix-range, the last N bytes of the body
+        let suffix: usize = last.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        return Some((len.saturating_sub(suffix), len - 1));
+    }
+    let start: usize = first.parse().ok()?;
+    let end = if last.is_empty() {
+        len - 1
+    } else {
+        let end: usize = last.parse().ok()?;
+        if end < start {
+            return None;
+        }
+        end.min(len - 1)
+    };
+    if start >= len {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// A response body read in chunks, see [`CacheManager::get_stream`]
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
+
+// How far past its freshness lifetime the policy is, or None while it's fresh
+pub(crate) fn staleness(
+    policy: &CachePolicy,
+    now: SystemTime,
+) -> Option<Duration> {
+    if !policy.is_stale(now) {
+        return None;
+    }
+    // Before the response was received only its `Age` header counts
+    // towards its age, so the lifetime is the time to live then plus that
+    let then = UNIX_EPOCH;
+    let lifetime = policy.time_to_live(then) + policy.age(then);
+    Some(policy.age(now).saturating_sub(lifetime))
+}
+
+// Whether the policy has been stale for at least the grace period, so the
+// response isn't worth keeping around to be revalidated anymore
+pub(crate) fn expired(
+    policy: &CachePolicy,
+    now: SystemTime,
+    grace: Duration,
+) -> bool {
+    staleness(policy, now).map_or(false, |stale| stale >= grace)
+}
+
+/// What was removed by [`CacheManager::prune_expired`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Number of records removed
+    pub entries: usize,
+    /// Bytes reclaimed by removing them, as counted by the manager. Usually
+    /// the size of the response bodies, or of the records as stored.
+    pub bytes: u64,
+}
+
+/// How much a [`CacheManager`] is storing, as returned by
+/// [`CacheManager::stats`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of records stored
+    pub entries: usize,
+    /// Bytes they take up, as counted by the manager. Usually the size of the
+    /// response bodies, or of the records as stored.
+    pub bytes: u64,
+}
+
+/// Describes a record stored by a [`CacheManager`], as listed by
+/// [`CacheManager::entries`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// The cache key the record is stored under
+    pub cache_key: String,
+    /// HTTP response url
+    pub url: Url,
+    /// HTTP response status code
+    pub status: u16,
+    /// When the response was received, or last revalidated
+    pub stored_at: SystemTime,
+    /// Size of the response body in bytes
+    pub body_size: u64,
+    /// How long the response stays fresh for, zero once it's stale
+    pub time_to_live: Duration,
+    /// The `ETag` header of the response, used to revalidate it
+    pub etag: Option<String>,
+    /// The `Last-Modified` header of the response, used to revalidate it
+    pub last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    /// Describes the record of a response and its policy. The body of the
+    /// response is only used for its size.
+    pub fn new(
+        cache_key: String,
+        response: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> Self {
+        Self::new_at(cache_key, response, policy, SystemTime::now())
+    }
+
+    /// Describes the record of a response and its policy as of the time
+    pub fn new_at(
+        cache_key: String,
+        response: &HttpResponse,
+        policy: &CachePolicy,
+        now: SystemTime,
+    ) -> Self {
+        Self::describe(
+            cache_key,
+            response.url.clone(),
+            response.status,
+            &response.headers,
+            policy,
+            response.body.len() as u64,
+            now,
+        )
+    }
+
+    // For managers that know the size of the body without reading it
+    pub(crate) fn describe(
+        cache_key: String,
+        url: Url,
+        status: u16,
+        headers: &HttpHeaders,
+        policy: &CachePolicy,
+        body_size: u64,
+        now: SystemTime,
+    ) -> Self {
+        // The age of a policy is the age of the response when it was
+        // received plus the time since, which leaves when it was received
+        let resident = policy.age(now).saturating_sub(policy.age(UNIX_EPOCH));
+        Self {
+            cache_key,
+            url,
+            status,
+            stored_at: now.checked_sub(resident).unwrap_or(now),
+            body_size,
+            time_to_live: policy.time_to_live(now),
+            etag: headers.get(ETAG.as_str()).cloned(),
+            last_modified: headers.get(LAST_MODIFIED.as_str()).cloned(),
+        }
+    }
+}
+
+/// A trait providing methods for storing, reading, and removing cache records.
+#[async_trait::async_trait]
+pub trait CacheManager: Send + Sync + 'static {
+    /// Attempts to pull a cached response and related policy from cache.
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>>;
+    /// Attempts to cache a response and related policy.
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse>;
+    /// Attempts to pull the metadata of a cached response, its status,
+    /// headers, url, and version, along with the related policy from cache.
+    /// The body of the returned response is empty, managers that can read
+    /// the metadata without the body should override this.
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self.get(cache_key).await?.map(|(mut res, policy)| {
+            res.body = Vec::new();
+            (res, policy)
+        }))
+    }
+    /// Attempts to pull a cached response and related policy from cache, with
+    /// the body as a stream so a large one doesn't have to be held in memory.
+    /// The body of the returned response is empty. The default implementation
+    /// calls `get` and streams the body in one chunk, managers that can read
+    /// it in parts should override this.
+    async fn get_stream(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, BodyStream)>> {
+        Ok(self.get(cache_key).await?.map(|(mut res, policy)| {
+            let body = Bytes::from(std::mem::take(&mut res.body));
+            let body: BodyStream = Box::pin(stream::once(async { Ok(body) }));
+            (res, policy, body)
+        }))
+    }
+    /// Attempts to cache a response and related policy, with the body read
+    /// from a stream so a large one doesn't have to be held in memory. The
+    /// body of the response is ignored. The default implementation collects
+    /// the stream and calls `put`, managers that can write the body as it's
+    /// read should override this.
+    async fn put_stream(
+        &self,
+        cache_key: String,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+        mut body: BodyStream,
+    ) -> Result<()> {
+        res.body.clear();
+        while let Some(chunk) = body.next().await {
+            res.body.extend_from_slice(&chunk?);
+        }
+        self.put(cache_key, res, policy).await?;
+        Ok(())
+    }
+    /// Attempts to cache a response and related policy only if no record is
+    /// cached under the key, returning the response as `put` does along with
+    /// whether it was stored. Concurrent misses of the same key then store it
+    /// once rather than overwriting each other. The default implementation
+    /// checks `contains` before calling `put`, which isn't atomic, managers
+    /// that can insert a record only if it's missing should override this.
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        if self.contains(&cache_key).await? {
+            return Ok((res, false));
+        }
+        Ok((self.put(cache_key, res, policy).await?, true))
+    }
+    /// Attempts to pull the cached responses and related policies of several
+    /// keys at once, returned in the order of the keys. The default
+    /// implementation calls `get` for each key, managers that can look them
+    /// up in a single round trip should override this.
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let mut stores = Vec::with_capacity(cache_keys.len());
+        for cache_key in cache_keys {
+            stores.push(self.get(cache_key).await?);
+        }
+        Ok(stores)
+    }
+    /// Attempts to cache several responses and related policies at once.
+    /// When a key is given more than once, the last response is kept. The
+    /// default implementation calls `put` for each response, managers that
+    /// can store them in a single round trip should override this.
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        for (cache_key, res, policy) in entries {
+            self.put(cache_key, res, policy).await?;
+        }
+        Ok(())
+    }
+    /// Checks if a record exists in cache. The default implementation calls
+    /// `get_meta`, managers with a cheaper lookup should override this.
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.get_meta(cache_key).await?.is_some())
+    }
+    /// Attempts to remove a record from cache.
+    async fn delete(&self, cache_key: &str) -> Result<()>;
+    /// Attempts to remove every record from cache, managers that can't do so
+    /// return an [`Unsupported`] error.
+    async fn clear(&self) -> Result<()> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to remove every record whose cache key matches the predicate,
+    /// managers that can't list their keys return an [`Unsupported`] error.
+    async fn delete_matching(
+        &self,
+        _predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to remove every record whose cache key starts with the
+    /// prefix. The default implementation calls `delete_matching`, managers
+    /// that can look up their keys by prefix should override this.
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.delete_matching(&|cache_key: &str| cache_key.starts_with(prefix))
+            .await
+    }
+    /// Attempts to list the cache keys of every record, managers that can't
+    /// list their keys return an [`Unsupported`] error.
+    async fn keys(&self) -> Result<Vec<String>> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to describe every record in cache. The default implementation
+    /// reads each record listed by `keys`, managers that can describe them
+    /// without reading the bodies should override this.
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        for cache_key in self.keys().await? {
+            // Records removed since they were listed are skipped
+            if let Some((res, policy)) = self.get(&cache_key).await? {
+                entries.push(CacheEntry::new(cache_key, &res, &policy));
+            }
+        }
+        Ok(entries)
+    }
+    /// Attempts to remove every record that has been stale for at least the
+    /// grace period, reporting how much was removed. A grace period keeps
+    /// stale records around for a while, as they can still be revalidated.
+    /// The default implementation reads each record listed by `keys`.
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let now = SystemTime::now();
+        let mut report = PruneReport::default();
+        for cache_key in self.keys().await? {
+            let Some((res, policy)) = self.get(&cache_key).await? else {
+                continue;
+            };
+            if expired(&policy, now, grace) {
+                self.delete(&cache_key).await?;
+                report.entries += 1;
+                report.bytes += res.body.len() as u64;
+            }
+        }
+        Ok(report)
+    }
+    /// Attempts to count the records in cache and the bytes they take up.
+    /// The default implementation sums up the body sizes of the records
+    /// described by `entries`, managers that keep track of their size or can
+    /// compute it cheaply should override this.
+    async fn stats(&self) -> Result<CacheStats> {
+        let entries = self.entries().await?;
+        Ok(CacheStats {
+            entries: entries.len(),
+            bytes: entries.iter().map(|entry| entry.body_size).sum(),
+        })
+    }
+    /// Waits for every write made so far to be persisted, or applied for an
+    /// in-memory manager. Managers that write through don't have to do
+    /// anything, which is the default.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Returns the [`CacheOptions`] policies stored by this manager should be
+    /// created with, taking precedence over [`HttpCacheOptions::cache_options`].
+    /// None by default.
+    fn cache_options(&self) -> Option<CacheOptions> {
+        None
+    }
+}
+
+/// Forwards to the boxed manager, so one can be chosen at runtime as a
+/// `Box<dyn CacheManager>`.
+#[async_trait::async_trait]
+impl<T: CacheManager + ?Sized> CacheManager for Box<T> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        (**self).get(cache_key).await
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        (**self).put(cache_key, res, policy).await
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        (**self).get_meta(cache_key).await
+    }
+
+    async fn get_stream(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, BodyStream)>> {
+        (**self).get_stream(cache_key).await
+    }
+
+    async fn put_stream(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        body: BodyStream,
+    ) -> Result<()> {
+        (**self).put_stream(cache_key, res, policy, body).await
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        (**self).put_if_absent(cache_key, res, policy).await
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        (**self).get_many(cache_keys).await
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        (**self).put_many(entries).await
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        (**self).contains(cache_key).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        (**self).delete(cache_key).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        (**self).clear().await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        (**self).delete_matching(predicate).await
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        (**self).delete_prefix(prefix).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        (**self).keys().await
+    }
+
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        (**self).entries().await
+    }
+
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        (**self).prune_expired(grace).await
+    }
+
+    async fn stats(&self) -> Result<CacheStats> {
+        (**self).stats().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        (**self).flush().await
+    }
+
+    fn cache_options(&self) -> Option<CacheOptions> {
+        (**self).cache_options()
+    }
+}
+
+/// Describes the functionality required for interfacing with HTTP client middleware
+#[async_trait::async_trait]
+pub trait Middleware: Send {
+    /// Determines if the request method is either GET or HEAD
+    fn is_method_get_head(&self) -> bool;
+    /// Returns a new cache policy with default options
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy>;
+    /// Returns a new cache policy with custom options
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy>;
+    /// Returns a new cache policy with custom options, for a response
+    /// received at the time, as told by the [`Clock`] of the cache.
+    /// Defaults to [`Middleware::policy_with_options`], which doesn't know
+    /// about the clock.
+    fn policy_with_options_at(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+        _now: SystemTime,
+    ) -> Result<CachePolicy> {
+        self.policy_with_options(response, options)
+    }
+    /// Attempts to update the request headers with the passed `http::request::Parts`
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()>;
+    /// Attempts to force the "no-cache" directive on the request
+    fn force_no_cache(&mut self) -> Result<()>;
+    /// Attempts to construct `http::request::Parts` from the request
+    fn parts(&self) -> Result<request::Parts>;
+    /// Attempts to determine the requested url
+    fn url(&self) -> Result<Url>;
+    /// Attempts to determine the request method
+    fn method(&self) -> Result<String>;
+    /// Returns the request body, or `None` if it isn't available (for example
+    /// a streaming body). Only used when the body is part of the cache key.
+    fn body(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
+    async fn remote_fetch(&mut self) -> Result<HttpResponse>;
+}
+
+/// Similar to [make-fetch-happen cache options](https://github.com/npm/make-fetch-happen#--optscache).
+/// Passed in when the [`HttpCache`] struct is being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Will inspect the HTTP cache on the way to the network.
+    /// If there is a fresh response it will be used.
+    /// If there is a stale response a conditional request will be created,
+    /// and a normal request otherwise.
+    /// It then updates the HTTP cache with the response.
+    /// If the revalidation request fails (for example, on a 500 or if you're offline),
+    /// the stale response will be returned, within its `stale-if-error` window
+    /// when it has one, see [`HttpCacheOptions::stale_if_error`].
+    Default,
+    /// Behaves as if there is no HTTP cache at all.
+    NoStore,
+    /// Behaves as if there is no HTTP cache on the way to the network.
+    /// Ergo, it creates a normal request and updates the HTTP cache with the response.
+    /// Useful to repopulate a cache without ever serving from it.
+    Reload,
+    /// Creates a conditional request if there is a response in the HTTP cache
+    /// and a normal request otherwise. It then updates the HTTP cache with the response.
+    NoCache,
+    /// Uses any response in the HTTP cache matching the request,
+    /// not paying attention to staleness. If there was no response,
+    /// it creates a normal request and updates the HTTP cache with the response.
+    ForceCache,
+    /// Uses any response in the HTTP cache matching the request,
+    /// not paying attention to staleness. If there was no response,
+    /// it returns a network error.
+    OnlyIfCached,
+    /// Overrides the check that determines if a response can be cached to always return true on 200.
+    /// Uses any response in the HTTP cache matching the request,
+    /// not paying attention to staleness. If there was no response,
+    /// it creates a normal request and updates the HTTP cache with the response.
+    IgnoreRules,
+    /// Behaves like [`CacheMode::Default`], except that a stale response is
+    /// served right away without revalidating it. The entry is then marked so
+    /// the next request for it is revalidated before it's served, which
+    /// spreads the revalidations over time without background tasks.
+    /// Requests with an explicit `no-cache` directive, and stale responses
+    /// that require revalidation (`must-revalidate`, `no-cache`, or in a
+    /// shared cache `proxy-revalidate`), are always revalidated regardless of
+    /// the mark. Stale responses are always revalidated when the cache is
+    /// read only, as the entry can't be marked.
+    RefreshStale,
+}
+
+impl TryFrom<http::Version> for HttpVersion {
+    type Error = BoxError;
+
+    fn try_from(value: http::Version) -> Result<Self> {
+        Ok(match value {
+            http::Version::HTTP_09 => Self::Http09,
+            http::Version::HTTP_10 => Self::Http10,
+            http::Version::HTTP_11 => Self::Http11,
+            http::Version::HTTP_2 => Self::H2,
+            http::Version::HTTP_3 => Self::H3,
+            _ => return Err(Box::new(BadVersion)),
+        })
+    }
+}
+
+impl From<HttpVersion> for http::Version {
+    fn from(value: HttpVersion) -> Self {
+        match value {
+            HttpVersion::Http09 => Self::HTTP_09,
+            HttpVersion::Http10 => Self::HTTP_10,
+            HttpVersion::Http11 => Self::HTTP_11,
+            HttpVersion::H2 => Self::HTTP_2,
+            HttpVersion::H3 => Self::HTTP_3,
+        }
+    }
+}
+
+#[cfg(feature = "http-types")]
+impl TryFrom<http_types::Version> for HttpVersion {
+    type Error = BoxError;
+
+    fn try_from(value: http_types::Version) -> Result<Self> {
+        Ok(match value {
+            http_types::Version::Http0_9 => Self::Http09,
+            http_types::Version::Http1_0 => Self::Http10,
+            http_types::Version::Http1_1 => Self::Http11,
+            http_types::Version::Http2_0 => Self::H2,
+            http_types::Version::Http3_0 => Self::H3,
+            _ => return Err(Box::new(BadVersion)),
+        })
+    }
+}
+
+#[cfg(feature = "http-types")]
+impl From<HttpVersion> for http_types::Version {
+    fn from(value: HttpVersion) -> Self {
+        match value {
+            HttpVersion::Http09 => Self::Http0_9,
+            HttpVersion::Http10 => Self::Http1_0,
+            HttpVersion::Http11 => Self::Http1_1,
+            HttpVersion::H2 => Self::Http2_0,
+            HttpVersion::H3 => Self::Http3_0,
+        }
+    }
+}
+
+/// Options struct provided by
+/// [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
+pub use http_cache_semantics::CacheOptions;
+
+/// A closure that takes [`http::request::Parts`] and returns a [`String`].
+/// By default, the cache key is a combination of the request method and uri with a colon in between.
+///
+/// When the cache removes the `GET` entry of a request with another method,
+/// the closure is given the parts of the request with the method replaced,
+/// and without their extensions.
+pub type CacheKey = Arc<dyn Fn(&request::Parts) -> String + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns the partition the
+/// request belongs to, e.g. from an `X-Partition` header.
+/// Entries of different partitions never share a cache key, requests without a
+/// partition use the single shared partition.
+pub type CachePartition =
+    Arc<dyn Fn(&request::Parts) -> Option<String> + Send + Sync>;
+
+/// A closure that can add or change the headers of a request the cache sends
+/// upstream, e.g. to authenticate or trace origin traffic. The changes are not
+/// seen by the cache key or the stored policy.
+pub type ModifyUpstreamRequest = Arc<dyn Fn(&mut request::Parts) + Send + Sync>;
+
+/// A closure that transforms a response on its way into or out of the cache,
+/// e.g. to rewrite urls in the body or redact headers before they're stored.
+/// The `Content-Length` header, if present, is updated when the body changes.
+pub type TransformResponse = Arc<dyn Fn(&mut HttpResponse) + Send + Sync>;
+
+/// A closure that decides whether a response the cache would store may be
+/// stored, e.g. to keep out responses whose body reports an error. It only
+/// ever vetoes storing, returning `true` doesn't make a response cacheable.
+pub type Cacheable = Arc<dyn Fn(&HttpResponse) -> bool + Send + Sync>;
+
+/// Bounds the freshness lifetime of responses with a matching `Content-Type`,
+/// e.g. to cache images for at least a day from origins that send poor caching
+/// headers.
+///
+/// The bounds take precedence over the freshness the origin sends through
+/// `Cache-Control` and `Expires`, but not over whether a response may be
+/// stored at all, so `no-store` and `private` responses are never stored.
+/// Only the first matching entry of [`HttpCacheOptions::content_type_freshness`]
+/// applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentTypeFreshness {
+    /// The media type to match, case insensitive. `image/*` matches every
+    /// image type and `*/*` matches every response with a `Content-Type`.
+    pub pattern: String,
+    /// Responses are fresh for at least this long, even when the origin marks
+    /// them `no-cache` or already expired.
+    pub min: Option<Duration>,
+    /// Responses are fresh for at most this long.
+    pub max: Option<Duration>,
+}
+
+impl ContentTypeFreshness {
+    /// Creates a new [`ContentTypeFreshness`] without bounds for the pattern
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self { pattern: pattern.into(), min: None, max: None }
+    }
+
+    /// Sets the lower bound of the freshness lifetime
+    #[must_use]
+    pub fn min(mut self, min: Duration) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    /// Sets the upper bound of the freshness lifetime
+    #[must_use]
+    pub fn max(mut self, max: Duration) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    fn matches(&self, media_type: &str) -> bool {
+        let pattern = self.pattern.trim();
+        match pattern.strip_suffix("/*") {
+            Some("*") => true,
+            Some(kind) => media_type
+                .split_once('/')
+                .map_or(false, |(k, _)| k.eq_ignore_ascii_case(kind)),
+            None => pattern.eq_ignore_ascii_case(media_type),
+        }
+    }
+}
+
+/// Overrides the mode and cache options of [`HttpCache`] for the requests it
+/// matches, e.g. to force caching the responses of a static asset host while
+/// never storing those of an auth provider, behind the same client.
+///
+/// A rule matches a request whose host matches [`CacheRule::host`] and whose
+/// url starts with [`CacheRule::url_prefix`], when they're set. Only the first
+/// matching entry of [`HttpCacheOptions::cache_rules`] applies, the requests
+/// no rule matches use the mode and cache options of the cache.
+#[derive(Debug, Clone, Default)]
+pub struct CacheRule {
+    /// The host to match, case insensitive. `*.example.com` matches every
+    /// subdomain of `example.com` and `*` matches every host.
+    pub host: Option<String>,
+    /// The start of the urls to match, e.g. `https://example.com/static/`.
+    pub url_prefix: Option<String>,
+    /// The mode used instead of [`HttpCache::mode`].
+    pub mode: Option<CacheMode>,
+    /// The cache options used instead of [`HttpCacheOptions::cache_options`].
+    /// Those of a [`CacheManager`] still take precedence.
+    pub cache_options: Option<CacheOptions>,
+}
+
+impl CacheRule {
+    /// Creates a new [`CacheRule`] without overrides matching the host
+    pub fn host(pattern: impl Into<String>) -> Self {
+        Self { host: Some(pattern.into()), ..Default::default() }
+    }
+
+    /// Creates a new [`CacheRule`] without overrides matching the urls that
+    /// start with the prefix
+    pub fn url_prefix(prefix: impl Into<String>) -> Self {
+        Self { url_prefix: Some(prefix.into()), ..Default::default() }
+    }
+
+    /// Sets the mode of the matching requests
+    #[must_use]
+    pub fn mode(mut self, mode: CacheMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the cache options of the matching requests
+    #[must_use]
+    pub fn cache_options(mut self, cache_options: CacheOptions) -> Self {
+        self.cache_options = Some(cache_options);
+        self
+    }
+
+    fn matches(&self, parts: &request::Parts) -> bool {
+        let host = parts.uri.host().unwrap_or_default();
+        let host_matches = self.host.as_deref().map_or(true, |pattern| {
+            let pattern = pattern.trim();
+            match pattern.strip_prefix('*') {
+                Some("") => true,
+                Some(suffix) if suffix.starts_with('.') => host
+                    .len()
+                    .checked_sub(suffix.len())
+                    .filter(|&start| start > 0)
+                    .and_then(|start| host.get(start..))
+                    .map_or(false, |end| end.eq_ignore_ascii_case(suffix)),
+                _ => pattern.eq_ignore_ascii_case(host),
+            }
+        });
+        host_matches
+            && self.url_prefix.as_deref().map_or(true, |prefix| {
+                parts.uri.to_string().starts_with(prefix)
+            })
+    }
+}
+
+/// Caches error responses with the listed statuses for a bounded time, even
+/// when the origin marks them uncacheable, so repeated requests for a missing
+/// resource are answered locally, see [`HttpCacheOptions::negative_caching`].
+///
+/// Only statuses HTTP caching understands can be served from the cache, such
+/// as `404`, `405`, `410`, `414`, and `501`, other statuses are never stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeCaching {
+    /// The statuses of the responses to cache
+    pub statuses: Vec<u16>,
+    /// How long the responses are fresh for
+    pub ttl: Duration,
+}
+
+impl NegativeCaching {
+    /// Creates a new [`NegativeCaching`] of the statuses for the time
+    pub fn new(statuses: impl IntoIterator<Item = u16>, ttl: Duration) -> Self {
+        Self { statuses: statuses.into_iter().collect(), ttl }
+    }
+
+    // Returns a copy of the response, without its body, that's fresh for the
+    // time responses with its status are cached for, if they are
+    fn freshen(&self, res: &HttpResponse) -> Option<HttpResponse> {
+        if !self.statuses.contains(&res.status) {
+            return None;
+        }
+        let mut headers = res.headers.clone();
+        headers.remove("expires");
+        headers.remove(PRAGMA.as_str());
+        headers.insert(
+            CACHE_CONTROL.as_str().to_string(),
+            format!("max-age={}", self.ttl.as_secs()),
+        );
+        Some(HttpResponse {
+            body: Vec::new(),
+            headers,
+            status: res.status,
+            url: res.url.clone(),
+            version: res.version,
+        })
+    }
+}
+
+/// An event emitted by [`HttpCache`], carrying the cache key of the entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent {
+    /// A response was stored in the cache
+    Stored(String),
+    /// A response was served from the cache
+    Hit(String),
+    /// No response was found in the cache
+    Miss(String),
+    /// A stored response was revalidated with the origin (304 Not Modified)
+    Revalidated(String),
+    /// A response was removed from the cache
+    Evicted(String),
+    /// A stored response was marked stale by [`HttpCache::soft_purge`]
+    Purged(String),
+    /// Reading or writing the response failed and was ignored as the cache
+    /// fails open, carrying the error message too
+    Failed(String, String),
+    /// A response wasn't stored as its body is larger than
+    /// [`HttpCacheOptions::max_body_size`]
+    Oversized(String),
+}
+
+/// The verdict of [`HttpCache::explain`] on a request and response pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDecision {
+    /// Whether the response would be stored
+    pub store: bool,
+    /// The directive, header, or setting that drove the decision
+    pub reason: String,
+    /// How long the response would be considered fresh
+    pub time_to_live: Duration,
+    /// Whether the response would have to be revalidated before being served
+    pub needs_revalidation: bool,
+}
+
+// Checks if any of the Cache-Control header values contain the directive,
+// ignoring directive names quoted in the value of another directive, e.g.
+// `no-cache="set-cookie, proxy-revalidate"`
+fn has_directive<'a>(
+    values: impl Iterator<Item = &'a str>,
+    directive: &str,
+) -> bool {
+    values.flat_map(split_directives).any(|d| {
+        d.split('=')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .eq_ignore_ascii_case(directive)
+    })
+}
+
+// Checks if responses with the status are stored, the statuses that are
+// cacheable by default, which are replayed as is
+fn is_storable_status(status: u16) -> bool {
+    matches!(status, 200 | 203 | 300 | 301 | 308 | 410)
+}
+
+// The headers that only apply to the connection the response came over
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Removes the hop-by-hop headers, and those the `Connection` header names,
+// which mean nothing once the response is replayed from the cache.
+fn strip_hop_by_hop(res: &mut HttpResponse) {
+    let named: Vec<String> = res
+        .headers
+        .get_all("connection")
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    for name in
+        HOP_BY_HOP.iter().copied().chain(named.iter().map(String::as_str))
+    {
+        res.headers.remove(name);
+    }
+}
+
+// Checks if the request only accepts a stored response
+fn only_if_cached(parts: &request::Parts) -> bool {
+    has_directive(
+        parts
+            .headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok()),
+        "only-if-cached",
+    )
+}
+
+// Returns the seconds given to the directive by any of the Cache-Control
+// header values, e.g. `stale-while-revalidate=60`
+fn directive_seconds<'a>(
+    values: impl Iterator<Item = &'a str>,
+    directive: &str,
+) -> Option<u64> {
+    values.flat_map(split_directives).find_map(|d| {
+        let (name, value) = d.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case(directive) {
+            return None;
+        }
+        value.trim().trim_matches('"').parse().ok()
+    })
+}
+
+/// A closure that runs a future in the background, e.g. with `tokio::spawn`.
+pub type Spawn = Arc<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>;
+
+/// A source of the current time, which decides the freshness and age of
+/// stored responses, see [`HttpCacheOptions::clock`]. Tests can provide one
+/// they advance by hand, rather than sleeping for responses to go stale.
+pub trait Clock: Send + Sync {
+    /// Returns the current time
+    fn now(&self) -> SystemTime;
+}
+
+/// The [`Clock`] of the system, used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A closure that starts revalidating a stale response in the background,
+/// given its cache key and the request it's served for,
+/// see [`HttpCache::revalidate_in_background`].
+pub type Revalidate = Arc<dyn Fn(String, http::Request<Vec<u8>>) + Send + Sync>;
+
+/// Lets concurrent misses for a cache key share a single upstream fetch,
+/// see [`HttpCacheOptions::coalescer`].
+///
+/// The first request that misses fetches the response, the others wait for
+/// it to be done and look the key up again. Clones share the requests in
+/// flight, so a cache and its clones coalesce with each other.
+#[derive(Debug, Clone, Default)]
+pub struct Coalescer {
+    in_flight: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl Coalescer {
+    // Leads the fetch for the key when there's none in flight, otherwise
+    // waits for the one in flight to be done
+    async fn join(&self, cache_key: &str) -> Option<Flight> {
+        let lock = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(cache_key) {
+                Some(lock) => lock.clone(),
+                None => {
+                    let lock = Arc::new(AsyncMutex::new(()));
+                    let guard = lock.try_lock_owned()?;
+                    in_flight.insert(cache_key.to_string(), lock);
+                    return Some(Flight {
+                        cache_key: cache_key.to_string(),
+                        in_flight: self.in_flight.clone(),
+                        _guard: guard,
+                    });
+                }
+            }
+        };
+        drop(lock.lock().await);
+        None
+    }
+}
+
+// A fetch in flight, others waiting for it are released when it's dropped
+struct Flight {
+    cache_key: String,
+    in_flight: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for Flight {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.cache_key);
+    }
+}
+
+/// A closure that receives every [`CacheEvent`] emitted by [`HttpCache`].
+///
+/// It is never called while the manager is performing an operation, so it is
+/// safe to call back into the manager. Evictions performed by a backend on its
+/// own (capacity or time based) are not seen by [`HttpCache`], the same closure
+/// can be registered with the backend where supported, e.g. moka's `eviction_listener`.
+pub type CacheEventHook = Arc<dyn Fn(CacheEvent) + Send + Sync>;
+
+/// Can be used to override the default [`CacheOptions`] and cache key.
+/// The cache key is a closure that takes [`http::request::Parts`] and returns a [`String`].
+#[derive(Default, Clone)]
+pub struct HttpCacheOptions {
+    /// Override the default cache options. Options returned by
+    /// [`CacheManager::cache_options`] take precedence.
+    pub cache_options: Option<CacheOptions>,
+    /// Overrides the mode and cache options for the requests matching one of
+    /// the rules, only the first matching rule applies. None by default.
+    pub cache_rules: Vec<CacheRule>,
+    /// Override the default cache key generator.
+    pub cache_key: Option<CacheKey>,
+    /// Receives the events emitted by the cache, none by default.
+    pub on_event: Option<CacheEventHook>,
+    /// Partitions the cache by request, all requests share one partition by default.
+    pub cache_partition: Option<CachePartition>,
+    /// Normalize the url before computing the default cache key, so urls that
+    /// only differ in host case, default port, trailing dot of the host,
+    /// dot segments, or the order of query parameters share an entry.
+    /// Disabled by default.
+    pub normalize_url: bool,
+    /// Names of query parameters left out of the url the default cache key
+    /// is computed from, such as tracking parameters, so urls that only
+    /// differ in them share an entry. A name ending with `*` matches every
+    /// parameter starting with the rest of it, e.g. `utm_*`. The request
+    /// sent upstream keeps them. None by default.
+    pub ignored_query_params: Vec<String>,
+    /// Modifies the requests sent upstream, none by default.
+    pub modify_upstream_request: Option<ModifyUpstreamRequest>,
+    /// Serve from and revalidate against the cache without ever writing to it,
+    /// neither storing responses nor removing entries. Disabled by default.
+    pub read_only: bool,
+    /// Fold a hash of the request body into the cache key of requests other
+    /// than GET and HEAD, which allows caching the responses to POST requests
+    /// that have explicit freshness. Requests whose body isn't available are
+    /// passed through without the cache. Disabled by default.
+    pub cache_request_body: bool,
+    /// Serve every request from the cache, stale responses included, without
+    /// ever going to the network. Requests without a cached response fail
+    /// with an [`Offline`] error. Disabled by default.
+    pub offline: bool,
+    /// Bounds the freshness lifetime of responses by their `Content-Type`,
+    /// none by default.
+    pub content_type_freshness: Vec<ContentTypeFreshness>,
+    /// Responses are fresh for at least this long, even when the origin marks
+    /// them `no-cache` or already expired, unless an entry of
+    /// `content_type_freshness` matches them. None by default.
+    pub min_freshness: Option<Duration>,
+    /// Responses are fresh for at most this long, unless an entry of
+    /// `content_type_freshness` matches them. None by default.
+    pub max_freshness: Option<Duration>,
+    /// Caps the heuristic freshness lifetime given to responses without
+    /// explicit freshness, based on their `Last-Modified` header. The
+    /// fraction of the time since then that is used is
+    /// [`CacheOptions::cache_heuristic`]. No cap by default.
+    pub max_heuristic_freshness: Option<Duration>,
+    /// Responses whose body, after `on_store`, is larger than this many bytes
+    /// are passed through without being stored, emitting a
+    /// [`CacheEvent::Oversized`]. A stored response for the same key is
+    /// removed, as it was replaced at the origin. No limit by default.
+    pub max_body_size: Option<u64>,
+    /// Caches error responses with the listed statuses for a bounded time,
+    /// whatever the origin says. Disabled by default.
+    pub negative_caching: Option<NegativeCaching>,
+    /// Purge the cached responses of an origin when one of its responses
+    /// carries a `Clear-Site-Data` header with the `"cache"` type, like a
+    /// browser does, e.g. on logout. Disabled by default.
+    pub clear_site_data: bool,
+    /// Transforms responses before they're stored, none by default. A stored
+    /// response that is revalidated is stored again after going through
+    /// `on_load`, so the transform must be deterministic and leave responses
+    /// it already transformed as they are.
+    pub on_store: Option<TransformResponse>,
+    /// Transforms responses loaded from the cache, none by default. Responses
+    /// that were just stored go through it too, so every response served from
+    /// the cache looks the same whether it was a hit or a miss.
+    pub on_load: Option<TransformResponse>,
+    /// Decides whether responses the cache would store may be stored, it's
+    /// given the response as received from the origin. A response it rejects
+    /// is passed through, and a stored response for the same key is removed
+    /// as it was replaced at the origin. None by default.
+    pub cacheable: Option<Cacheable>,
+    /// Refuse to store responses with a `Set-Cookie` header, unless their
+    /// Cache-Control header has a `public` or `immutable` directive, or lists
+    /// `Set-Cookie` in a `no-cache` directive, so a cookie sent to one user
+    /// is never replayed to another. Shared caches already treat them as
+    /// stale, this keeps them out of the backend as well. Disabled by
+    /// default.
+    pub refuse_set_cookie: bool,
+    /// Treat errors of the cache manager reading or storing a response as a
+    /// miss and a skipped store, so requests still reach the origin when the
+    /// backend is unavailable. The errors are reported as
+    /// [`CacheEvent::Failed`], other errors, like building the cache key, are
+    /// still returned. Disabled by default.
+    pub fail_open: bool,
+    /// How long past its freshness lifetime a stale response still stands in
+    /// for a server error or a network error of its revalidation, when it has
+    /// no `stale-if-error` directive of its own. None by default, which serves
+    /// it however stale it is.
+    pub stale_if_error: Option<Duration>,
+    /// Append an [RFC 9211](https://www.rfc-editor.org/rfc/rfc9211)
+    /// `Cache-Status` header named `http-cache` to every response, telling
+    /// whether it was a hit or forwarded to the origin and why. Disabled by
+    /// default.
+    pub cache_status_header: bool,
+    /// Names of targeted cache control headers, such as `CDN-Cache-Control`
+    /// or `Surrogate-Control`, in order of precedence. The first of them a
+    /// response carries takes the place of its `Cache-Control` header when
+    /// the policy is created, so origins can address this cache separately
+    /// from browsers ([RFC 9213](https://www.rfc-editor.org/rfc/rfc9213)).
+    /// The headers are left untouched in the response. None by default.
+    pub targeted_cache_control: Vec<String>,
+    /// Coalesces concurrent misses for a cache key into a single upstream
+    /// fetch, whose stored response then answers the others. Responses that
+    /// aren't stored are fetched again by each of them. None by default.
+    pub coalescer: Option<Coalescer>,
+    /// Revalidates stale responses within the `stale-while-revalidate` window
+    /// of their Cache-Control header in the background, serving them right
+    /// away instead of once revalidated. None by default, it's set by
+    /// [`HttpCache::revalidate_in_background`].
+    pub revalidate: Option<Revalidate>,
+    /// The source of the current time the freshness and age of responses are
+    /// computed with. None by default, which uses the [`SystemClock`]. Cache
+    /// managers keep using the system time, to expire or prune entries.
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+impl Debug for HttpCacheOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpCacheOptions")
+            .field("cache_options", &self.cache_options)
+            .field("cache_rules", &self.cache_rules)
+            .field("cache_key", &"Fn(&request::Parts) -> String")
+            .field("on_event", &"Fn(CacheEvent)")
+            .field("cache_partition", &"Fn(&request::Parts) -> Option<String>")
+            .field("normalize_url", &self.normalize_url)
+            .field("ignored_query_params", &self.ignored_query_params)
+            .field("modify_upstream_request", &"Fn(&mut request::Parts)")
+            .field("read_only", &self.read_only)
+            .field("cache_request_body", &self.cache_request_body)
+            .field("offline", &self.offline)
+            .field("content_type_freshness", &self.content_type_freshness)
+            .field("min_freshness", &self.min_freshness)
+            .field("max_freshness", &self.max_freshness)
+            .field("max_heuristic_freshness", &self.max_heuristic_freshness)
+            .field("max_body_size", &self.max_body_size)
+            .field("negative_caching", &self.negative_caching)
+            .field("clear_site_data", &self.clear_site_data)
+            .field("on_store", &"Fn(&mut HttpResponse)")
+            .field("on_load", &"Fn(&mut HttpResponse)")
+            .field("cacheable", &"Fn(&HttpResponse) -> bool")
+            .field("refuse_set_cookie", &self.refuse_set_cookie)
+            .field("fail_open", &self.fail_open)
+            .field("stale_if_error", &self.stale_if_error)
+            .field("cache_status_header", &self.cache_status_header)
+            .field("targeted_cache_control", &self.targeted_cache_control)
+            .field("coalescer", &self.coalescer)
+            .field("revalidate", &"Fn(String, http::Request<Vec<u8>>)")
+            .field("clock", &"dyn Clock")
+            .finish()
+    }
+}
+
+impl HttpCacheOptions {
+    // The current time according to the clock, the system time by default
+    fn now(&self) -> SystemTime {
+        self.clock.as_ref().map_or_else(SystemTime::now, |clock| clock.now())
+    }
+
+    fn create_cache_key(
+        &self,
+        parts: &request::Parts,
+        override_method: Option<&str>,
+    ) -> String {
+        let cache_key = if let Some(cache_key) = &self.cache_key {
+            // The closure is given the method the key is created for
+            match override_method.filter(|method| parts.method != *method) {
+                Some(method) => match with_method(parts, method) {
+                    Some(parts) => cache_key(&parts),
+                    None => cache_key(parts),
+                },
+                None => cache_key(parts),
+            }
+        } else {
+            let method =
+                override_method.unwrap_or_else(|| parts.method.as_str());
+            let uri = if self.normalize_url {
+                normalize_uri(&parts.uri)
+            } else {
+                parts.uri.to_string()
+            };
+            if self.ignored_query_params.is_empty() {
+                format!("{}:{}", method, uri)
+            } else {
+                let uri =
+                    without_query_params(&uri, &self.ignored_query_params);
+                format!("{}:{}", method, uri)
+            }
+        };
+        match self.cache_partition.as_ref().and_then(|p| p(parts)) {
+            // The length prefix keeps a partition from spelling out the key
+            // of another partition, e.g. `a:b` + `c` and `a` + `b:c`.
+            Some(partition) => {
+                format!("{}:{}:{}", partition.len(), partition, cache_key)
+            }
+            None => cache_key,
+        }
+    }
+}
+
+// Copies the parts of a request with another method. The extensions aren't
+// copied, they can't be cloned.
+fn with_method(parts: &request::Parts, method: &str) -> Option<request::Parts> {
+    let mut request = http::Request::builder()
+        .method(method)
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(())
+        .ok()?;
+    *request.headers_mut() = parts.headers.clone();
+    Some(request.into_parts().0)
+}
+
+// Normalizes the parts of an absolute uri that don't change its meaning.
+// Parsing as a `Url` lowercases the host, removes the default port, and
+// resolves dot segments, the trailing dot of the host is removed and the
+// query parameters are sorted by name, keeping the order of repeated names.
+fn normalize_uri(uri: &http::Uri) -> String {
+    let mut url = match Url::parse(&uri.to_string()) {
+        Ok(url) => url,
+        Err(_) => return uri.to_string(),
+    };
+    if let Some(host) = url.host_str() {
+        if host.len() > 1 && host.ends_with('.') {
+            let host = host.trim_end_matches('.').to_string();
+            // Leave the host alone if it isn't valid without the dot
+            let _ = url.set_host(Some(&host));
+        }
+    }
+    if let Some(query) = url.query() {
+        let mut params =
+            query.split('&').filter(|p| !p.is_empty()).collect::<Vec<_>>();
+        params.sort_by_key(|p| p.split('=').next().unwrap_or_default());
+        let query = params.join("&");
+        url.set_query(if query.is_empty() { None } else { Some(&query) });
+    }
+    url.to_string()
+}
+
+// Removes the query parameters with the given names from the uri, a name
+// ending with `*` matches every parameter starting with the rest of it.
+fn without_query_params(uri: &str, names: &[String]) -> String {
+    let mut url = match Url::parse(uri) {
+        Ok(url) => url,
+        Err(_) => return uri.to_string(),
+    };
+    if let Some(query) = url.query() {
+        let ignored = |param: &str| {
+            let name = param.split('=').next().unwrap_or_default();
+            names.iter().any(|ignored| match ignored.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == ignored,
+            })
+        };
+        let query = query
+            .split('&')
+            .filter(|p| !p.is_empty() && !ignored(p))
+            .collect::<Vec<_>>()
+            .join("&");
+        url.set_query(if query.is_empty() { None } else { Some(&query) });
+    }
+    url.to_string()
+}
+
+// Returns the `Range` header of a GET request if present
+fn range_request(middleware: &impl Middleware) -> Result<Option<String>> {
+    if middleware.method()? != Method::GET.as_str() {
+        return Ok(None);
+    }
+    match middleware.parts()?.headers.get(RANGE) {
+        Some(range) => Ok(Some(range.to_str()?.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Caches requests according to http spec.
 #[derive(Debug, Clone)]
 pub struct HttpCache<T: CacheManager> {
     /// Determines the manager behavior.
@@ -408,14 +2146,864 @@ pub struct HttpCache<T: CacheManager> {
     pub options: HttpCacheOptions,
 }
 
-#[allow(dead_code)]
-impl<T: CacheManager> HttpCache<T> {
+/// Builder for [`HttpCache`].
+///
+/// Defaults to [`CacheMode::Default`] and [`HttpCacheOptions::default`],
+/// a manager must be provided before the cache can be built.
+/// ```
+/// # use http_cache::{CacheMode, HttpCacheBuilder, InMemoryManager};
+/// let cache = HttpCacheBuilder::new()
+///     .mode(CacheMode::ForceCache)
+///     .manager(InMemoryManager::new())
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpCacheBuilder<T = ()> {
+    mode: CacheMode,
+    manager: T,
+    options: HttpCacheOptions,
+}
+
+impl Default for HttpCacheBuilder {
+    fn default() -> Self {
+        Self {
+            mode: CacheMode::Default,
+            manager: (),
+            options: HttpCacheOptions::default(),
+        }
+    }
+}
+
+impl HttpCacheBuilder {
+    /// Creates a new builder with the default mode and options
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> HttpCacheBuilder<T> {
+    /// Sets the [`CacheMode`]
+    #[must_use]
+    pub fn mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the manager instance that implements the [`CacheManager`] trait
+    pub fn manager<M: CacheManager>(self, manager: M) -> HttpCacheBuilder<M> {
+        HttpCacheBuilder { mode: self.mode, manager, options: self.options }
+    }
+
+    /// Sets the [`HttpCacheOptions`], replacing any previously set options
+    #[must_use]
+    pub fn options(mut self, options: HttpCacheOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Overrides the default [`CacheOptions`]
+    #[must_use]
+    pub fn cache_options(mut self, cache_options: CacheOptions) -> Self {
+        self.options.cache_options = Some(cache_options);
+        self
+    }
+
+    /// Sets the rules that override the mode and cache options per request
+    #[must_use]
+    pub fn cache_rules(mut self, cache_rules: Vec<CacheRule>) -> Self {
+        self.options.cache_rules = cache_rules;
+        self
+    }
+
+    /// Sets whether the cache is shared, the default, or private.
+    /// A shared cache doesn't store responses to requests with an
+    /// `Authorization` header unless the response explicitly allows it
+    /// with `public`, `must-revalidate`, or `s-maxage`, nor responses marked
+    /// `private`.
+    #[must_use]
+    pub fn shared(mut self, shared: bool) -> Self {
+        let cache_options = self
+            .options
+            .cache_options
+            .get_or_insert_with(CacheOptions::default);
+        cache_options.shared = shared;
+        self
+    }
+
+    /// Overrides the default cache key generator
+    #[must_use]
+    pub fn cache_key(mut self, cache_key: CacheKey) -> Self {
+        self.options.cache_key = Some(cache_key);
+        self
+    }
+
+    /// Sets the closure that partitions the cache by request
+    #[must_use]
+    pub fn cache_partition(mut self, cache_partition: CachePartition) -> Self {
+        self.options.cache_partition = Some(cache_partition);
+        self
+    }
+
+    /// Normalizes the url before computing the default cache key
+    #[must_use]
+    pub fn normalize_url(mut self, normalize_url: bool) -> Self {
+        self.options.normalize_url = normalize_url;
+        self
+    }
+
+    /// Sets the query parameters left out of the default cache key
+    #[must_use]
+    pub fn ignored_query_params(
+        mut self,
+        ignored_query_params: Vec<String>,
+    ) -> Self {
+        self.options.ignored_query_params = ignored_query_params;
+        self
+    }
+
+    /// Sets the closure that modifies the requests sent upstream
+    #[must_use]
+    pub fn modify_upstream_request(
+        mut self,
+        modify_upstream_request: ModifyUpstreamRequest,
+    ) -> Self {
+        self.options.modify_upstream_request = Some(modify_upstream_request);
+        self
+    }
+
+    /// Sets the closure that receives the events emitted by the cache
+    #[must_use]
+    pub fn on_event(mut self, on_event: CacheEventHook) -> Self {
+        self.options.on_event = Some(on_event);
+        self
+    }
+
+    /// Prevents the cache from storing responses or removing entries
+    #[must_use]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    /// Folds a hash of the request body into the cache key of requests other
+    /// than GET and HEAD, allowing POST requests to be cached
+    #[must_use]
+    pub fn cache_request_body(mut self, cache_request_body: bool) -> Self {
+        self.options.cache_request_body = cache_request_body;
+        self
+    }
+
+    /// Serves every request from the cache without going to the network
+    #[must_use]
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.options.offline = offline;
+        self
+    }
+
+    /// Sets the freshness bounds of responses by their `Content-Type`
+    #[must_use]
+    pub fn content_type_freshness(
+        mut self,
+        content_type_freshness: Vec<ContentTypeFreshness>,
+    ) -> Self {
+        self.options.content_type_freshness = content_type_freshness;
+        self
+    }
+
+    /// Sets the lower bound of the freshness lifetime of responses
+    #[must_use]
+    pub fn min_freshness(mut self, min_freshness: Duration) -> Self {
+        self.options.min_freshness = Some(min_freshness);
+        self
+    }
+
+    /// Sets the upper bound of the freshness lifetime of responses
+    #[must_use]
+    pub fn max_freshness(mut self, max_freshness: Duration) -> Self {
+        self.options.max_freshness = Some(max_freshness);
+        self
+    }
+
+    /// Sets the fraction of the time since a response was last modified that
+    /// it's considered fresh for when it has no explicit freshness, 0.1 by
+    /// default
+    #[must_use]
+    pub fn cache_heuristic(mut self, cache_heuristic: f32) -> Self {
+        let cache_options = self
+            .options
+            .cache_options
+            .get_or_insert_with(CacheOptions::default);
+        cache_options.cache_heuristic = cache_heuristic;
+        self
+    }
+
+    /// Caps the heuristic freshness lifetime of responses
+    #[must_use]
+    pub fn max_heuristic_freshness(
+        mut self,
+        max_heuristic_freshness: Duration,
+    ) -> Self {
+        self.options.max_heuristic_freshness = Some(max_heuristic_freshness);
+        self
+    }
+
+    /// Sets the size in bytes above which response bodies aren't stored
+    #[must_use]
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.options.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Caches error responses with the listed statuses for a bounded time
+    #[must_use]
+    pub fn negative_caching(
+        mut self,
+        negative_caching: NegativeCaching,
+    ) -> Self {
+        self.options.negative_caching = Some(negative_caching);
+        self
+    }
+
+    /// Purges the cached responses of an origin on `Clear-Site-Data: "cache"`
+    #[must_use]
+    pub fn clear_site_data(mut self, clear_site_data: bool) -> Self {
+        self.options.clear_site_data = clear_site_data;
+        self
+    }
+
+    /// Sets the closure that transforms responses before they're stored
+    #[must_use]
+    pub fn on_store(mut self, on_store: TransformResponse) -> Self {
+        self.options.on_store = Some(on_store);
+        self
+    }
+
+    /// Sets the closure that transforms responses loaded from the cache
+    #[must_use]
+    pub fn on_load(mut self, on_load: TransformResponse) -> Self {
+        self.options.on_load = Some(on_load);
+        self
+    }
+
+    /// Sets the closure that decides whether responses may be stored
+    #[must_use]
+    pub fn cacheable(mut self, cacheable: Cacheable) -> Self {
+        self.options.cacheable = Some(cacheable);
+        self
+    }
+
+    /// Refuses to store responses with a `Set-Cookie` header
+    #[must_use]
+    pub fn refuse_set_cookie(mut self, refuse_set_cookie: bool) -> Self {
+        self.options.refuse_set_cookie = refuse_set_cookie;
+        self
+    }
+
+    /// Ignores errors of the cache manager reading or storing a response
+    #[must_use]
+    pub fn fail_open(mut self, fail_open: bool) -> Self {
+        self.options.fail_open = fail_open;
+        self
+    }
+
+    /// Sets how long past its freshness lifetime a stale response stands in
+    /// for a failed revalidation when it has no `stale-if-error` directive
+    #[must_use]
+    pub fn stale_if_error(mut self, stale_if_error: Duration) -> Self {
+        self.options.stale_if_error = Some(stale_if_error);
+        self
+    }
+
+    /// Appends an RFC 9211 `Cache-Status` header to every response
+    #[must_use]
+    pub fn cache_status_header(mut self, cache_status_header: bool) -> Self {
+        self.options.cache_status_header = cache_status_header;
+        self
+    }
+
+    /// Sets the targeted cache control headers that take precedence over
+    /// `Cache-Control`, in order
+    #[must_use]
+    pub fn targeted_cache_control(
+        mut self,
+        targeted_cache_control: Vec<String>,
+    ) -> Self {
+        self.options.targeted_cache_control = targeted_cache_control;
+        self
+    }
+
+    /// Sets whether concurrent misses for a cache key share a single
+    /// upstream fetch
+    #[must_use]
+    pub fn coalesce_requests(mut self, coalesce_requests: bool) -> Self {
+        self.options.coalescer = coalesce_requests.then(Coalescer::default);
+        self
+    }
+
+    /// Sets the source of the current time the freshness and age of
+    /// responses are computed with
+    #[must_use]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.options.clock = Some(clock);
+        self
+    }
+}
+
+impl<T: CacheManager> HttpCacheBuilder<T> {
+    /// Builds the [`HttpCache`]
+    pub fn build(self) -> HttpCache<T> {
+        HttpCache {
+            mode: self.mode,
+            manager: self.manager,
+            options: self.options,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: CacheManager> HttpCache<T> {
+    /// Creates a new [`HttpCache`] with the default mode and options
+    pub fn with_manager(manager: T) -> Self {
+        HttpCacheBuilder::new().manager(manager).build()
+    }
+
+    /// Returns the cache manager, e.g. to inspect or warm the cache after the
+    /// middleware has been handed to a client
+    pub fn manager(&self) -> &T {
+        &self.manager
+    }
+
+    /// Removes the cached response for the given method and url, using the
+    /// same cache key the middleware would for such a request. The request
+    /// carries no headers, so a [`CachePartition`] sees it without them.
+    pub async fn invalidate(&self, method: &str, url: &Url) -> Result<()> {
+        let cache_key = self.cache_key_for(method, url)?;
+        self.manager.delete(&cache_key).await?;
+        self.emit(CacheEvent::Evicted(cache_key));
+        Ok(())
+    }
+
+    /// Marks the cached response for the given method and url stale, so the
+    /// next request for it revalidates with the origin instead of being
+    /// served from the cache. Unlike [`HttpCache::invalidate`] the response
+    /// is kept, so it can still be revalidated with a conditional request or
+    /// served stale when revalidating fails. Does nothing if there is no
+    /// such response, or if the cache is read only. A response with a `Vary`
+    /// header is removed instead, as the headers of the request it answered
+    /// are only known to its policy, which is created again here.
+    pub async fn soft_purge(&self, method: &str, url: &Url) -> Result<()> {
+        if self.options.read_only {
+            return Ok(());
+        }
+        let cache_key = self.cache_key_for(method, url)?;
+        let Some((res, _)) = self.manager.get(&cache_key).await? else {
+            return Ok(());
+        };
+        if res.headers.get("vary").is_some() {
+            return self.invalidate(method, url).await;
+        }
+        let parts = http::Request::builder()
+            .method(method)
+            .uri(url.as_str())
+            .body(())?
+            .into_parts()
+            .0;
+        let options = self.cache_options(&parts).unwrap_or_default();
+        // Received as long ago as it would stay fresh if received now, the
+        // response is stale from now on, and how long it's been stale for,
+        // as `stale-if-error` sees it, is counted from now too
+        let now = self.now();
+        let fresh_for =
+            CachePolicy::new_options(&parts, &res.parts()?, now, options)
+                .time_to_live(now);
+        let received = now.checked_sub(fresh_for).unwrap_or(now);
+        let stale =
+            CachePolicy::new_options(&parts, &res.parts()?, received, options);
+        self.manager.put(cache_key.clone(), res, stale).await?;
+        self.emit(CacheEvent::Purged(cache_key));
+        Ok(())
+    }
+
+    /// Removes every cached response from the manager
+    pub async fn clear(&self) -> Result<()> {
+        self.manager.clear().await
+    }
+
+    /// Waits for every write made so far to be persisted by the manager. There
+    /// is no async `Drop`, so call this before shutting down when the manager
+    /// buffers writes.
+    pub async fn flush(&self) -> Result<()> {
+        self.manager.flush().await
+    }
+
+    /// Removes every cached response whose cache key matches the predicate,
+    /// e.g. those of a host or under a path prefix. The default cache key is
+    /// the method and the url joined by a `:`. Managers that can't list their
+    /// keys return an [`Unsupported`] error.
+    pub async fn clear_matching(
+        &self,
+        predicate: impl Fn(&str) -> bool + Send + Sync,
+    ) -> Result<()> {
+        self.manager.delete_matching(&predicate).await
+    }
+
+    /// Removes every cached response whose cache key starts with the prefix,
+    /// e.g. `GET:https://api.example.com/v1/` for the `GET` responses under
+    /// that path. Managers that can't list their keys return an
+    /// [`Unsupported`] error.
+    pub async fn clear_prefix(&self, prefix: &str) -> Result<()> {
+        self.manager.delete_prefix(prefix).await
+    }
+
+    /// Describes every cached response, such as to show what's in the cache.
+    /// Managers that can't list their keys return an [`Unsupported`] error.
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        self.manager.entries().await
+    }
+
+    /// Removes every cached response that has been stale for at least the
+    /// grace period, such as those of urls that are never requested again.
+    /// Managers that can't list their keys return an [`Unsupported`] error.
+    pub async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        self.manager.prune_expired(grace).await
+    }
+
+    /// Counts the cached responses and the bytes they take up, such as to
+    /// monitor the growth of the cache. Managers that can't list their keys
+    /// return an [`Unsupported`] error.
+    pub async fn stats(&self) -> Result<CacheStats> {
+        self.manager.stats().await
+    }
+
+    /// Purges the cached responses of the url's origin when the values of the
+    /// `Clear-Site-Data` header of its response include the `"cache"` (or
+    /// `"*"`) type, if [`HttpCacheOptions::clear_site_data`] is enabled.
+    ///
+    /// Entries are matched by the url in their cache key, which a custom
+    /// [`CacheKey`] might not contain. Managers that can't delete entries by
+    /// key are cleared entirely instead, so a purge is never skipped.
+    pub async fn clear_site_data(
+        &self,
+        url: &Url,
+        header_values: &[&str],
+    ) -> Result<()> {
+        if !self.options.clear_site_data || self.options.read_only {
+            return Ok(());
+        }
+        let clear = header_values
+            .iter()
+            .flat_map(|value| value.split(','))
+            .any(|kind| matches!(kind.trim(), "\"cache\"" | "\"*\""));
+        if !clear {
+            return Ok(());
+        }
+        // The url follows the method in the key, and the origin is followed
+        // by the path, so `https://a.com` never matches `https://a.com.b`
+        let origin = format!(":{}/", url.origin().ascii_serialization());
+        match self
+            .manager
+            .delete_matching(&|cache_key: &str| cache_key.contains(&origin))
+            .await
+        {
+            Err(e) if e.is::<Unsupported>() => self.manager.clear().await,
+            result => result,
+        }
+    }
+
+    /// Removes the cached `GET` and `HEAD` responses for the url, and for the
+    /// `Location` and `Content-Location` urls of its response, when a request
+    /// with an unsafe method succeeds (2xx or 3xx), as
+    /// [RFC 9111](https://www.rfc-editor.org/rfc/rfc9111#section-4.4)
+    /// requires. Locations of another origin are left alone, so a response
+    /// can't purge the entries of a site the request didn't go to.
+    ///
+    /// The locations are resolved against the url, and their cache keys are
+    /// created like [`HttpCache::invalidate`] does.
+    pub async fn invalidate_unsafe(
+        &self,
+        method: &str,
+        url: &Url,
+        status: u16,
+        locations: &[&str],
+    ) -> Result<()> {
+        let safe = Method::from_str(method).map_or(false, |m| m.is_safe());
+        if safe || !(200..400).contains(&status) {
+            return Ok(());
+        }
+        let urls = locations
+            .iter()
+            .filter_map(|location| url.join(location).ok())
+            .filter(|location| location.origin() == url.origin());
+        for url in std::iter::once(url.clone()).chain(urls) {
+            for method in ["GET", "HEAD"] {
+                let cache_key = self.cache_key_for(method, &url)?;
+                // Only report the entries that were actually there
+                if self.manager.contains(&cache_key).await.unwrap_or(true) {
+                    self.delete(cache_key).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Invalidates the entries a successful unsafe request changed
+    async fn invalidate_unsafe_for(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<()> {
+        let locations = [LOCATION, CONTENT_LOCATION]
+            .iter()
+            .flat_map(|name| res.headers.get_all(name.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        self.invalidate_unsafe(
+            &middleware.method()?,
+            &middleware.url()?,
+            res.status,
+            &locations,
+        )
+        .await
+    }
+
+    // Purges the origin of the request if its response asks for it
+    async fn clear_site_data_for(
+        &self,
+        url: &Url,
+        res: &HttpResponse,
+    ) -> Result<()> {
+        let values = res.headers.get_all("clear-site-data").map(String::as_str);
+        self.clear_site_data(url, &values.collect::<Vec<_>>()).await
+    }
+
+    /// Checks if a response for the given method and url is cached
+    pub async fn contains(&self, method: &str, url: &Url) -> Result<bool> {
+        self.manager.contains(&self.cache_key_for(method, url)?).await
+    }
+
+    /// Returns whether the cached response for the given method and url is
+    /// currently fresh per its policy, or `None` if there is no such response.
+    /// The body of the response isn't loaded where the manager supports it.
+    pub async fn is_fresh(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<bool>> {
+        let cache_key = self.cache_key_for(method, url)?;
+        Ok(self
+            .manager
+            .get_meta(&cache_key)
+            .await?
+            .map(|(_, policy)| !policy.is_stale(self.now())))
+    }
+
+    /// Populates the cache with the responses to `GET` requests for the urls,
+    /// so it's warm before serving traffic. At most `concurrency` requests
+    /// are in flight at a time. The `detach` closure creates a middleware
+    /// that sends a request, or `None` when it can't, in which case the url
+    /// is skipped. Responses are stored following the usual rules, and the
+    /// first error stops priming.
+    pub async fn prime<M: Middleware>(
+        &self,
+        urls: impl IntoIterator<Item = Url>,
+        concurrency: usize,
+        detach: impl Fn(http::Request<Vec<u8>>) -> Option<M>,
+    ) -> Result<()> {
+        stream::iter(urls.into_iter().map(Ok))
+            .try_for_each_concurrent(concurrency.max(1), |url| {
+                let middleware = http::Request::builder()
+                    .method(Method::GET)
+                    .uri(url.as_str())
+                    .body(Vec::new())
+                    .map(&detach);
+                async move {
+                    match middleware? {
+                        Some(middleware) => {
+                            self.run(middleware).await.map(drop)
+                        }
+                        None => Ok(()),
+                    }
+                }
+            })
+            .await
+    }
+
+    // Creates the cache key of a request without headers
+    fn cache_key_for(&self, method: &str, url: &Url) -> Result<String> {
+        let parts = http::Request::builder()
+            .method(method)
+            .uri(url.as_str())
+            .body(())?
+            .into_parts()
+            .0;
+        Ok(self.options.create_cache_key(&parts, None))
+    }
+
+    /// Describes the cached response for the given method and url, including
+    /// how long it stays fresh for and its validators, without sending a
+    /// request. Returns `None` when nothing is cached.
+    pub async fn lookup(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<CacheEntry>> {
+        let cache_key = self.cache_key_for(method, url)?;
+        Ok(self.manager.get(&cache_key).await?.map(|(res, policy)| {
+            CacheEntry::new_at(cache_key, &res, &policy, self.now())
+        }))
+    }
+
+    /// Explains what the cache would do with the response to the request,
+    /// using the same rules as [`HttpCache::run`] without storing or
+    /// fetching anything.
+    pub fn explain(
+        &self,
+        parts: &request::Parts,
+        response: &HttpResponse,
+    ) -> Result<CacheDecision> {
+        let now = self.now();
+        let mode = self.mode_for(parts);
+        let oversized = self.is_oversized(response);
+        let rejected = self.is_rejected(response);
+        let refused = self.refuses_cookie(response);
+        let targeted = with_targeted_cache_control(
+            &self.options.targeted_cache_control,
+            response,
+        );
+        let response = targeted.as_ref().unwrap_or(response);
+        let stripped = without_no_cache_fields(response);
+        let response = stripped.as_ref().unwrap_or(response);
+        let new_policy = |response: &HttpResponse| -> Result<CachePolicy> {
+            let res_parts = response.parts()?;
+            Ok(match self.cache_options(parts) {
+                Some(options) => {
+                    CachePolicy::new_options(parts, &res_parts, now, options)
+                }
+                None => CachePolicy::new(parts, &res_parts),
+            })
+        };
+        let mut policy = new_policy(response)?;
+        let bounded = bounded_freshness(&self.options, response, &policy);
+        if let Some(bounded) = &bounded {
+            policy = new_policy(bounded)?;
+        }
+        let capped = capped_heuristic_freshness(
+            self.options.max_heuristic_freshness,
+            bounded.as_ref().unwrap_or(response),
+            &policy,
+            now,
+        );
+        if let Some(capped) = &capped {
+            policy = new_policy(capped)?;
+        }
+        let negative = self
+            .options
+            .negative_caching
+            .as_ref()
+            .and_then(|negative| negative.freshen(response));
+        if let Some(negative) = &negative {
+            policy = new_policy(negative)?;
+        }
+        let shared = self.is_shared(parts);
+        let req_cc = || {
+            parts
+                .headers
+                .get_all(CACHE_CONTROL)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+        };
+        let res_cc = || {
+            response.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str)
+        };
+        let is_get_head =
+            parts.method == Method::GET || parts.method == Method::HEAD;
+        let (store, reason) = if self.options.read_only {
+            (false, "read only cache".to_string())
+        } else if mode == CacheMode::NoStore {
+            (false, "NoStore mode".to_string())
+        } else if oversized {
+            (false, "max body size".to_string())
+        } else if rejected {
+            (false, "cacheable predicate".to_string())
+        } else if refused {
+            (false, "set-cookie".to_string())
+        } else if mode == CacheMode::IgnoreRules
+            && is_storable_status(response.status)
+        {
+            (true, "IgnoreRules mode".to_string())
+        } else if !is_get_head {
+            (false, format!("{} method", parts.method))
+        } else if negative.is_some() && policy.is_storable() {
+            (true, "negative caching".to_string())
+        } else if !is_storable_status(response.status) {
+            (false, format!("{} status", response.status))
+        } else if policy.is_storable() {
+            let reason = if bounded.is_some()
+                && content_type_rule(
+                    &self.options.content_type_freshness,
+                    response,
+                )
+                .is_some()
+            {
+                "content type freshness"
+            } else if bounded.is_some() {
+                "freshness bounds"
+            } else if has_directive(res_cc(), "no-cache") {
+                "no-cache"
+            } else if shared && has_directive(res_cc(), "s-maxage") {
+                "s-maxage"
+            } else if has_directive(res_cc(), "max-age") {
+                "max-age"
+            } else if response.headers.contains_key("expires") {
+                "expires"
+            } else if has_directive(res_cc(), "public") {
+                "public"
+            } else {
+                "heuristic freshness"
+            };
+            (true, reason.to_string())
+        } else {
+            let reason = if has_directive(req_cc(), "no-store") {
+                "request no-store"
+            } else if has_directive(res_cc(), "no-store") {
+                "no-store"
+            } else if shared && has_directive(res_cc(), "private") {
+                "private"
+            } else if shared && parts.headers.contains_key(AUTHORIZATION) {
+                "authorization"
+            } else {
+                "no explicit freshness"
+            };
+            (false, reason.to_string())
+        };
+        Ok(CacheDecision {
+            store,
+            reason,
+            time_to_live: policy.time_to_live(now),
+            needs_revalidation: policy.is_stale(now),
+        })
+    }
+
+    // The first of the rules that matches the request, if any
+    fn rule(&self, parts: &request::Parts) -> Option<&CacheRule> {
+        self.options.cache_rules.iter().find(|rule| rule.matches(parts))
+    }
+
+    // The mode of the rule matching the request takes precedence
+    fn mode_for(&self, parts: &request::Parts) -> CacheMode {
+        self.rule(parts).and_then(|rule| rule.mode).unwrap_or(self.mode)
+    }
+
+    fn middleware_mode(&self, middleware: &impl Middleware) -> CacheMode {
+        middleware.parts().map_or(self.mode, |parts| self.mode_for(&parts))
+    }
+
+    // The options of the manager take precedence over the configured ones,
+    // those of the rule matching the request over those of the cache
+    fn cache_options(&self, parts: &request::Parts) -> Option<CacheOptions> {
+        self.manager
+            .cache_options()
+            .or_else(|| self.rule(parts).and_then(|rule| rule.cache_options))
+            .or(self.options.cache_options)
+    }
+
+    // Creates the policy of an error response that is cached whatever the
+    // origin says, if it is
+    fn negative_policy(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> Result<Option<CachePolicy>> {
+        let freshened = match &self.options.negative_caching {
+            Some(negative) if middleware.is_method_get_head() => {
+                negative.freshen(res)
+            }
+            _ => None,
+        };
+        match freshened {
+            Some(freshened) => {
+                let policy = self.new_policy(middleware, parts, &freshened)?;
+                Ok(policy.is_storable().then_some(policy))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Checks if the predicate rejects storing the response
+    fn is_rejected(&self, res: &HttpResponse) -> bool {
+        self.options
+            .cacheable
+            .as_ref()
+            .map_or(false, |cacheable| !cacheable(res))
+    }
+
+    // Checks if the response sets cookies without its Cache-Control header
+    // explicitly allowing it to be stored
+    fn refuses_cookie(&self, res: &HttpResponse) -> bool {
+        let cache_control =
+            || res.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str);
+        self.options.refuse_set_cookie
+            && res.headers.contains_key("set-cookie")
+            && !has_directive(cache_control(), "public")
+            && !has_directive(cache_control(), "immutable")
+            && !res
+                .no_cache_fields()
+                .iter()
+                .any(|field| field.eq_ignore_ascii_case("set-cookie"))
+    }
+
+    // Checks if the body is too large to be stored
+    fn is_oversized(&self, res: &HttpResponse) -> bool {
+        self.options
+            .max_body_size
+            .map_or(false, |max| res.body.len() as u64 > max)
+    }
+
+    // The current time according to the clock
+    fn now(&self) -> SystemTime {
+        self.options.now()
+    }
+
+    // Caches are shared unless the cache options say otherwise
+    fn is_shared(&self, parts: &request::Parts) -> bool {
+        self.cache_options(parts).map_or(true, |o| o.shared)
+    }
+
+    // Checks if the stored response must not be served stale, `proxy-revalidate`
+    // being the shared cache analog of `must-revalidate`.
+    fn must_revalidate(
+        &self,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> bool {
+        res.must_revalidate()
+            || self.is_shared(parts)
+                && has_directive(
+                    res.headers
+                        .get_all(CACHE_CONTROL.as_str())
+                        .map(String::as_str),
+                    "proxy-revalidate",
+                )
+    }
+
     /// Determines if the request should be cached
     pub fn can_cache_request(&self, middleware: &impl Middleware) -> bool {
-        self.mode == CacheMode::IgnoreRules
-            || middleware.is_method_get_head()
-                && self.mode != CacheMode::NoStore
-                && self.mode != CacheMode::Reload
+        if self.options.offline {
+            // Every request has to be answered by the cache
+            return true;
+        }
+        let mode = self.middleware_mode(middleware);
+        if self.options.cache_request_body && !middleware.is_method_get_head() {
+            // Without the body the key can't tell the requests apart
+            return middleware.body().is_some()
+                && (mode == CacheMode::IgnoreRules
+                    || mode != CacheMode::NoStore
+                        && middleware.method().map_or(false, |m| m == "POST"));
+        }
+        mode == CacheMode::IgnoreRules
+            || middleware.is_method_get_head() && mode != CacheMode::NoStore
     }
 
     /// Runs the actions to preform when the client middleware is running without the cache
@@ -423,33 +3011,379 @@ impl<T: CacheManager> HttpCache<T> {
         &self,
         middleware: &mut impl Middleware,
     ) -> Result<()> {
-        self.manager
-            .delete(
-                &self
-                    .options
-                    .create_cache_key(&middleware.parts()?, Some("GET")),
-            )
-            .await
-            .ok();
+        // A safe request that isn't cached, as in the no store mode, fetches
+        // a response that won't be stored, so the stored one is removed
+        // rather than being served once the client has seen a newer one.
+        // Unsafe requests invalidate the cached responses once they succeed,
+        // see [`HttpCache::invalidate_unsafe`]
+        let method = middleware.method()?;
+        if Method::from_str(&method).map_or(false, |m| m.is_safe()) {
+            self.delete(self.cache_key(middleware, Some("GET"))?).await;
+        }
+        self.modify_upstream_request(middleware)
+    }
+
+    // Creates the cache key for the request, with a hash of the body when it
+    // is part of the key.
+    fn cache_key(
+        &self,
+        middleware: &impl Middleware,
+        override_method: Option<&str>,
+    ) -> Result<String> {
+        let parts = middleware.parts()?;
+        let cache_key = self.options.create_cache_key(&parts, override_method);
+        let method = override_method.unwrap_or_else(|| parts.method.as_str());
+        if !self.options.cache_request_body
+            || method == "GET"
+            || method == "HEAD"
+        {
+            return Ok(cache_key);
+        }
+        Ok(match middleware.body() {
+            Some(body) => format!("{}:{:x}", cache_key, Sha256::digest(body)),
+            None => cache_key,
+        })
+    }
+
+    // Applies the upstream request modifications, if any, to the request
+    fn modify_upstream_request(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<()> {
+        if let Some(modify) = &self.options.modify_upstream_request {
+            let mut parts = middleware.parts()?;
+            modify(&mut parts);
+            middleware.update_headers(&parts)?;
+        }
         Ok(())
     }
 
+    // Returns the request parts, with the uri normalized when the cache key is
+    // so the stored policy matches every request sharing the entry.
+    fn request_parts(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<request::Parts> {
+        let mut parts = middleware.parts()?;
+        if self.options.normalize_url {
+            parts.uri = normalize_uri(&parts.uri).parse()?;
+        }
+        Ok(parts)
+    }
+
+    // Creates the policy for the response to the request, the parts are taken
+    // before the request is modified on its way upstream.
+    fn policy(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        let targeted = with_targeted_cache_control(
+            &self.options.targeted_cache_control,
+            res,
+        );
+        let res = targeted.as_ref().unwrap_or(res);
+        let stripped = without_no_cache_fields(res);
+        let res = stripped.as_ref().unwrap_or(res);
+        let policy = self.new_policy(middleware, parts, res)?;
+        let bounded = bounded_freshness(&self.options, res, &policy);
+        let (res, policy) = match &bounded {
+            Some(bounded) => {
+                (bounded, self.new_policy(middleware, parts, bounded)?)
+            }
+            None => (res, policy),
+        };
+        match capped_heuristic_freshness(
+            self.options.max_heuristic_freshness,
+            res,
+            &policy,
+            self.now(),
+        ) {
+            Some(res) => self.new_policy(middleware, parts, &res),
+            None => Ok(policy),
+        }
+    }
+
+    fn new_policy(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        let options = self.cache_options(parts).unwrap_or_default();
+        if self.options.normalize_url
+            || self.options.modify_upstream_request.is_some()
+        {
+            return Ok(CachePolicy::new_options(
+                parts,
+                &res.parts()?,
+                self.now(),
+                options,
+            ));
+        }
+        middleware.policy_with_options_at(res, options, self.now())
+    }
+
+    // Stores the response unless the cache is read only. The policy of a
+    // transformed response is created again so both describe the same headers.
+    // When nothing was cached under the key, the response is only stored if
+    // that's still the case, so concurrent misses don't overwrite each other.
+    async fn put(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        cache_key: String,
+        mut res: HttpResponse,
+        mut policy: CachePolicy,
+        absent: bool,
+    ) -> Result<HttpResponse> {
+        if self.options.read_only {
+            return Ok(res);
+        }
+        if self.is_rejected(&res) || self.refuses_cookie(&res) {
+            if !absent {
+                self.delete(cache_key).await;
+            }
+            return Ok(res);
+        }
+        if self.options.on_store.is_some() {
+            transform(&self.options.on_store, &mut res);
+            policy = self.policy(middleware, parts, &res)?;
+        }
+        if self.is_oversized(&res) {
+            if !absent {
+                self.delete(cache_key.clone()).await;
+            }
+            self.emit(CacheEvent::Oversized(cache_key));
+            return Ok(res);
+        }
+        let (res, stored) =
+            self.store(cache_key.clone(), res, policy, absent).await?;
+        if stored {
+            self.emit(CacheEvent::Stored(cache_key));
+        }
+        Ok(res)
+    }
+
+    // Writes the response, already transformed for storing, to the manager
+    // and returns it as it would be loaded, along with whether it was written.
+    // A failed write is only reported when the cache fails open.
+    async fn store(
+        &self,
+        cache_key: String,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+        absent: bool,
+    ) -> Result<(HttpResponse, bool)> {
+        strip_hop_by_hop(&mut res);
+        // The manager takes the response, keep a copy to serve if it fails
+        let fallback = self.options.fail_open.then(|| res.clone());
+        let result = if absent {
+            self.manager.put_if_absent(cache_key.clone(), res, policy).await
+        } else {
+            self.manager
+                .put(cache_key.clone(), res, policy)
+                .await
+                .map(|res| (res, true))
+        };
+        let (mut res, stored) = match result {
+            Ok(stored) => stored,
+            Err(e) => match fallback {
+                Some(res) => {
+                    self.emit(CacheEvent::Failed(cache_key, e.to_string()));
+                    (res, false)
+                }
+                None => return Err(e),
+            },
+        };
+        transform(&self.options.on_load, &mut res);
+        Ok((res, stored))
+    }
+
+    // Reads the response from the manager, a failed read is treated as a miss
+    // when the cache fails open.
+    async fn load(
+        &self,
+        cache_key: &str,
+        meta: bool,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let store = if meta {
+            self.manager.get_meta(cache_key).await
+        } else {
+            self.manager.get(cache_key).await
+        };
+        match store {
+            Err(e) if self.options.fail_open => {
+                self.emit(CacheEvent::Failed(
+                    cache_key.to_string(),
+                    e.to_string(),
+                ));
+                Ok(None)
+            }
+            store => store,
+        }
+    }
+
+    // Removes the entry unless the cache is read only, errors are ignored
+    async fn delete(&self, cache_key: String) {
+        if self.options.read_only {
+            return;
+        }
+        if self.manager.delete(&cache_key).await.is_ok() {
+            self.emit(CacheEvent::Evicted(cache_key));
+        }
+    }
+
+    fn emit(&self, event: CacheEvent) {
+        if let Some(on_event) = &self.options.on_event {
+            on_event(event);
+        }
+    }
+
+    /// Returns the `Cache-Status` header value for a response to a request
+    /// the cache doesn't handle, which the client passes through to the
+    /// origin. `None` unless [`HttpCacheOptions::cache_status_header`] is set.
+    pub fn passthrough_cache_status(&self, status: u16) -> Option<String> {
+        self.options.cache_status_header.then(|| {
+            format!("{CACHE_STATUS_NAME}; fwd=miss; fwd-status={status}")
+        })
+    }
+
+    // Appends a member with the parameters to the `Cache-Status` header
+    fn append_cache_status(&self, res: &mut HttpResponse, params: &str) {
+        if self.options.cache_status_header {
+            res.headers.append(
+                CACHESTATUS.to_string(),
+                format!("{CACHE_STATUS_NAME}; {params}"),
+            );
+        }
+    }
+
+    // Describes how the response was served in the `Cache-Status` header,
+    // unless it was already described where that couldn't be told apart
+    fn describe_cache_status(&self, res: &mut HttpResponse) {
+        let described = res
+            .headers
+            .get_all(CACHESTATUS)
+            .any(|member| member.starts_with(CACHE_STATUS_NAME));
+        if described {
+            return;
+        }
+        let params = match (
+            res.headers.get(XCACHE).map(String::as_str),
+            res.headers.get(XCACHELOOKUP).map(String::as_str),
+            res.headers.get(XCACHESTALE),
+        ) {
+            (Some("HIT"), _, None) => "hit".to_string(),
+            (Some("HIT"), _, Some(stale)) => match stale.parse::<i64>() {
+                Ok(stale) => format!("hit; ttl={}", -stale),
+                Err(_) => "hit".to_string(),
+            },
+            (_, Some("HIT"), _) => {
+                format!("fwd=stale; fwd-status={}", res.status)
+            }
+            _ => format!("fwd=miss; fwd-status={}", res.status),
+        };
+        self.append_cache_status(res, &params);
+    }
+
     /// Attempts to run the passed middleware along with the cache
     pub async fn run(
+        &self,
+        middleware: impl Middleware,
+    ) -> Result<HttpResponse> {
+        let mut res = self.serve(middleware).await?;
+        if self.options.cache_status_header {
+            self.describe_cache_status(&mut res);
+        }
+        Ok(res)
+    }
+
+    // Answers the request from the cache or the origin as the mode and the
+    // stored response call for
+    async fn serve(
         &self,
         mut middleware: impl Middleware,
     ) -> Result<HttpResponse> {
+        if self.options.offline {
+            return self.run_offline(&middleware).await;
+        }
         let is_cacheable = self.can_cache_request(&middleware);
-        if !is_cacheable {
-            return self.remote_fetch(&mut middleware).await;
+        // A request that only accepts a stored response is answered like in
+        // the only if cached mode, whatever the mode of the cache
+        let mode = if is_cacheable && only_if_cached(&middleware.parts()?) {
+            CacheMode::OnlyIfCached
+        } else {
+            self.middleware_mode(&middleware)
+        };
+        if !is_cacheable || mode == CacheMode::Reload {
+            return self.remote_fetch(&mut middleware, false).await;
         }
-        if let Some(store) = self
-            .manager
-            .get(&self.options.create_cache_key(&middleware.parts()?, None))
-            .await?
-        {
+        let cache_key = self.cache_key(&middleware, None)?;
+        // The no cache mode always goes to the network, so the stored body is
+        // only loaded when it turns out to be needed.
+        let store = self.load(&cache_key, mode == CacheMode::NoCache).await?;
+        let absent = store.is_none();
+        // A response stored for other values of the request headers listed
+        // by its `Vary` header is a miss, and is replaced by the fetched one.
+        let parts = self.request_parts(&middleware)?;
+        let mut store = store
+            .filter(|(_, policy)| vary_matches(policy, &parts, self.now()));
+        // A HEAD request nothing is stored for is answered by the response
+        // stored for a GET, without its body
+        if store.is_none() && parts.method == Method::HEAD {
+            if let Some(res) =
+                self.head_from_get(&middleware, &parts, mode).await?
+            {
+                return Ok(res);
+            }
+        }
+        // Concurrent misses wait for the first one to fetch the response and
+        // look it up again, the first one holds the flight until it's stored
+        let _flight = match &self.options.coalescer {
+            Some(coalescer)
+                if store.is_none() && mode != CacheMode::OnlyIfCached =>
+            {
+                let flight = coalescer.join(&cache_key).await;
+                if flight.is_none() {
+                    store = self
+                        .load(&cache_key, mode == CacheMode::NoCache)
+                        .await?
+                        .filter(|(_, policy)| {
+                            vary_matches(policy, &parts, self.now())
+                        });
+                }
+                flight
+            }
+            _ => None,
+        };
+        if let Some(store) = store {
             let (mut res, policy) = store;
+            if mode != CacheMode::NoCache {
+                transform(&self.options.on_load, &mut res);
+            }
+            let marked = self.refresh_pending(&parts, &policy)?;
             res.cache_lookup_status(HitOrMiss::HIT);
+            if let Some(range) = range_request(&middleware)? {
+                if let Some(partial) =
+                    self.satisfy_range(&middleware, &res, &policy, &range)?
+                {
+                    self.emit(CacheEvent::Hit(cache_key));
+                    return Ok(partial);
+                }
+                if matches!(
+                    mode,
+                    CacheMode::Default
+                        | CacheMode::NoCache
+                        | CacheMode::RefreshStale
+                ) {
+                    // The stored response can't be used to satisfy the range,
+                    // fetch it without revalidating so a partial response
+                    // is never merged with the complete stored one.
+                    return self.remote_fetch(&mut middleware, false).await;
+                }
+            }
             if let Some(warning_code) = res.warning_code() {
                 // https://tools.ietf.org/html/rfc7234#section-4.3.4
                 //
@@ -466,13 +3400,60 @@ impl<T: CacheManager> HttpCache<T> {
                 }
             }
 
-            match self.mode {
+            if matches!(mode, CacheMode::Default | CacheMode::NoCache) {
+                if let Some(parts) =
+                    self.immutable_fresh(&middleware, &res, &policy)?
+                {
+                    let body = match mode {
+                        CacheMode::NoCache => self
+                            .manager
+                            .get(&cache_key)
+                            .await?
+                            .map(|(stored, _)| stored.body),
+                        _ => Some(std::mem::take(&mut res.body)),
+                    };
+                    if let Some(body) = body {
+                        res.body = body;
+                        if mode == CacheMode::NoCache {
+                            transform(&self.options.on_load, &mut res);
+                        }
+                        update_cached_headers(
+                            &mut res,
+                            &parts,
+                            &self.options.targeted_cache_control,
+                        )?;
+                        res.remove_no_cache_fields();
+                        res.cache_status(HitOrMiss::HIT);
+                        self.emit(CacheEvent::Hit(cache_key));
+                        return Ok(res);
+                    }
+                }
+            }
+
+            match mode {
+                CacheMode::Default
+                    if self.revalidates_in_background(
+                        &middleware,
+                        &parts,
+                        &res,
+                        &policy,
+                    ) =>
+                {
+                    self.serve_revalidating(&middleware, res, policy, cache_key)
+                }
                 CacheMode::Default => {
                     self.conditional_fetch(middleware, res, policy).await
                 }
+                CacheMode::RefreshStale if !marked => {
+                    self.serve_stale(middleware, res, policy, cache_key).await
+                }
+                CacheMode::RefreshStale => {
+                    self.conditional_fetch(middleware, res, policy).await
+                }
                 CacheMode::NoCache => {
                     middleware.force_no_cache()?;
-                    let mut res = self.remote_fetch(&mut middleware).await?;
+                    let mut res =
+                        self.remote_fetch(&mut middleware, false).await?;
                     res.cache_lookup_status(HitOrMiss::HIT);
                     Ok(res)
                 }
@@ -483,75 +3464,436 @@ impl<T: CacheManager> HttpCache<T> {
                     // SHOULD be included if the cache is intentionally disconnected from
                     // the rest of the network for a period of time.
                     // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    res.add_warning(
+                    res.add_warning_at(
                         &res.url.clone(),
                         112,
                         "Disconnected operation",
+                        self.now(),
                     );
+                    res.remove_no_cache_fields();
                     res.cache_status(HitOrMiss::HIT);
+                    res.cache_stale_status_at(&policy, self.now());
+                    res.update_age_at(&policy, self.now());
+                    self.emit(CacheEvent::Hit(cache_key));
                     Ok(res)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                _ => self.remote_fetch(&mut middleware, false).await,
             }
         } else {
-            match self.mode {
+            self.emit(CacheEvent::Miss(cache_key));
+            match mode {
                 CacheMode::OnlyIfCached => {
                     // ENOTCACHED
                     let mut res = HttpResponse {
                         body: b"GatewayTimeout".to_vec(),
-                        headers: HashMap::default(),
+                        headers: HttpHeaders::default(),
                         status: 504,
                         url: middleware.url()?,
                         version: HttpVersion::Http11,
                     };
                     res.cache_status(HitOrMiss::MISS);
                     res.cache_lookup_status(HitOrMiss::MISS);
+                    // Nothing was forwarded to the origin
+                    self.append_cache_status(&mut res, "fwd=miss");
                     Ok(res)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                _ => self.remote_fetch(&mut middleware, absent).await,
+            }
+        }
+    }
+
+    // Serves the cached response regardless of its freshness, or fails with
+    // an `Offline` error when there is none.
+    async fn run_offline(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<HttpResponse> {
+        let cache_key = self.cache_key(middleware, None)?;
+        let parts = self.request_parts(middleware)?;
+        let store = self.load(&cache_key, false).await?;
+        let (mut res, policy) = match store
+            .filter(|(_, policy)| vary_matches(policy, &parts, self.now()))
+        {
+            Some(store) => store,
+            None => {
+                self.emit(CacheEvent::Miss(cache_key));
+                return Err(Box::new(Offline));
+            }
+        };
+        transform(&self.options.on_load, &mut res);
+        //   112 Disconnected operation
+        // SHOULD be included if the cache is intentionally disconnected from
+        // the rest of the network for a period of time.
+        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+        res.add_warning_at(
+            &res.url.clone(),
+            112,
+            "Disconnected operation",
+            self.now(),
+        );
+        res.remove_no_cache_fields();
+        res.cache_status(HitOrMiss::HIT);
+        res.cache_lookup_status(HitOrMiss::HIT);
+        res.cache_stale_status_at(&policy, self.now());
+        res.update_age_at(&policy, self.now());
+        self.emit(CacheEvent::Hit(cache_key));
+        Ok(res)
+    }
+
+    // A fresh `immutable` response is never revalidated, even when the request
+    // or the cache mode asks for `no-cache`. Returns the headers to serve the
+    // stored response with when that is the case.
+    fn immutable_fresh(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> Result<Option<response::Parts>> {
+        if !res.is_immutable() {
+            return Ok(None);
+        }
+        let mut parts = self.request_parts(middleware)?;
+        parts.headers.remove(PRAGMA);
+        if let Some(cache_control) = parts.headers.remove(CACHE_CONTROL) {
+            let directives = cache_control
+                .to_str()?
+                .split(',')
+                .map(str::trim)
+                .filter(|directive| !directive.eq_ignore_ascii_case("no-cache"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !directives.is_empty() {
+                parts.headers.insert(CACHE_CONTROL, directives.parse()?);
+            }
+        }
+        match policy.before_request(&parts, self.now()) {
+            BeforeRequest::Fresh(res_parts) => Ok(Some(res_parts)),
+            BeforeRequest::Stale { .. } => Ok(None),
+        }
+    }
+
+    // Attempts to answer a HEAD request with the headers of the response
+    // stored for a GET to the same url, when it can be used as is.
+    async fn head_from_get(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        mode: CacheMode,
+    ) -> Result<Option<HttpResponse>> {
+        let cache_key = self.cache_key(middleware, Some("GET"))?;
+        let Some((mut res, policy)) = self.load(&cache_key, true).await? else {
+            return Ok(None);
+        };
+        if !vary_matches(&policy, parts, self.now()) {
+            return Ok(None);
+        }
+        transform(&self.options.on_load, &mut res);
+        match mode {
+            CacheMode::Default | CacheMode::RefreshStale => {
+                let (mut get_parts, _) = http::Request::builder()
+                    .method(Method::GET)
+                    .uri(parts.uri.clone())
+                    .version(parts.version)
+                    .body(())?
+                    .into_parts();
+                get_parts.headers = parts.headers.clone();
+                match policy.before_request(&get_parts, self.now()) {
+                    BeforeRequest::Fresh(res_parts) => {
+                        update_cached_headers(
+                            &mut res,
+                            &res_parts,
+                            &self.options.targeted_cache_control,
+                        )?;
+                    }
+                    BeforeRequest::Stale { .. } => return Ok(None),
+                }
+            }
+            CacheMode::ForceCache
+            | CacheMode::OnlyIfCached
+            | CacheMode::IgnoreRules => res.update_age_at(&policy, self.now()),
+            _ => return Ok(None),
+        }
+        res.body = Vec::new();
+        res.remove_no_cache_fields();
+        res.cache_status(HitOrMiss::HIT);
+        res.cache_lookup_status(HitOrMiss::HIT);
+        self.emit(CacheEvent::Hit(cache_key));
+        Ok(Some(res))
+    }
+
+    // Attempts to serve a `Range` request from a complete stored response
+    // without going to the network.
+    fn satisfy_range(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+        range: &str,
+    ) -> Result<Option<HttpResponse>> {
+        let parts = self.request_parts(middleware)?;
+        // An `If-Range` makes the range conditional on the validators of the
+        // stored response, leave that decision to the origin.
+        if parts.headers.contains_key(IF_RANGE) {
+            return Ok(None);
+        }
+        let mut res = res.clone();
+        match self.mode_for(&parts) {
+            CacheMode::Default | CacheMode::RefreshStale => {
+                match policy.before_request(&parts, self.now()) {
+                    BeforeRequest::Fresh(res_parts) => {
+                        update_cached_headers(
+                            &mut res,
+                            &res_parts,
+                            &self.options.targeted_cache_control,
+                        )?;
+                    }
+                    BeforeRequest::Stale { .. } => return Ok(None),
+                }
             }
+            CacheMode::ForceCache
+            | CacheMode::OnlyIfCached
+            | CacheMode::IgnoreRules => res.update_age_at(policy, self.now()),
+            _ => return Ok(None),
+        }
+        let mut partial = match res.partial_content(range) {
+            Some(partial) => partial,
+            None => return Ok(None),
+        };
+        partial.remove_no_cache_fields();
+        partial.cache_status(HitOrMiss::HIT);
+        partial.cache_lookup_status(HitOrMiss::HIT);
+        Ok(Some(partial))
+    }
+
+    // Whether a stale response has to be revalidated before it's served, as
+    // the request or the response asks for it
+    fn must_revalidate_stale(
+        &self,
+        parts: &request::Parts,
+        res: &HttpResponse,
+    ) -> bool {
+        let req_cc = parts
+            .headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok());
+        let no_cache_request = has_directive(req_cc, "no-cache")
+            || parts
+                .headers
+                .get(PRAGMA)
+                .and_then(|v| v.to_str().ok())
+                .map_or(false, |v| v.contains("no-cache"));
+        let no_cache_response = res
+            .headers
+            .get_all(CACHE_CONTROL.as_str())
+            .flat_map(|v| split_directives(v))
+            .any(|d| d.eq_ignore_ascii_case("no-cache"));
+        no_cache_request
+            || no_cache_response
+            || self.must_revalidate(parts, res)
+    }
+
+    // Creates the policy of the response again as it's been in the cache for
+    // as long as the given policy says, so it's neither fresher nor staler
+    fn rebuild_policy(
+        &self,
+        parts: &request::Parts,
+        res: &response::Parts,
+        policy: &CachePolicy,
+    ) -> CachePolicy {
+        let options = self.cache_options(parts).unwrap_or_default();
+        let now = self.now();
+        let resident = policy.age(now).saturating_sub(
+            CachePolicy::new_options(parts, res, now, options).age(now),
+        );
+        let received = now.checked_sub(resident).unwrap_or(now);
+        CachePolicy::new_options(parts, res, received, options)
+    }
+
+    // Whether the entry was served stale in the refresh stale mode, its policy
+    // then requires revalidation even when a request accepts a stale response
+    fn refresh_pending(
+        &self,
+        parts: &request::Parts,
+        policy: &CachePolicy,
+    ) -> Result<bool> {
+        let (mut probe, ()) = http::Request::builder()
+            .method(parts.method.clone())
+            .uri(parts.uri.clone())
+            .body(())?
+            .into_parts();
+        probe.headers = parts.headers.clone();
+        probe.headers.remove(PRAGMA);
+        probe
+            .headers
+            .insert(CACHE_CONTROL, http::HeaderValue::from_static("max-stale"));
+        Ok(matches!(
+            policy.before_request(&probe, self.now()),
+            BeforeRequest::Stale { matches: true, .. }
+        ))
+    }
+
+    // Whether the response has been stale for longer than the `stale-if-error`
+    // window of its Cache-Control header, or the configured one when it has
+    // none, so a failed revalidation is no longer answered with it
+    fn past_stale_if_error(
+        &self,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> bool {
+        let window = directive_seconds(
+            res.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str),
+            "stale-if-error",
+        )
+        .map(Duration::from_secs)
+        .or(self.options.stale_if_error);
+        match (window, staleness(policy, self.now())) {
+            (Some(window), Some(stale)) => stale > window,
+            _ => false,
+        }
+    }
+
+    // Whether the response is stale but within the `stale-while-revalidate`
+    // window of its Cache-Control header, so it can be served while it's
+    // revalidated in the background
+    fn revalidates_in_background(
+        &self,
+        middleware: &impl Middleware,
+        parts: &request::Parts,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> bool {
+        if self.options.revalidate.is_none()
+            || self.options.read_only
+            || self.must_revalidate_stale(parts, res)
+            || middleware.body().is_none() && !middleware.is_method_get_head()
+        {
+            return false;
+        }
+        let window = directive_seconds(
+            res.headers.get_all(CACHE_CONTROL.as_str()).map(String::as_str),
+            "stale-while-revalidate",
+        );
+        match (window, staleness(policy, self.now())) {
+            (Some(window), Some(stale)) => stale <= Duration::from_secs(window),
+            _ => false,
+        }
+    }
+
+    // Serves a stale response right away and hands the request over to be
+    // revalidated in the background
+    fn serve_revalidating(
+        &self,
+        middleware: &impl Middleware,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+        cache_key: String,
+    ) -> Result<HttpResponse> {
+        if let Some(revalidate) = &self.options.revalidate {
+            let body = middleware.body().unwrap_or_default().to_vec();
+            let req = http::Request::from_parts(middleware.parts()?, body);
+            revalidate(cache_key.clone(), req);
+        }
+        //   110 Response is stale
+        //   MUST be included whenever the returned response is stale.
+        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+        res.add_warning_at(
+            &res.url.clone(),
+            110,
+            "Response is stale",
+            self.now(),
+        );
+        res.remove_no_cache_fields();
+        res.cache_status(HitOrMiss::HIT);
+        res.cache_stale_status_at(&policy, self.now());
+        res.update_age_at(&policy, self.now());
+        self.emit(CacheEvent::Hit(cache_key));
+        Ok(res)
+    }
+
+    // Serves a stale response without revalidating it and marks the entry, so
+    // the next request for it is revalidated. Anything else, including fresh
+    // responses, goes through the usual conditional fetch.
+    async fn serve_stale(
+        &self,
+        middleware: impl Middleware,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+        cache_key: String,
+    ) -> Result<HttpResponse> {
+        let parts = self.request_parts(&middleware)?;
+        let stale = matches!(
+            policy.before_request(&parts, self.now()),
+            BeforeRequest::Stale { matches: true, .. }
+        );
+        if !stale
+            || self.options.read_only
+            || self.must_revalidate_stale(&parts, &res)
+        {
+            return self.conditional_fetch(middleware, res, policy).await;
         }
+        let mut marked = res.parts()?;
+        marked.headers.append(
+            CACHE_CONTROL,
+            http::HeaderValue::from_static(REFRESH_DIRECTIVE),
+        );
+        let marked = self.rebuild_policy(&parts, &marked, &policy);
+        transform(&self.options.on_store, &mut res);
+        let (mut res, _) =
+            self.store(cache_key.clone(), res, marked, false).await?;
+        //   110 Response is stale
+        //   MUST be included whenever the returned response is stale.
+        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+        res.add_warning_at(
+            &res.url.clone(),
+            110,
+            "Response is stale",
+            self.now(),
+        );
+        res.remove_no_cache_fields();
+        res.cache_status(HitOrMiss::HIT);
+        res.cache_stale_status_at(&policy, self.now());
+        res.update_age_at(&policy, self.now());
+        self.emit(CacheEvent::Hit(cache_key));
+        Ok(res)
     }
 
+    // Fetches the response and stores it if it's cacheable, `absent` when the
+    // lookup found nothing cached under the key
     async fn remote_fetch(
         &self,
         middleware: &mut impl Middleware,
+        absent: bool,
     ) -> Result<HttpResponse> {
+        let parts = self.request_parts(middleware)?;
+        let cache_key = self.cache_key(middleware, None)?;
+        self.modify_upstream_request(middleware)?;
         let mut res = middleware.remote_fetch().await?;
+        self.clear_site_data_for(&middleware.url()?, &res).await?;
+        self.invalidate_unsafe_for(middleware, &res).await?;
         res.cache_status(HitOrMiss::MISS);
         res.cache_lookup_status(HitOrMiss::MISS);
-        let policy = match self.options.cache_options {
-            Some(options) => middleware.policy_with_options(&res, options)?,
-            None => middleware.policy(&res)?,
-        };
+        let policy = self.policy(middleware, &parts, &res)?;
         let is_get_head = middleware.is_method_get_head();
-        let mut is_cacheable = is_get_head
-            && self.mode != CacheMode::NoStore
-            && self.mode != CacheMode::Reload
-            && res.status == 200
+        let mode = self.mode_for(&parts);
+        let mut is_cacheable = (is_get_head || self.options.cache_request_body)
+            && mode != CacheMode::NoStore
+            && is_storable_status(res.status)
             && policy.is_storable();
-        if self.mode == CacheMode::IgnoreRules && res.status == 200 {
+        if mode == CacheMode::IgnoreRules && is_storable_status(res.status) {
             is_cacheable = true;
         }
+        if is_get_head && mode != CacheMode::NoStore {
+            if let Some(policy) =
+                self.negative_policy(middleware, &parts, &res)?
+            {
+                return self
+                    .put(middleware, &parts, cache_key, res, policy, absent)
+                    .await;
+            }
+        }
         if is_cacheable {
-            Ok(self
-                .manager
-                .put(
-                    self.options.create_cache_key(&middleware.parts()?, None),
-                    res,
-                    policy,
-                )
-                .await?)
-        } else if !is_get_head {
-            self.manager
-                .delete(
-                    &self
-                        .options
-                        .create_cache_key(&middleware.parts()?, Some("GET")),
-                )
-                .await
-                .ok();
-            Ok(res)
+            self.put(middleware, &parts, cache_key, res, policy, absent).await
         } else {
             Ok(res)
         }
@@ -563,13 +3905,20 @@ impl<T: CacheManager> HttpCache<T> {
         mut cached_res: HttpResponse,
         mut policy: CachePolicy,
     ) -> Result<HttpResponse> {
-        let before_req =
-            policy.before_request(&middleware.parts()?, SystemTime::now());
+        let cache_key = self.cache_key(&middleware, None)?;
+        let parts = self.request_parts(&middleware)?;
+        let before_req = policy.before_request(&parts, self.now());
         match before_req {
             BeforeRequest::Fresh(parts) => {
-                cached_res.update_headers(&parts)?;
+                update_cached_headers(
+                    &mut cached_res,
+                    &parts,
+                    &self.options.targeted_cache_control,
+                )?;
+                cached_res.remove_no_cache_fields();
                 cached_res.cache_status(HitOrMiss::HIT);
                 cached_res.cache_lookup_status(HitOrMiss::HIT);
+                self.emit(CacheEvent::Hit(cache_key));
                 return Ok(cached_res);
             }
             BeforeRequest::Stale { request: parts, matches } => {
@@ -579,72 +3928,141 @@ impl<T: CacheManager> HttpCache<T> {
             }
         }
         let req_url = middleware.url()?;
+        self.modify_upstream_request(&mut middleware)?;
         match middleware.remote_fetch().await {
             Ok(mut cond_res) => {
+                self.clear_site_data_for(&req_url, &cond_res).await?;
                 let status = StatusCode::from_u16(cond_res.status)?;
-                if status.is_server_error() && cached_res.must_revalidate() {
+                if status.is_server_error()
+                    && (self.must_revalidate(&parts, &cached_res)
+                        || self.past_stale_if_error(&cached_res, &policy))
+                {
+                    // Must not be served stale, or too stale to stand in for
+                    // the error
+                    cond_res.cache_status(HitOrMiss::MISS);
+                    cond_res.cache_lookup_status(HitOrMiss::HIT);
+                    Ok(cond_res)
+                } else if status.is_server_error() {
                     //   111 Revalidation failed
                     //   MUST be included if a cache returns a stale response
                     //   because an attempt to revalidate the response failed,
                     //   due to an inability to reach the server.
                     // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
+                    cached_res.add_warning_at(
                         &req_url,
                         111,
                         "Revalidation failed",
+                        self.now(),
                     );
+                    cached_res.remove_no_cache_fields();
                     cached_res.cache_status(HitOrMiss::HIT);
+                    cached_res.cache_stale_status_at(&policy, self.now());
+                    cached_res.update_age_at(&policy, self.now());
+                    self.emit(CacheEvent::Hit(cache_key));
                     Ok(cached_res)
                 } else if cond_res.status == 304 {
+                    let targeted = with_targeted_cache_control(
+                        &self.options.targeted_cache_control,
+                        &cond_res,
+                    );
+                    let stripped = without_no_cache_fields(
+                        targeted.as_ref().unwrap_or(&cond_res),
+                    );
+                    if self.refresh_pending(&parts, &policy)? {
+                        // Drop the mark, the 304 only updates what was stored
+                        policy = self.rebuild_policy(
+                            &parts,
+                            &cached_res.parts()?,
+                            &policy,
+                        );
+                    }
                     let after_res = policy.after_response(
-                        &middleware.parts()?,
-                        &cond_res.parts()?,
-                        SystemTime::now(),
+                        &parts,
+                        &stripped
+                            .as_ref()
+                            .or(targeted.as_ref())
+                            .unwrap_or(&cond_res)
+                            .parts()?,
+                        self.now(),
                     );
+                    if cond_res.headers.contains_key(CACHE_CONTROL.as_str()) {
+                        cached_res.headers.0.insert(
+                            CACHE_CONTROL.as_str().to_string(),
+                            cond_res
+                                .headers
+                                .get_all(CACHE_CONTROL.as_str())
+                                .cloned()
+                                .collect(),
+                        );
+                    }
                     match after_res {
                         AfterResponse::Modified(new_policy, parts)
                         | AfterResponse::NotModified(new_policy, parts) => {
                             policy = new_policy;
-                            cached_res.update_headers(&parts)?;
+                            update_cached_headers(
+                                &mut cached_res,
+                                &parts,
+                                &self.options.targeted_cache_control,
+                            )?;
                         }
                     }
                     cached_res.cache_status(HitOrMiss::HIT);
                     cached_res.cache_lookup_status(HitOrMiss::HIT);
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
-                            cached_res,
-                            policy,
-                        )
-                        .await?;
-                    Ok(res)
-                } else if cond_res.status == 200 {
-                    let policy = match self.options.cache_options {
-                        Some(options) => middleware
-                            .policy_with_options(&cond_res, options)?,
-                        None => middleware.policy(&cond_res)?,
+                    let mut res = if self.options.read_only {
+                        cached_res
+                    } else {
+                        transform(&self.options.on_store, &mut cached_res);
+                        self.store(cache_key.clone(), cached_res, policy, false)
+                            .await?
+                            .0
                     };
+                    // Served from the cache, but only once revalidated
+                    self.append_cache_status(
+                        &mut res,
+                        "fwd=stale; fwd-status=304",
+                    );
+                    self.emit(CacheEvent::Revalidated(cache_key));
+                    Ok(res)
+                } else if is_storable_status(cond_res.status) {
+                    let policy = self.policy(&middleware, &parts, &cond_res)?;
                     cond_res.cache_status(HitOrMiss::MISS);
                     cond_res.cache_lookup_status(HitOrMiss::HIT);
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
-                            cond_res,
-                            policy,
-                        )
-                        .await?;
-                    Ok(res)
+                    self.put(
+                        &middleware,
+                        &parts,
+                        cache_key,
+                        cond_res,
+                        policy,
+                        false,
+                    )
+                    .await
+                } else if let Some(policy) =
+                    self.negative_policy(&middleware, &parts, &cond_res)?
+                {
+                    cond_res.cache_status(HitOrMiss::MISS);
+                    cond_res.cache_lookup_status(HitOrMiss::HIT);
+                    self.put(
+                        &middleware,
+                        &parts,
+                        cache_key,
+                        cond_res,
+                        policy,
+                        false,
+                    )
+                    .await
                 } else {
+                    cached_res.remove_no_cache_fields();
                     cached_res.cache_status(HitOrMiss::HIT);
+                    cached_res.cache_stale_status_at(&policy, self.now());
+                    cached_res.update_age_at(&policy, self.now());
+                    self.emit(CacheEvent::Hit(cache_key));
                     Ok(cached_res)
                 }
             }
             Err(e) => {
-                if cached_res.must_revalidate() {
+                if self.must_revalidate(&parts, &cached_res)
+                    || self.past_stale_if_error(&cached_res, &policy)
+                {
                     Err(e)
                 } else {
                     //   111 Revalidation failed
@@ -652,12 +4070,17 @@ impl<T: CacheManager> HttpCache<T> {
                     //   because an attempt to revalidate the response failed,
                     //   due to an inability to reach the server.
                     // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
+                    cached_res.add_warning_at(
                         &req_url,
                         111,
                         "Revalidation failed",
+                        self.now(),
                     );
+                    cached_res.remove_no_cache_fields();
                     cached_res.cache_status(HitOrMiss::HIT);
+                    cached_res.cache_stale_status_at(&policy, self.now());
+                    cached_res.update_age_at(&policy, self.now());
+                    self.emit(CacheEvent::Hit(cache_key));
                     Ok(cached_res)
                 }
             }
@@ -665,5 +4088,51 @@ impl<T: CacheManager> HttpCache<T> {
     }
 }
 
+impl<T: CacheManager + Clone> HttpCache<T> {
+    /// Revalidates stale responses within the `stale-while-revalidate` window
+    /// of their Cache-Control header in the background, so they're served
+    /// right away rather than once revalidated. This applies to the default
+    /// mode only. The `detach` closure creates a middleware that sends the
+    /// request once the original one has been answered, or `None` when it
+    /// can't, and `spawn` runs the revalidation. A single revalidation per
+    /// cache key is in flight at a time, other requests for it are served the
+    /// stale response meanwhile.
+    pub fn revalidate_in_background<M: Middleware + Sync + 'static>(
+        mut self,
+        spawn: Spawn,
+        detach: impl Fn(http::Request<Vec<u8>>) -> Option<M> + Send + Sync + 'static,
+    ) -> Self {
+        // The revalidation asks for a fresh response, which is stored as usual
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: self.manager.clone(),
+            options: HttpCacheOptions {
+                revalidate: None,
+                ..self.options.clone()
+            },
+        };
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        self.options.revalidate = Some(Arc::new(move |cache_key, req| {
+            let mut middleware = match detach(req) {
+                Some(middleware) => middleware,
+                None => return,
+            };
+            if !pending.lock().unwrap().insert(cache_key.clone()) {
+                return;
+            }
+            let cache = cache.clone();
+            let pending = pending.clone();
+            spawn(Box::pin(async move {
+                if middleware.force_no_cache().is_ok() {
+                    // A failed revalidation leaves the stale response in place
+                    cache.run(middleware).await.ok();
+                }
+                pending.lock().unwrap().remove(&cache_key);
+            }));
+        }));
+        self
+    }
+}
+
 #[cfg(test)]
 mod test;
@@ -1,11 +1,32 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{
+    expired, BodyStream, CacheManager, CacheStats, HttpResponse, PruneReport,
+    Result,
+};
 
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
 use http_cache_semantics::CachePolicy;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "cacache-async-std")]
+use futures_util::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "cacache-tokio")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// How much of a streamed body is read at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
 /// Implements [`CacheManager`] with [`cacache`](https://github.com/zkat/cacache-rs) as the backend.
+///
+/// Responses stored with `put_stream` keep their body as the content of the
+/// entry, and the rest of the response and its policy in the entry's
+/// metadata, so the body can be written and read in parts.
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
 #[derive(Debug, Clone)]
 pub struct CACacheManager {
@@ -32,6 +53,53 @@ impl CACacheManager {
         cacache::clear(&self.path).await?;
         Ok(())
     }
+
+    /// Rewrites the records stored in an older layout in the current one,
+    /// returning how many were rewritten. Older records are still read
+    /// without being migrated, this only saves converting them on each read.
+    pub async fn migrate(&self) -> Result<usize> {
+        // Listed before any is rewritten, as that adds to the index
+        let entries = cacache::list_sync(&self.path)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut migrated = 0;
+        for metadata in entries {
+            match metadata.raw_metadata {
+                Some(raw) => {
+                    if super::meta::is_current(&raw) {
+                        continue;
+                    }
+                    let (response, policy) = super::meta::deserialize(&raw)?;
+                    let data = Store { response, policy };
+                    let opts = cacache::WriteOpts::new()
+                        .integrity(metadata.integrity)
+                        .size(metadata.size)
+                        .raw_metadata(bincode::serialize(&data)?);
+                    cacache::index::insert_async(
+                        &self.path,
+                        &metadata.key,
+                        opts,
+                    )
+                    .await?;
+                }
+                None => {
+                    let Ok(data) =
+                        cacache::read_hash(&self.path, &metadata.integrity)
+                            .await
+                    else {
+                        continue;
+                    };
+                    if super::meta::is_current(&data) {
+                        continue;
+                    }
+                    let (response, policy) =
+                        super::meta::deserialize_store(&data)?;
+                    self.put(metadata.key, response, policy).await?;
+                }
+            }
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
 }
 
 #[async_trait::async_trait]
@@ -40,13 +108,86 @@ impl CacheManager for CACacheManager {
         &self,
         cache_key: &str,
     ) -> Result<Option<(HttpResponse, CachePolicy)>> {
-        let store: Store = match cacache::read(&self.path, cache_key).await {
-            Ok(d) => bincode::deserialize(&d)?,
-            Err(_e) => {
-                return Ok(None);
+        let Some(metadata) = cacache::metadata(&self.path, cache_key).await?
+        else {
+            return Ok(None);
+        };
+        let data =
+            match cacache::read_hash(&self.path, &metadata.integrity).await {
+                Ok(d) => d,
+                Err(_e) => {
+                    return Ok(None);
+                }
+            };
+        match metadata.raw_metadata {
+            Some(raw) => {
+                let (mut response, policy) = super::meta::deserialize(&raw)?;
+                response.body = data;
+                Ok(Some((response, policy)))
             }
+            None => Ok(Some(super::meta::deserialize_store(&data)?)),
+        }
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some(metadata) = cacache::metadata(&self.path, cache_key).await?
+        else {
+            return Ok(None);
         };
-        Ok(Some((store.response, store.policy)))
+        if let Some(raw) = metadata.raw_metadata {
+            return Ok(Some(super::meta::deserialize(&raw)?));
+        }
+        match cacache::read_hash(&self.path, &metadata.integrity).await {
+            Ok(d) => Ok(Some(super::meta::deserialize(&d)?)),
+            Err(_e) => Ok(None),
+        }
+    }
+
+    async fn get_stream(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, BodyStream)>> {
+        let Some(metadata) = cacache::metadata(&self.path, cache_key).await?
+        else {
+            return Ok(None);
+        };
+        // Stored whole, the body can't be read apart from the rest
+        let Some(raw) = metadata.raw_metadata else {
+            return Ok(self.get(cache_key).await?.map(|(mut res, policy)| {
+                let body = Bytes::from(std::mem::take(&mut res.body));
+                let body: BodyStream =
+                    Box::pin(stream::once(async { Ok(body) }));
+                (res, policy, body)
+            }));
+        };
+        let (response, policy) = super::meta::deserialize(&raw)?;
+        let reader =
+            match cacache::Reader::open_hash(&self.path, metadata.integrity)
+                .await
+            {
+                Ok(reader) => reader,
+                Err(_e) => return Ok(None),
+            };
+        // The integrity of the content is checked once it's all read
+        let body = stream::unfold(Some(reader), |reader| async move {
+            let mut reader = reader?;
+            let mut chunk = vec![0; CHUNK_SIZE];
+            match reader.read(&mut chunk).await {
+                Ok(0) => match reader.check() {
+                    Ok(_) => None,
+                    Err(e) => Some((Err(e.into()), None)),
+                },
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some((Ok(Bytes::from(chunk)), Some(reader)))
+                }
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        });
+        Ok(Some((response, policy, Box::pin(body))))
     }
 
     async fn put(
@@ -61,7 +202,114 @@ impl CacheManager for CACacheManager {
         Ok(response)
     }
 
+    async fn put_stream(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+        mut body: BodyStream,
+    ) -> Result<()> {
+        response.body.clear();
+        let data = Store { response, policy };
+        // The content is written before it's indexed, so the entry can be
+        // indexed with its size, which isn't known until the stream ends
+        let mut writer =
+            cacache::WriteOpts::new().open_hash(&self.path).await?;
+        let mut size = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            size += chunk.len();
+        }
+        let integrity = writer.commit().await?;
+        let opts = cacache::WriteOpts::new()
+            .integrity(integrity)
+            .size(size)
+            .raw_metadata(bincode::serialize(&data)?);
+        cacache::index::insert_async(&self.path, &cache_key, opts).await?;
+        Ok(())
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(cacache::metadata(&self.path, cache_key).await?.is_some())
+    }
+
     async fn delete(&self, cache_key: &str) -> Result<()> {
         Ok(cacache::remove(&self.path, cache_key).await?)
     }
+
+    async fn clear(&self) -> Result<()> {
+        CACacheManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        for cache_key in self.keys().await? {
+            if predicate(&cache_key) {
+                cacache::remove(&self.path, &cache_key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Removing an entry only removes it from the index, so the content of
+    // the pruned entries is removed too, unless another entry still uses it
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let now = SystemTime::now();
+        let mut kept = HashSet::new();
+        let mut pruned = Vec::new();
+        for metadata in cacache::list_sync(&self.path) {
+            let metadata = metadata?;
+            let (_, policy) = match &metadata.raw_metadata {
+                Some(raw) => super::meta::deserialize(raw)?,
+                None => {
+                    let Ok(data) =
+                        cacache::read_hash(&self.path, &metadata.integrity)
+                            .await
+                    else {
+                        continue;
+                    };
+                    super::meta::deserialize(&data)?
+                }
+            };
+            if expired(&policy, now, grace) {
+                pruned.push(metadata);
+            } else {
+                kept.insert(metadata.integrity.to_string());
+            }
+        }
+        let mut report = PruneReport::default();
+        for metadata in pruned {
+            cacache::remove(&self.path, &metadata.key).await?;
+            report.entries += 1;
+            if kept.insert(metadata.integrity.to_string()) {
+                cacache::remove_hash(&self.path, &metadata.integrity).await?;
+                report.bytes += metadata.size as u64;
+            }
+        }
+        Ok(report)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        cacache::list_sync(&self.path)
+            .map(|metadata| Ok(metadata?.key))
+            .collect()
+    }
+
+    // Counted from the index without reading any content, entries sharing
+    // their content only count its size once
+    async fn stats(&self) -> Result<CacheStats> {
+        let mut contents = HashSet::new();
+        let mut stats = CacheStats::default();
+        for metadata in cacache::list_sync(&self.path) {
+            let metadata = metadata?;
+            stats.entries += 1;
+            if contents.insert(metadata.integrity.to_string()) {
+                stats.bytes += metadata.size as u64;
+            }
+        }
+        Ok(stats)
+    }
 }
@@ -0,0 +1,191 @@
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+use crate::{CacheManager, CacheStats, HttpResponse, PruneReport, Result};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use http_cache_semantics::{CacheOptions, CachePolicy};
+
+// Marks a stored response whose body was compressed, it's removed as soon as
+// the response is read back from the inner manager.
+const XCACHECOMPRESSED: &str = "x-cache-compressed";
+
+/// Implements [`CacheManager`] by compressing the bodies of the responses
+/// with gzip before passing them to another manager.
+///
+/// Bodies that don't get smaller are stored as they are, and so are the
+/// entries stored before the manager was wrapped, so both can be read back.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-compressed")))]
+#[derive(Debug, Clone)]
+pub struct CompressedManager<T> {
+    /// The manager the compressed entries are stored in
+    pub inner: T,
+    /// The compression level, from 0 (none) to 9 (best). 6 by default.
+    pub level: u32,
+}
+
+impl<T: CacheManager> CompressedManager<T> {
+    /// Create a new manager compressing the bodies stored in the inner manager
+    pub fn new(inner: T) -> Self {
+        Self { inner, level: 6 }
+    }
+    /// Sets the compression level, from 0 (none) to 9 (best)
+    #[must_use]
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    // Compresses the body when that makes it smaller, returning the original
+    // body to put back into the response once it's stored
+    fn compress(&self, response: &mut HttpResponse) -> Result<Option<Vec<u8>>> {
+        let mut encoder =
+            GzEncoder::new(Vec::new(), Compression::new(self.level.min(9)));
+        encoder.write_all(&response.body)?;
+        let compressed = encoder.finish()?;
+        if compressed.len() >= response.body.len() {
+            response.headers.remove(XCACHECOMPRESSED);
+            return Ok(None);
+        }
+        response.headers.insert(XCACHECOMPRESSED.into(), "gzip".into());
+        Ok(Some(std::mem::replace(&mut response.body, compressed)))
+    }
+}
+
+fn restore(mut response: HttpResponse, body: Option<Vec<u8>>) -> HttpResponse {
+    if let Some(body) = body {
+        response.headers.remove(XCACHECOMPRESSED);
+        response.body = body;
+    }
+    response
+}
+
+fn decompress(response: &mut HttpResponse) -> Result<()> {
+    if response.headers.remove(XCACHECOMPRESSED).is_none()
+        || response.body.is_empty()
+    {
+        return Ok(());
+    }
+    let mut body = Vec::new();
+    GzDecoder::new(response.body.as_slice()).read_to_end(&mut body)?;
+    response.body = body;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl<T: CacheManager> CacheManager for CompressedManager<T> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some((mut response, policy)) = self.inner.get(cache_key).await?
+        else {
+            return Ok(None);
+        };
+        decompress(&mut response)?;
+        Ok(Some((response, policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let body = self.compress(&mut response)?;
+        let response = self.inner.put(cache_key, response, policy).await?;
+        Ok(restore(response, body))
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let mut stores = self.inner.get_many(cache_keys).await?;
+        for (response, _) in stores.iter_mut().flatten() {
+            decompress(response)?;
+        }
+        Ok(stores)
+    }
+
+    async fn put_many(
+        &self,
+        mut entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        for (_, response, _) in &mut entries {
+            self.compress(response)?;
+        }
+        self.inner.put_many(entries).await
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let body = self.compress(&mut response)?;
+        let (response, stored) =
+            self.inner.put_if_absent(cache_key, response, policy).await?;
+        Ok((restore(response, body), stored))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some((mut response, policy)) =
+            self.inner.get_meta(cache_key).await?
+        else {
+            return Ok(None);
+        };
+        response.headers.remove(XCACHECOMPRESSED);
+        Ok(Some((response, policy)))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.inner.delete(cache_key).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.inner.delete_matching(predicate).await
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.inner.delete_prefix(prefix).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.inner.keys().await
+    }
+
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        self.inner.prune_expired(grace).await
+    }
+
+    // The records are counted as stored, compressed
+    async fn stats(&self) -> Result<CacheStats> {
+        self.inner.stats().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    fn cache_options(&self) -> Option<CacheOptions> {
+        self.inner.cache_options()
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue, Client};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+// The partition key of the table, holding the cache key
+const KEY: &str = "key";
+// The serialized response and policy
+const ENTRY: &str = "entry";
+// When DynamoDB may remove the item, in seconds since the epoch
+const EXPIRES_AT: &str = "expires_at";
+
+// DynamoDB rejects items larger than this, attribute names included
+const MAX_ITEM_SIZE: usize = 400 * 1024;
+
+/// Implements [`CacheManager`] with [`DynamoDB`](https://aws.amazon.com/dynamodb/) as the backend,
+/// using the [AWS SDK](https://github.com/awslabs/aws-sdk-rust).
+///
+/// The table needs a string partition key named `key`. Each item records
+/// when its response goes stale in the `expires_at` attribute, so enabling
+/// time to live on that attribute lets DynamoDB remove stale items.
+/// Responses too large for an item aren't stored.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-dynamodb")))]
+#[derive(Clone)]
+pub struct DynamoDbManager {
+    /// The client for DynamoDB
+    pub client: Client,
+    /// The name of the table the entries are stored in
+    pub table: String,
+    /// How long a stale response is kept, so it can still be revalidated,
+    /// before DynamoDB may remove it. Zero by default.
+    pub keep_stale_for: Duration,
+}
+
+impl fmt::Debug for DynamoDbManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynamoDbManager")
+            .field("table", &self.table)
+            .field("keep_stale_for", &self.keep_stale_for)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl DynamoDbManager {
+    /// Create a new manager storing the entries in the table
+    pub fn new(client: Client, table: impl Into<String>) -> Self {
+        Self { client, table: table.into(), keep_stale_for: Duration::ZERO }
+    }
+    /// Sets how long a stale response is kept before DynamoDB may remove it
+    #[must_use]
+    pub fn keep_stale_for(mut self, keep_stale_for: Duration) -> Self {
+        self.keep_stale_for = keep_stale_for;
+        self
+    }
+    /// Clears out the entire cache, every item in the table is removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.delete_items(&|_| true).await
+    }
+
+    // Removes the items whose cache key matches the predicate
+    async fn delete_items(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        for cache_key in self.scan_keys().await? {
+            if predicate(&cache_key) {
+                CacheManager::delete(self, &cache_key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Lists the cache key of every item, a page of the table at a time
+    async fn scan_keys(&self) -> Result<Vec<String>> {
+        let mut cache_keys = Vec::new();
+        let mut start_key = None;
+        loop {
+            let output = self
+                .client
+                .scan()
+                .table_name(&self.table)
+                .projection_expression("#k")
+                .expression_attribute_names("#k", KEY)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+            for item in output.items().unwrap_or_default() {
+                if let Some(cache_key) =
+                    item.get(KEY).and_then(|key| key.as_s().ok())
+                {
+                    cache_keys.push(cache_key.clone());
+                }
+            }
+            start_key = output.last_evaluated_key().cloned();
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(cache_keys)
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for DynamoDbManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key(KEY, AttributeValue::S(cache_key.into()))
+            .send()
+            .await?;
+        let Some(entry) =
+            output.item().and_then(|item| item.get(ENTRY)?.as_b().ok())
+        else {
+            return Ok(None);
+        };
+        let store: Store = bincode::deserialize(entry.as_ref())?;
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let size = KEY.len() + cache_key.len() + ENTRY.len() + bytes.len();
+        if size + EXPIRES_AT.len() + 20 > MAX_ITEM_SIZE {
+            // Don't leave an older version of the response behind
+            CacheManager::delete(self, &cache_key).await?;
+            return Ok(data.response);
+        }
+        let now = SystemTime::now();
+        let expires_at =
+            (now + data.policy.time_to_live(now) + self.keep_stale_for)
+                .duration_since(UNIX_EPOCH)?
+                .as_secs();
+        self.client
+            .put_item()
+            .table_name(&self.table)
+            .item(KEY, AttributeValue::S(cache_key))
+            .item(ENTRY, AttributeValue::B(Blob::new(bytes)))
+            .item(EXPIRES_AT, AttributeValue::N(expires_at.to_string()))
+            .send()
+            .await?;
+        Ok(data.response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key(KEY, AttributeValue::S(cache_key.into()))
+            .projection_expression("#k")
+            .expression_attribute_names("#k", KEY)
+            .send()
+            .await?;
+        Ok(output.item().is_some())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table)
+            .key(KEY, AttributeValue::S(cache_key.into()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        DynamoDbManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.delete_items(predicate).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.scan_keys().await
+    }
+}
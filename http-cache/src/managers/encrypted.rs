@@ -0,0 +1,233 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::DecryptionFailed, CacheManager, CacheStats, HttpResponse,
+    HttpVersion, PruneReport, Result,
+};
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use http::header::CACHE_CONTROL;
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use serde::{Deserialize, Serialize};
+
+// XChaCha20-Poly1305 nonces are long enough to be picked at random
+const NONCE_SIZE: usize = 24;
+
+/// Implements [`CacheManager`] by encrypting the entries with
+/// XChaCha20-Poly1305 before passing them to another manager.
+///
+/// The response and its policy are serialized and sealed together, bound to
+/// their cache key, so the inner manager stores only an opaque body. It's
+/// given a stand-in policy carrying nothing but the time to live, which
+/// managers that expire entries on their own rely on. The cache keys
+/// themselves, and so the URLs, aren't encrypted.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-encrypted")))]
+#[derive(Clone)]
+pub struct EncryptedManager<T> {
+    /// The manager the encrypted entries are stored in
+    pub inner: T,
+    cipher: XChaCha20Poly1305,
+}
+
+impl<T: fmt::Debug> fmt::Debug for EncryptedManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncryptedManager")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl<T: CacheManager> EncryptedManager<T> {
+    /// Create a new manager encrypting the entries of the inner manager with
+    /// the 256-bit key. Entries stored with another key can't be read back.
+    pub fn new(inner: T, key: [u8; 32]) -> Self {
+        Self { inner, cipher: XChaCha20Poly1305::new(&key.into()) }
+    }
+
+    // Seals the store into the body of the response given to the inner manager
+    fn encrypt(
+        &self,
+        cache_key: &str,
+        store: &Store,
+    ) -> Result<(HttpResponse, CachePolicy)> {
+        let plaintext = bincode::serialize(store)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload { msg: &plaintext, aad: cache_key.as_bytes() },
+            )
+            .map_err(|_| "Error encrypting cache entry")?;
+        let mut body = nonce.to_vec();
+        body.extend_from_slice(&ciphertext);
+        let response = HttpResponse {
+            body,
+            headers: Default::default(),
+            status: 200,
+            url: store.response.url.clone(),
+            version: HttpVersion::Http11,
+        };
+        // Only the time to live of the real policy is passed on, rounded up
+        // so the inner manager never expires the entry early
+        let ttl = store.policy.time_to_live(SystemTime::now());
+        let ttl = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+        let res = http::Response::builder()
+            .header(CACHE_CONTROL, format!("max-age={ttl}"))
+            .body(())?;
+        let policy = CachePolicy::new(&http::Request::new(()), &res);
+        Ok((response, policy))
+    }
+
+    fn decrypt(&self, cache_key: &str, body: &[u8]) -> Result<Store> {
+        if body.len() < NONCE_SIZE {
+            return Err(Box::new(DecryptionFailed));
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_SIZE);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload { msg: ciphertext, aad: cache_key.as_bytes() },
+            )
+            .map_err(|_| DecryptionFailed)?;
+        Ok(bincode::deserialize(&plaintext)?)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: CacheManager> CacheManager for EncryptedManager<T> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some((sealed, _)) = self.inner.get(cache_key).await? else {
+            return Ok(None);
+        };
+        let store = self.decrypt(cache_key, &sealed.body)?;
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let store = Store { response, policy };
+        let (sealed, stand_in) = self.encrypt(&cache_key, &store)?;
+        self.inner.put(cache_key, sealed, stand_in).await?;
+        Ok(store.response)
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let sealed = self.inner.get_many(cache_keys).await?;
+        cache_keys
+            .iter()
+            .zip(sealed)
+            .map(|(cache_key, sealed)| {
+                let Some((sealed, _)) = sealed else { return Ok(None) };
+                let store = self.decrypt(cache_key, &sealed.body)?;
+                Ok(Some((store.response, store.policy)))
+            })
+            .collect()
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        let sealed = entries
+            .into_iter()
+            .map(|(cache_key, response, policy)| {
+                let store = Store { response, policy };
+                let (sealed, stand_in) = self.encrypt(&cache_key, &store)?;
+                Ok((cache_key, sealed, stand_in))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.inner.put_many(sealed).await
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let store = Store { response, policy };
+        let (sealed, stand_in) = self.encrypt(&cache_key, &store)?;
+        let (_, stored) =
+            self.inner.put_if_absent(cache_key, sealed, stand_in).await?;
+        Ok((store.response, stored))
+    }
+
+    // The metadata is sealed with the body, so the whole entry is read
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self.get(cache_key).await?.map(|(mut response, policy)| {
+            response.body = Vec::new();
+            (response, policy)
+        }))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.inner.delete(cache_key).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.inner.delete_matching(predicate).await
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.inner.delete_prefix(prefix).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.inner.keys().await
+    }
+
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        self.inner.prune_expired(grace).await
+    }
+
+    // The records are counted as stored, encrypted
+    async fn stats(&self) -> Result<CacheStats> {
+        self.inner.stats().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    fn cache_options(&self) -> Option<CacheOptions> {
+        self.inner.cache_options()
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    expired, BodyStream, CacheEntry, CacheManager, HttpHeaders, HttpResponse,
+    HttpVersion, PruneReport, Result,
+};
+
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+// Holds the entries whose cache key isn't a method and an URL
+const OTHER_KEYS: &str = "_keys";
+
+// How much of a streamed body is read at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Implements [`CacheManager`] by storing each response as plain files in a directory.
+///
+/// The location of an entry follows its URL, so the response to
+/// `GET https://example.com/docs/page?q=1` is kept in
+/// `https/example.com/docs/page/`. The body is written as is to
+/// `GET-<hash of the query>.body`, next to a `.json` file of the same name
+/// holding the cache key, status, headers and policy. Cache keys that aren't
+/// a method and an URL are stored under `_keys/` by their hash.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-filesystem")))]
+#[derive(Debug, Clone)]
+pub struct FileSystemManager {
+    /// Directory where the cache will be stored.
+    pub path: PathBuf,
+}
+
+impl Default for FileSystemManager {
+    fn default() -> Self {
+        Self { path: "./http-cache-files".into() }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Metadata {
+    cache_key: String,
+    url: Url,
+    status: u16,
+    version: HttpVersion,
+    headers: HttpHeaders,
+    policy: CachePolicy,
+}
+
+impl FileSystemManager {
+    /// Create a new manager storing the cache in the directory
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+    /// Clears out the entire cache, the directory is removed.
+    pub async fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // The path of an entry's files, without their extension
+    fn entry_path(&self, cache_key: &str) -> PathBuf {
+        if let Some(path) = self.url_path(cache_key) {
+            return path;
+        }
+        self.path.join(OTHER_KEYS).join(hash(cache_key))
+    }
+
+    // Lays out a `METHOD:URL` cache key by the scheme, host and path of the URL
+    fn url_path(&self, cache_key: &str) -> Option<PathBuf> {
+        let (method, url) = cache_key.split_once(':')?;
+        if method.is_empty() || !method.bytes().all(|b| b.is_ascii_alphabetic())
+        {
+            return None;
+        }
+        let url = Url::parse(url).ok()?;
+        let mut host = url.host_str()?.to_owned();
+        if let Some(port) = url.port() {
+            host = format!("{host}:{port}");
+        }
+        let mut path = self.path.join(url.scheme()).join(file_name(&host));
+        for segment in url.path_segments()?.filter(|s| !s.is_empty()) {
+            path.push(file_name(segment));
+        }
+        match url.query() {
+            Some(query) => {
+                path.push(format!("{method}-{}", &hash(query)[..16]))
+            }
+            None => path.push(method),
+        }
+        Some(path)
+    }
+
+    // Writes the metadata of an entry, after its body, as an entry exists
+    // once its metadata is there
+    fn write_metadata(
+        path: &Path,
+        cache_key: String,
+        response: &HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<()> {
+        let meta = Metadata {
+            cache_key,
+            url: response.url.clone(),
+            status: response.status,
+            version: response.version,
+            headers: response.headers.clone(),
+            policy,
+        };
+        write(&path.with_extension("json"), &serde_json::to_vec_pretty(&meta)?)
+    }
+
+    // Removes the directories left empty between an entry and the root
+    fn prune(&self, entry_path: &Path) {
+        let mut dir = entry_path.parent();
+        while let Some(path) = dir {
+            if path == self.path
+                || !path.starts_with(&self.path)
+                || fs::remove_dir(path).is_err()
+            {
+                break;
+            }
+            dir = path.parent();
+        }
+    }
+
+    // Collects the metadata files in the directory and below it
+    fn metadata_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::metadata_files(&path, files)?;
+            } else if path.extension().map_or(false, |ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Percent-encodes the characters that some file systems don't allow in a name
+fn file_name(value: &str) -> String {
+    let mut name = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => {
+                name.push_str(&format!("%{:02X}", c as u8));
+            }
+            c if c.is_control() => {
+                name.push_str(&format!("%{:02X}", c as u32));
+            }
+            c => name.push(c),
+        }
+    }
+    name
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Writes through a temporary file, so a reader never sees half a file
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for FileSystemManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let path = self.entry_path(cache_key);
+        let Some(meta) = self.get_meta(cache_key).await? else {
+            return Ok(None);
+        };
+        let Some(body) = read(&path.with_extension("body"))? else {
+            return Ok(None);
+        };
+        Ok(Some((HttpResponse { body, ..meta.0 }, meta.1)))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let path = self.entry_path(cache_key).with_extension("json");
+        let Some(bytes) = read(&path)? else {
+            return Ok(None);
+        };
+        let meta: Metadata = serde_json::from_slice(&bytes)?;
+        let response = HttpResponse {
+            body: Vec::new(),
+            headers: meta.headers,
+            status: meta.status,
+            url: meta.url,
+            version: meta.version,
+        };
+        Ok(Some((response, meta.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let path = self.entry_path(&cache_key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write(&path.with_extension("body"), &response.body)?;
+        Self::write_metadata(&path, cache_key, &response, policy)?;
+        Ok(response)
+    }
+
+    async fn get_stream(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, BodyStream)>> {
+        let path = self.entry_path(cache_key);
+        let Some((response, policy)) = self.get_meta(cache_key).await? else {
+            return Ok(None);
+        };
+        let file = match fs::File::open(path.with_extension("body")) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let body = stream::unfold(Some(file), |file| async move {
+            let mut file = file?;
+            let mut chunk = vec![0; CHUNK_SIZE];
+            match file.read(&mut chunk) {
+                Ok(0) => None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some((Ok(Bytes::from(chunk)), Some(file)))
+                }
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        });
+        Ok(Some((response, policy, Box::pin(body))))
+    }
+
+    async fn put_stream(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        mut body: BodyStream,
+    ) -> Result<()> {
+        let path = self.entry_path(&cache_key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written through a temporary file like `write`, which is removed if
+        // the stream fails
+        let mut tmp = path.with_extension("body").into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        while let Some(chunk) = body.next().await {
+            let written = match chunk {
+                Ok(chunk) => file.write_all(&chunk).map_err(Into::into),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                drop(file);
+                fs::remove_file(&tmp)?;
+                self.prune(&path);
+                return Err(e);
+            }
+        }
+        drop(file);
+        fs::rename(&tmp, path.with_extension("body"))?;
+        Self::write_metadata(&path, cache_key, &response, policy)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.entry_path(cache_key).with_extension("json").is_file())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let path = self.entry_path(cache_key);
+        remove(&path.with_extension("json"))?;
+        remove(&path.with_extension("body"))?;
+        self.prune(&path);
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        FileSystemManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let mut files = Vec::new();
+        Self::metadata_files(&self.path, &mut files)?;
+        for file in files {
+            let Some(bytes) = read(&file)? else {
+                continue;
+            };
+            let meta: Metadata = serde_json::from_slice(&bytes)?;
+            if predicate(&meta.cache_key) {
+                remove(&file)?;
+                remove(&file.with_extension("body"))?;
+                self.prune(&file);
+            }
+        }
+        Ok(())
+    }
+
+    // Checked with the metadata files, so the bodies aren't read
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        Self::metadata_files(&self.path, &mut files)?;
+        let mut report = PruneReport::default();
+        for file in files {
+            let Some(bytes) = read(&file)? else {
+                continue;
+            };
+            let meta: Metadata = serde_json::from_slice(&bytes)?;
+            if !expired(&meta.policy, now, grace) {
+                continue;
+            }
+            let body = file.with_extension("body");
+            let body_size = match fs::metadata(&body) {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e.into()),
+            };
+            remove(&file)?;
+            remove(&body)?;
+            self.prune(&file);
+            report.entries += 1;
+            report.bytes += bytes.len() as u64 + body_size;
+        }
+        Ok(report)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.entries().await?.into_iter().map(|e| e.cache_key).collect())
+    }
+
+    // Described by the metadata files and the sizes of the body files
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut files = Vec::new();
+        Self::metadata_files(&self.path, &mut files)?;
+        let mut entries = Vec::new();
+        for file in files {
+            let Some(bytes) = read(&file)? else {
+                continue;
+            };
+            let body_size = match fs::metadata(file.with_extension("body")) {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let meta: Metadata = serde_json::from_slice(&bytes)?;
+            entries.push(CacheEntry::describe(
+                meta.cache_key,
+                meta.url,
+                meta.status,
+                &meta.headers,
+                &meta.policy,
+                body_size,
+                SystemTime::now(),
+            ));
+        }
+        Ok(entries)
+    }
+}
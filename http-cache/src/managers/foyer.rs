@@ -0,0 +1,111 @@
+use std::{fmt, path::Path};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use foyer::{
+    BlockEngineConfig, DeviceBuilder, FsDeviceBuilder, HybridCache,
+    HybridCacheBuilder,
+};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+/// Implements [`CacheManager`] with [`foyer`](https://github.com/foyer-rs/foyer) as the backend.
+///
+/// Entries are kept in memory and spill over to disk when they're evicted,
+/// following the admission and eviction policies the cache was built with.
+/// The keys of a foyer cache can't be listed, so
+/// [`CacheManager::delete_matching`] isn't supported.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-foyer")))]
+#[derive(Clone)]
+pub struct FoyerManager {
+    /// The instance of `foyer::HybridCache`
+    pub cache: HybridCache<String, Vec<u8>>,
+}
+
+impl fmt::Debug for FoyerManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FoyerManager")
+            .field("name", &self.cache.name())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl FoyerManager {
+    /// Create a new manager from a pre-configured HybridCache
+    pub fn new(cache: HybridCache<String, Vec<u8>>) -> Self {
+        Self { cache }
+    }
+    /// Create a new manager keeping up to `memory_capacity` bytes of entries
+    /// in memory, and up to `disk_capacity` bytes in the directory. The
+    /// entries already on disk are recovered.
+    pub async fn with_capacity(
+        path: impl AsRef<Path>,
+        memory_capacity: usize,
+        disk_capacity: usize,
+    ) -> Result<Self> {
+        let device =
+            FsDeviceBuilder::new(path).with_capacity(disk_capacity).build()?;
+        let cache = HybridCacheBuilder::new()
+            .with_name("http-cache")
+            .memory(memory_capacity)
+            .with_weighter(Self::weigher)
+            .storage()
+            .with_engine_config(BlockEngineConfig::new(device))
+            .build()
+            .await?;
+        Ok(Self::new(cache))
+    }
+    /// Weighs an entry by the size of its key and serialized value in bytes,
+    /// can be passed to `with_weighter` when building a custom cache.
+    // The signature is dictated by foyer's weighter closure
+    #[allow(clippy::ptr_arg)]
+    pub fn weigher(key: &String, value: &Vec<u8>) -> usize {
+        key.len() + value.len()
+    }
+    /// Clears out the entire cache, both in memory and on disk.
+    pub async fn clear(&self) -> Result<()> {
+        self.cache.clear().await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for FoyerManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let store: Store = match self.cache.get(cache_key).await? {
+            Some(entry) => bincode::deserialize(entry.value())?,
+            None => return Ok(None),
+        };
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        self.cache.insert(cache_key, bytes);
+        Ok(data.response)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.cache.remove(cache_key);
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        FoyerManager::clear(self).await
+    }
+}
@@ -0,0 +1,226 @@
+use std::{fmt, future::Future, rc::Rc};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use idb::{
+    Database, DatabaseEvent, Factory, ObjectStore, ObjectStoreParams, Query,
+    Transaction, TransactionMode,
+};
+use js_sys::Uint8Array;
+use send_wrapper::SendWrapper;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+// The object store holding the entries, created with the database
+const STORE: &str = "http-cache";
+
+/// Implements [`CacheManager`] with [IndexedDB](https://developer.mozilla.org/en-US/docs/Web/API/IndexedDB_API)
+/// as the backend, persisting the cache in the browser. Only available when
+/// compiling to `wasm32`.
+///
+/// IndexedDB handles can't leave the thread they were made on, which is never
+/// an issue in the browser, where everything runs on a single thread.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-indexeddb")))]
+#[derive(Clone)]
+pub struct IdbManager {
+    database: SendWrapper<Rc<Database>>,
+}
+
+impl fmt::Debug for IdbManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdbManager")
+            .field("database", &self.database.name())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+// IndexedDB errors hold JavaScript values, which can't be sent between threads
+fn js_error(e: idb::Error) -> crate::BoxError {
+    e.to_string().into()
+}
+
+// Runs a future holding IndexedDB handles where a `Send` future is expected
+fn local<F: Future>(future: F) -> SendWrapper<F> {
+    SendWrapper::new(future)
+}
+
+impl IdbManager {
+    /// Opens the database with the given name, creating it if needed
+    pub async fn open(name: &str) -> Result<Self> {
+        let factory = Factory::new().map_err(js_error)?;
+        let mut request = factory.open(name, Some(1)).map_err(js_error)?;
+        request.on_upgrade_needed(|event| {
+            if let Ok(database) = event.database() {
+                if !database.store_names().iter().any(|s| s == STORE) {
+                    let _ = database
+                        .create_object_store(STORE, ObjectStoreParams::new());
+                }
+            }
+        });
+        let database = request.await.map_err(js_error)?;
+        Ok(Self { database: SendWrapper::new(Rc::new(database)) })
+    }
+    /// Clears out the entire cache.
+    pub async fn clear(&self) -> Result<()> {
+        local(async {
+            let (transaction, store) =
+                self.store(TransactionMode::ReadWrite)?;
+            store.clear().map_err(js_error)?.await.map_err(js_error)?;
+            transaction.commit().map_err(js_error)?.await.map_err(js_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    fn store(
+        &self,
+        mode: TransactionMode,
+    ) -> Result<(Transaction, ObjectStore)> {
+        let transaction =
+            self.database.transaction(&[STORE], mode).map_err(js_error)?;
+        let store = transaction.object_store(STORE).map_err(js_error)?;
+        Ok((transaction, store))
+    }
+
+    async fn read(&self, cache_key: &str) -> Result<Option<Vec<u8>>> {
+        local(async {
+            let (_transaction, store) =
+                self.store(TransactionMode::ReadOnly)?;
+            let value = store
+                .get(JsValue::from_str(cache_key))
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            Ok(value.map(|value| Uint8Array::new(&value).to_vec()))
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for IdbManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.read(cache_key).await? {
+            Some(bytes) => Ok(Some(super::meta::deserialize_store(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        local(async {
+            let (transaction, store) =
+                self.store(TransactionMode::ReadWrite)?;
+            let value = Uint8Array::from(bytes.as_slice());
+            store
+                .put(&value.into(), Some(&JsValue::from_str(&cache_key)))
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            transaction.commit().map_err(js_error)?.await.map_err(js_error)?;
+            Ok::<_, crate::BoxError>(())
+        })
+        .await?;
+        Ok(data.response)
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.read(cache_key).await? {
+            Some(bytes) => Ok(Some(super::meta::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        local(async {
+            let (_transaction, store) =
+                self.store(TransactionMode::ReadOnly)?;
+            let count = store
+                .count(Some(Query::from(JsValue::from_str(cache_key))))
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            Ok(count > 0)
+        })
+        .await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        local(async {
+            let (transaction, store) =
+                self.store(TransactionMode::ReadWrite)?;
+            store
+                .delete(JsValue::from_str(cache_key))
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            transaction.commit().map_err(js_error)?.await.map_err(js_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        IdbManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        local(async {
+            let (transaction, store) =
+                self.store(TransactionMode::ReadWrite)?;
+            let keys = store
+                .get_all_keys(None, None)
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            for key in keys {
+                if key.as_string().map_or(false, |key| predicate(&key)) {
+                    store
+                        .delete(key)
+                        .map_err(js_error)?
+                        .await
+                        .map_err(js_error)?;
+                }
+            }
+            transaction.commit().map_err(js_error)?.await.map_err(js_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        local(async {
+            let (_transaction, store) =
+                self.store(TransactionMode::ReadOnly)?;
+            let keys = store
+                .get_all_keys(None, None)
+                .map_err(js_error)?
+                .await
+                .map_err(js_error)?;
+            Ok(keys.into_iter().filter_map(|key| key.as_string()).collect())
+        })
+        .await
+    }
+}
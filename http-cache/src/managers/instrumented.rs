@@ -0,0 +1,288 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    BodyStream, CacheEntry, CacheManager, CacheStats, HttpResponse,
+    PruneReport, Result,
+};
+
+use futures_util::StreamExt;
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use metrics::{counter, histogram};
+
+const OPERATIONS: &str = "http_cache_manager_operations_total";
+const DURATION: &str = "http_cache_manager_operation_duration_seconds";
+const STORED_BYTES: &str = "http_cache_manager_stored_bytes_total";
+
+/// Implements [`CacheManager`] by recording metrics about the operations of
+/// another manager through the [`metrics`] facade.
+///
+/// - `http_cache_manager_operations_total` counts the operations, labeled
+///   with `manager`, `operation` and `outcome`. Reads are a `hit`, `miss` or
+///   `error`, the other operations are `ok` or `error`.
+/// - `http_cache_manager_operation_duration_seconds` records how long the
+///   operations took, labeled with `manager` and `operation`.
+/// - `http_cache_manager_stored_bytes_total` counts the bytes of the response
+///   bodies stored, labeled with `manager`.
+///
+/// Nothing is recorded until a recorder, such as a Prometheus exporter, is
+/// installed by the application.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-instrumented")))]
+#[derive(Debug, Clone)]
+pub struct InstrumentedManager<T> {
+    /// The manager whose operations are recorded
+    pub inner: T,
+    /// The value of the `manager` label, "default" unless set
+    pub name: String,
+}
+
+impl<T: CacheManager> InstrumentedManager<T> {
+    /// Create a new manager recording the operations of the inner manager
+    pub fn new(inner: T) -> Self {
+        Self { inner, name: "default".into() }
+    }
+    /// Sets the value of the `manager` label, telling apart the metrics of
+    /// several instrumented managers, such as the tiers of a [`TieredManager`]
+    ///
+    /// [`TieredManager`]: crate::TieredManager
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    fn record(
+        &self,
+        operation: &'static str,
+        outcome: &'static str,
+        start: Instant,
+    ) {
+        counter!(
+            OPERATIONS,
+            1,
+            "manager" => self.name.clone(),
+            "operation" => operation,
+            "outcome" => outcome
+        );
+        histogram!(
+            DURATION,
+            start.elapsed(),
+            "manager" => self.name.clone(),
+            "operation" => operation
+        );
+    }
+
+    fn record_read<V>(
+        &self,
+        operation: &'static str,
+        start: Instant,
+        result: &Result<Option<V>>,
+    ) {
+        let outcome = match result {
+            Ok(Some(_)) => "hit",
+            Ok(None) => "miss",
+            Err(_) => "error",
+        };
+        self.record(operation, outcome, start);
+    }
+
+    fn record_write<V>(
+        &self,
+        operation: &'static str,
+        start: Instant,
+        result: &Result<V>,
+    ) {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.record(operation, outcome, start);
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: CacheManager> CacheManager for InstrumentedManager<T> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let start = Instant::now();
+        let result = self.inner.get(cache_key).await;
+        self.record_read("get", start, &result);
+        result
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let start = Instant::now();
+        let size = response.body.len() as u64;
+        let result = self.inner.put(cache_key, response, policy).await;
+        self.record_write("put", start, &result);
+        if result.is_ok() {
+            counter!(STORED_BYTES, size, "manager" => self.name.clone());
+        }
+        result
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let start = Instant::now();
+        let result = self.inner.get_many(cache_keys).await;
+        self.record_write("get_many", start, &result);
+        result
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let size: u64 = entries
+            .iter()
+            .map(|(_, response, _)| response.body.len() as u64)
+            .sum();
+        let result = self.inner.put_many(entries).await;
+        self.record_write("put_many", start, &result);
+        if result.is_ok() {
+            counter!(STORED_BYTES, size, "manager" => self.name.clone());
+        }
+        result
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let start = Instant::now();
+        let size = response.body.len() as u64;
+        let result =
+            self.inner.put_if_absent(cache_key, response, policy).await;
+        self.record_write("put_if_absent", start, &result);
+        if let Ok((_, true)) = result {
+            counter!(STORED_BYTES, size, "manager" => self.name.clone());
+        }
+        result
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let start = Instant::now();
+        let result = self.inner.get_meta(cache_key).await;
+        self.record_read("get_meta", start, &result);
+        result
+    }
+
+    // The duration is how long it took to start reading the body
+    async fn get_stream(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, BodyStream)>> {
+        let start = Instant::now();
+        let result = self.inner.get_stream(cache_key).await;
+        self.record_read("get_stream", start, &result);
+        result
+    }
+
+    // The body's size isn't known before it's read, so it's counted as it
+    // passes through
+    async fn put_stream(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        body: BodyStream,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let size = Arc::new(AtomicU64::new(0));
+        let counted = size.clone();
+        let body = body.inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                counted.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+        });
+        let result =
+            self.inner.put_stream(cache_key, res, policy, Box::pin(body)).await;
+        self.record_write("put_stream", start, &result);
+        if result.is_ok() {
+            let size = size.load(Ordering::Relaxed);
+            counter!(STORED_BYTES, size, "manager" => self.name.clone());
+        }
+        result
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.delete(cache_key).await;
+        self.record_write("delete", start, &result);
+        result
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.clear().await;
+        self.record_write("clear", start, &result);
+        result
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.delete_matching(predicate).await;
+        self.record_write("delete_matching", start, &result);
+        result
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.delete_prefix(prefix).await;
+        self.record_write("delete_prefix", start, &result);
+        result
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.inner.keys().await
+    }
+
+    // Passed straight on so the records aren't counted as reads
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        self.inner.entries().await
+    }
+
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let start = Instant::now();
+        let result = self.inner.prune_expired(grace).await;
+        self.record_write("prune_expired", start, &result);
+        result
+    }
+
+    // Passed straight on so the records aren't counted as reads
+    async fn stats(&self) -> Result<CacheStats> {
+        self.inner.stats().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    fn cache_options(&self) -> Option<CacheOptions> {
+        self.inner.cache_options()
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fmt, path::Path};
+
+use crate::{BoxError, CacheManager, HttpResponse, Result};
+
+use heed::{
+    types::{ByteSlice, Str},
+    Database, Env, EnvOpenOptions,
+};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+/// Implements [`CacheManager`] with [`LMDB`](http://www.lmdb.tech/doc/) as the backend,
+/// using the [`heed`](https://github.com/meilisearch/heed) bindings.
+///
+/// Reads come straight from the memory map, so looking up the metadata of a
+/// response or whether it exists doesn't copy its body.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-lmdb")))]
+#[derive(Clone)]
+pub struct LmdbManager {
+    /// The environment the database lives in
+    pub env: Env,
+    /// The database the entries are stored in
+    pub db: Database<Str, ByteSlice>,
+}
+
+impl fmt::Debug for LmdbManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LmdbManager")
+            .field("path", &self.env.path())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl LmdbManager {
+    /// Opens the environment in the directory, creating it if it's missing.
+    /// The cache can grow to 1 GiB.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_map_size(path, 1024 * 1024 * 1024)
+    }
+    /// Opens the environment in the directory, creating it if it's missing.
+    /// The map size is the most the cache can grow to in bytes, it should be
+    /// a multiple of the page size.
+    pub fn with_map_size(
+        path: impl AsRef<Path>,
+        map_size: usize,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(&path)?;
+        let env = EnvOpenOptions::new()
+            .map_size(map_size)
+            .max_dbs(1)
+            .open(path)
+            .map_err(error)?;
+        Self::with_env(env)
+    }
+    /// Create a new manager from an open environment, storing the entries in
+    /// its `http-cache` database. The environment needs room for a named database.
+    pub fn with_env(env: Env) -> Result<Self> {
+        let db = env.create_database(Some("http-cache")).map_err(error)?;
+        Ok(Self { env, db })
+    }
+    /// Clears out the entire cache.
+    pub async fn clear(&self) -> Result<()> {
+        let mut wtxn = self.env.write_txn().map_err(error)?;
+        self.db.clear(&mut wtxn).map_err(error)?;
+        wtxn.commit().map_err(error)
+    }
+}
+
+// The errors of heed aren't `Send`, so only their message is kept
+fn error(e: heed::Error) -> BoxError {
+    e.to_string().into()
+}
+
+#[async_trait::async_trait]
+impl CacheManager for LmdbManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let rtxn = self.env.read_txn().map_err(error)?;
+        match self.db.get(&rtxn, cache_key).map_err(error)? {
+            Some(d) => Ok(Some(super::meta::deserialize_store(d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let rtxn = self.env.read_txn().map_err(error)?;
+        match self.db.get(&rtxn, cache_key).map_err(error)? {
+            Some(d) => Ok(Some(super::meta::deserialize(d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let mut wtxn = self.env.write_txn().map_err(error)?;
+        self.db.put(&mut wtxn, &cache_key, &bytes).map_err(error)?;
+        wtxn.commit().map_err(error)?;
+        Ok(data.response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let rtxn = self.env.read_txn().map_err(error)?;
+        Ok(self.db.get(&rtxn, cache_key).map_err(error)?.is_some())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let mut wtxn = self.env.write_txn().map_err(error)?;
+        self.db.delete(&mut wtxn, cache_key).map_err(error)?;
+        wtxn.commit().map_err(error)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        LmdbManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let mut wtxn = self.env.write_txn().map_err(error)?;
+        let mut cache_keys = Vec::new();
+        for entry in self.db.iter(&*wtxn).map_err(error)? {
+            let (cache_key, _) = entry.map_err(error)?;
+            if predicate(cache_key) {
+                cache_keys.push(cache_key.to_owned());
+            }
+        }
+        for cache_key in cache_keys {
+            self.db.delete(&mut wtxn, &cache_key).map_err(error)?;
+        }
+        wtxn.commit().map_err(error)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let rtxn = self.env.read_txn().map_err(error)?;
+        let mut cache_keys = Vec::new();
+        for entry in self.db.iter(&rtxn).map_err(error)? {
+            let (cache_key, _) = entry.map_err(error)?;
+            cache_keys.push(cache_key.to_owned());
+        }
+        Ok(cache_keys)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.env.force_sync().map_err(error)
+    }
+}
@@ -0,0 +1,292 @@
+use std::{fmt, sync::Arc};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use async_memcached::{Client, Error, ErrorKind, Status};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+// Keys longer than this are rejected by memcached
+const MAX_KEY_LENGTH: usize = 250;
+
+// Marks a key that was hashed because memcached couldn't store it as is
+const HASHED: &str = "sha256:";
+
+/// Implements [`CacheManager`] with [`memcached`](https://memcached.org) as the backend,
+/// using the [`async-memcached`](https://github.com/cobbinma/async-memcached) client.
+///
+/// Requests take turns on a single connection, which is reopened when it
+/// fails. Responses larger than [`MemcachedManager::max_item_size`] aren't
+/// stored, the server would reject them anyway.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-memcached")))]
+#[derive(Clone)]
+pub struct MemcachedManager {
+    /// The connection to the memcached server
+    pub connection: Arc<Mutex<Client>>,
+    /// Prepended to every cache key, `http-cache:` by default
+    pub prefix: String,
+    /// The largest item the server accepts in bytes, 1 MiB by default.
+    /// This should match the server's `-I` option.
+    pub max_item_size: usize,
+    dsn: String,
+}
+
+impl fmt::Debug for MemcachedManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemcachedManager")
+            .field("dsn", &self.dsn)
+            .field("prefix", &self.prefix)
+            .field("max_item_size", &self.max_item_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+    // The cache key, so entries stored under a hashed key can still be matched
+    cache_key: String,
+}
+
+impl MemcachedManager {
+    /// Connects to the memcached server at the address, e.g. `127.0.0.1:11211`
+    pub async fn new(dsn: &str) -> Result<Self> {
+        let client = Client::new(dsn).await?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(client)),
+            prefix: "http-cache:".into(),
+            max_item_size: 1024 * 1024,
+            dsn: dsn.into(),
+        })
+    }
+    /// Sets the prefix of the keys, so several caches can share a server
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+    /// Sets the largest item in bytes the server accepts
+    #[must_use]
+    pub fn max_item_size(mut self, max_item_size: usize) -> Self {
+        self.max_item_size = max_item_size;
+        self
+    }
+    /// Clears out the entire cache, only the keys with the prefix are removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.delete_keys(&|_| true).await
+    }
+
+    // Memcached keys can't hold whitespace or control characters and have a
+    // length limit, so any other key is replaced by its hash
+    fn key(&self, cache_key: &str) -> String {
+        let key = format!("{}{}", self.prefix, cache_key);
+        if key.len() <= MAX_KEY_LENGTH
+            && !key
+                .bytes()
+                .any(|b| b.is_ascii_whitespace() || b.is_ascii_control())
+        {
+            return key;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(cache_key.as_bytes());
+        let hash: String =
+            hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+        format!("{}{}{}", self.prefix, HASHED, hash)
+    }
+
+    // Reopens the connection after an error that may have left it unusable,
+    // the error is still returned
+    async fn recover<T>(
+        &self,
+        client: &mut Client,
+        result: std::result::Result<T, Error>,
+    ) -> Result<T> {
+        match result {
+            Err(e @ (Error::Io(_) | Error::Protocol(Status::Error(_)))) => {
+                if let Ok(reconnected) = Client::new(&self.dsn).await {
+                    *client = reconnected;
+                }
+                Err(e.into())
+            }
+            result => Ok(result?),
+        }
+    }
+
+    // Lists the keys with the prefix along with the cache keys they hold
+    async fn cache_keys(
+        &self,
+        client: &mut Client,
+    ) -> Result<Vec<(String, String)>> {
+        let dumped = dump_keys(client).await;
+        let dumped = self.recover(client, dumped).await?;
+        let hashed = format!("{}{}", self.prefix, HASHED);
+        let mut cache_keys = Vec::new();
+        for key in dumped {
+            let Some(cache_key) = key.strip_prefix(&self.prefix) else {
+                continue;
+            };
+            let cache_key = if key.starts_with(&hashed) {
+                // The cache key behind a hash is only known from the entry
+                let value = client.get(&key).await;
+                match self.recover(client, value).await? {
+                    Some(value) => {
+                        match bincode::deserialize::<Store>(&value.data) {
+                            Ok(store) => store.cache_key,
+                            Err(_) => continue,
+                        }
+                    }
+                    None => continue,
+                }
+            } else {
+                cache_key.to_owned()
+            };
+            cache_keys.push((key, cache_key));
+        }
+        Ok(cache_keys)
+    }
+
+    // Removes the keys with the prefix whose cache key matches the predicate
+    async fn delete_keys(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let mut client = self.connection.lock().await;
+        for (key, cache_key) in self.cache_keys(&mut client).await? {
+            if predicate(&cache_key) {
+                let deleted = ignore_not_found(client.delete(&key).await);
+                self.recover(&mut client, deleted).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Lists the keys on the server, the metadump percent-encodes them
+async fn dump_keys(
+    client: &mut Client,
+) -> std::result::Result<Vec<String>, Error> {
+    let mut keys = Vec::new();
+    let mut iter = client.dump_keys().await?;
+    while let Some(metadata) = iter.next().await {
+        let key = metadata?.key;
+        let mut decoded = Vec::with_capacity(key.len());
+        let mut i = 0;
+        while i < key.len() {
+            let hex = key.get(i + 1..i + 3).and_then(|hex| {
+                u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+            });
+            match (key[i], hex) {
+                (b'%', Some(byte)) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                (byte, _) => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        keys.extend(String::from_utf8(decoded).ok());
+    }
+    Ok(keys)
+}
+
+// A key that's already gone doesn't need deleting
+fn ignore_not_found(
+    result: std::result::Result<(), Error>,
+) -> std::result::Result<(), Error> {
+    match result {
+        Err(Error::Protocol(Status::NotFound)) => Ok(()),
+        result => result,
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for MemcachedManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let mut client = self.connection.lock().await;
+        let value = client.get(self.key(cache_key)).await;
+        match self.recover(&mut client, value).await? {
+            Some(value) => {
+                Ok(Some(super::meta::deserialize_store(&value.data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let mut client = self.connection.lock().await;
+        let value = client.get(self.key(cache_key)).await;
+        match self.recover(&mut client, value).await? {
+            Some(value) => Ok(Some(super::meta::deserialize(&value.data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let key = self.key(&cache_key);
+        let data = Store { response, policy, cache_key };
+        let bytes = bincode::serialize(&data)?;
+        let mut client = self.connection.lock().await;
+        if key.len() + bytes.len() > self.max_item_size {
+            // Don't leave an older version of the response behind
+            let deleted = ignore_not_found(client.delete(&key).await);
+            self.recover(&mut client, deleted).await?;
+            return Ok(data.response);
+        }
+        let stored =
+            match client.set(&key, &bytes, None, None).await {
+                // The server's limit may be lower than the one configured
+                Err(Error::Protocol(Status::Error(ErrorKind::Server(
+                    message,
+                )))) if message.contains("too large") => Ok(()),
+                stored => stored,
+            };
+        self.recover(&mut client, stored).await?;
+        Ok(data.response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let mut client = self.connection.lock().await;
+        let value = client.get(self.key(cache_key)).await;
+        Ok(self.recover(&mut client, value).await?.is_some())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let mut client = self.connection.lock().await;
+        let deleted =
+            ignore_not_found(client.delete(self.key(cache_key)).await);
+        self.recover(&mut client, deleted).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        MemcachedManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.delete_keys(predicate).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let mut client = self.connection.lock().await;
+        let cache_keys = self.cache_keys(&mut client).await?;
+        Ok(cache_keys.into_iter().map(|(_, cache_key)| cache_key).collect())
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use crate::{CacheEntry, CacheManager, CacheStats, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// Implements [`CacheManager`] with a `HashMap` in memory, needing no other
+/// dependencies or features.
+///
+/// The map isn't bounded and nothing is evicted, so it suits tests and
+/// short-lived programs. Clones of the manager share the same entries.
+#[derive(Clone, Default)]
+pub struct InMemoryManager {
+    entries: Arc<RwLock<HashMap<String, (HttpResponse, CachePolicy)>>>,
+}
+
+impl fmt::Debug for InMemoryManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InMemoryManager")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl InMemoryManager {
+    /// Create a new, empty manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the number of entries in the cache
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+    /// Returns true if the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().unwrap().is_empty()
+    }
+    /// Clears out the entire cache.
+    pub async fn clear(&self) -> Result<()> {
+        self.entries.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for InMemoryManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self.entries.read().unwrap().get(cache_key).cloned())
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let entries = self.entries.read().unwrap();
+        let Some((response, policy)) = entries.get(cache_key) else {
+            return Ok(None);
+        };
+        let response = HttpResponse {
+            body: Vec::new(),
+            headers: response.headers.clone(),
+            status: response.status,
+            url: response.url.clone(),
+            version: response.version,
+        };
+        Ok(Some((response, policy.clone())))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(cache_key, (response.clone(), policy));
+        Ok(response)
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let entries = self.entries.read().unwrap();
+        Ok(cache_keys
+            .iter()
+            .map(|cache_key| entries.get(*cache_key).cloned())
+            .collect())
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        let mut stored = self.entries.write().unwrap();
+        for (cache_key, response, policy) in entries {
+            stored.insert(cache_key, (response, policy));
+        }
+        Ok(())
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        match self.entries.write().unwrap().entry(cache_key) {
+            Entry::Occupied(_) => Ok((response, false)),
+            Entry::Vacant(entry) => {
+                entry.insert((response.clone(), policy));
+                Ok((response, true))
+            }
+        }
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.entries.read().unwrap().contains_key(cache_key))
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.entries.write().unwrap().remove(cache_key);
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        InMemoryManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.entries
+            .write()
+            .unwrap()
+            .retain(|cache_key, _| !predicate(cache_key));
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.entries.read().unwrap().keys().cloned().collect())
+    }
+
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        Ok(self
+            .entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(cache_key, (response, policy))| {
+                CacheEntry::new(cache_key.clone(), response, policy)
+            })
+            .collect())
+    }
+
+    async fn stats(&self) -> Result<CacheStats> {
+        let entries = self.entries.read().unwrap();
+        Ok(CacheStats {
+            entries: entries.len(),
+            bytes: entries
+                .values()
+                .map(|(response, _)| response.body.len() as u64)
+                .sum(),
+        })
+    }
+}
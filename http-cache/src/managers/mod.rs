@@ -1,5 +1,178 @@
 #[cfg(feature = "manager-cacache")]
 pub mod cacache;
 
+#[cfg(feature = "manager-compressed")]
+pub mod compressed;
+
+#[cfg(feature = "manager-dynamodb")]
+pub mod dynamodb;
+
+#[cfg(feature = "manager-encrypted")]
+pub mod encrypted;
+
+#[cfg(feature = "manager-filesystem")]
+pub mod filesystem;
+
+#[cfg(feature = "manager-foyer")]
+pub mod foyer;
+
+#[cfg(all(feature = "manager-indexeddb", target_arch = "wasm32"))]
+pub mod indexeddb;
+
+#[cfg(feature = "manager-instrumented")]
+pub mod instrumented;
+
+#[cfg(feature = "manager-lmdb")]
+pub mod lmdb;
+
+#[cfg(feature = "manager-memcached")]
+pub mod memcached;
+
 #[cfg(feature = "manager-moka")]
 pub mod moka;
+
+#[cfg(feature = "manager-mongodb")]
+pub mod mongodb;
+
+#[cfg(feature = "manager-object-store")]
+pub mod object_store;
+
+#[cfg(feature = "manager-postgres")]
+pub mod postgres;
+
+#[cfg(feature = "manager-redis")]
+pub mod redis;
+
+#[cfg(feature = "manager-replay")]
+pub mod replay;
+
+#[cfg(feature = "manager-rocksdb")]
+pub mod rocksdb;
+
+#[cfg(feature = "manager-sled")]
+pub mod sled;
+
+pub mod memory;
+
+pub mod noop;
+
+pub mod quota;
+
+pub mod tiered;
+
+// The records of the managers storing them with bincode are the response and
+// its policy, serialized one after the other. That layout is frozen, checked
+// against the fixtures under `fixtures/`, and changing it has to keep reading
+// the records of the previous one, like the legacy layout below.
+#[cfg(any(
+    feature = "manager-cacache",
+    all(feature = "manager-indexeddb", target_arch = "wasm32"),
+    feature = "manager-lmdb",
+    feature = "manager-memcached",
+    feature = "manager-moka",
+    feature = "manager-redis",
+    feature = "manager-sled"
+))]
+mod meta {
+    use crate::{HttpHeaders, HttpResponse, HttpVersion, Result};
+
+    use std::collections::HashMap;
+
+    use http_cache_semantics::CachePolicy;
+    use serde::Deserialize;
+    use url::Url;
+
+    // Mirrors the bincode layout of the stored `HttpResponse`, borrowing the
+    // body instead of copying it so it can be skipped.
+    #[derive(Deserialize)]
+    struct ResponseMeta<'a> {
+        #[allow(dead_code)]
+        body: &'a [u8],
+        headers: HttpHeaders,
+        status: u16,
+        url: Url,
+        version: HttpVersion,
+    }
+
+    #[derive(Deserialize)]
+    struct StoreMeta<'a> {
+        #[serde(borrow)]
+        response: ResponseMeta<'a>,
+        policy: CachePolicy,
+    }
+
+    // The layout of stores written before repeated headers were kept, with
+    // a single value per header name
+    #[derive(Deserialize)]
+    struct LegacyResponse<'a> {
+        body: &'a [u8],
+        headers: HashMap<String, String>,
+        status: u16,
+        url: Url,
+        version: HttpVersion,
+    }
+
+    #[derive(Deserialize)]
+    struct LegacyStore<'a> {
+        #[serde(borrow)]
+        response: LegacyResponse<'a>,
+        policy: CachePolicy,
+    }
+
+    // Whether the bytes are a store in the current layout, rather than one
+    // that has to be migrated
+    #[cfg(feature = "manager-cacache")]
+    pub(crate) fn is_current(bytes: &[u8]) -> bool {
+        bincode::deserialize::<StoreMeta>(bytes).is_ok()
+    }
+
+    // Deserializes a bincode serialized store without its body
+    pub(crate) fn deserialize(
+        bytes: &[u8],
+    ) -> Result<(HttpResponse, CachePolicy)> {
+        let store: StoreMeta = match bincode::deserialize(bytes) {
+            Ok(store) => store,
+            Err(e) => {
+                let (mut response, policy) = deserialize_legacy(bytes, e)?;
+                response.body = Vec::new();
+                return Ok((response, policy));
+            }
+        };
+        let response = HttpResponse {
+            body: Vec::new(),
+            headers: store.response.headers,
+            status: store.response.status,
+            url: store.response.url,
+            version: store.response.version,
+        };
+        Ok((response, store.policy))
+    }
+
+    // Deserializes a bincode serialized store, body included
+    pub(crate) fn deserialize_store(
+        bytes: &[u8],
+    ) -> Result<(HttpResponse, CachePolicy)> {
+        match bincode::deserialize(bytes) {
+            Ok(store) => Ok(store),
+            Err(e) => deserialize_legacy(bytes, e),
+        }
+    }
+
+    // Falls back to the legacy layout, reporting the error of the current
+    // one when the bytes don't match either
+    fn deserialize_legacy(
+        bytes: &[u8],
+        error: bincode::Error,
+    ) -> Result<(HttpResponse, CachePolicy)> {
+        let store: LegacyStore =
+            bincode::deserialize(bytes).map_err(|_| error)?;
+        let response = HttpResponse {
+            body: store.response.body.to_vec(),
+            headers: store.response.headers.into(),
+            status: store.response.status,
+            url: store.response.url,
+            version: store.response.version,
+        };
+        Ok((response, store.policy))
+    }
+}
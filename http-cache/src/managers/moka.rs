@@ -1,4 +1,4 @@
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{CacheManager, CacheStats, HttpResponse, Result};
 
 use std::{fmt, sync::Arc};
 
@@ -38,6 +38,20 @@ impl MokaManager {
     pub fn new(cache: Cache<String, Arc<Vec<u8>>>) -> Self {
         Self { cache: Arc::new(cache) }
     }
+    /// Create a new manager bounded by the total size in bytes of the
+    /// stored entries rather than the number of entries.
+    pub fn with_byte_capacity(bytes: u64) -> Self {
+        Self::new(
+            Cache::builder().weigher(Self::weigher).max_capacity(bytes).build(),
+        )
+    }
+    /// Weighs an entry by the size of its key and serialized value in bytes,
+    /// can be passed to `MokaCacheBuilder::weigher` when building a custom cache.
+    // The signature is dictated by moka's weigher closure
+    #[allow(clippy::ptr_arg)]
+    pub fn weigher(key: &String, value: &Arc<Vec<u8>>) -> u32 {
+        u32::try_from(key.len() + value.len()).unwrap_or(u32::MAX)
+    }
     /// Clears out the entire cache.
     pub async fn clear(&self) -> Result<()> {
         self.cache.invalidate_all();
@@ -52,11 +66,20 @@ impl CacheManager for MokaManager {
         &self,
         cache_key: &str,
     ) -> Result<Option<(HttpResponse, CachePolicy)>> {
-        let store: Store = match self.cache.get(cache_key).await {
-            Some(d) => bincode::deserialize(&d)?,
-            None => return Ok(None),
-        };
-        Ok(Some((store.response, store.policy)))
+        match self.cache.get(cache_key).await {
+            Some(d) => Ok(Some(super::meta::deserialize_store(&d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.cache.get(cache_key).await {
+            Some(d) => Ok(Some(super::meta::deserialize(&d)?)),
+            None => Ok(None),
+        }
     }
 
     async fn put(
@@ -72,9 +95,72 @@ impl CacheManager for MokaManager {
         Ok(response)
     }
 
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let entry =
+            self.cache.entry(cache_key).or_insert(Arc::new(bytes)).await;
+        self.cache.run_pending_tasks().await;
+        Ok((data.response, entry.is_fresh()))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.cache.contains_key(cache_key))
+    }
+
     async fn delete(&self, cache_key: &str) -> Result<()> {
         self.cache.invalidate(cache_key).await;
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+
+    async fn clear(&self) -> Result<()> {
+        MokaManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let cache_keys = self
+            .cache
+            .iter()
+            .filter(|(cache_key, _)| predicate(cache_key))
+            .map(|(cache_key, _)| cache_key)
+            .collect::<Vec<_>>();
+        for cache_key in cache_keys {
+            self.cache.invalidate(cache_key.as_str()).await;
+        }
+        self.cache.run_pending_tasks().await;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .cache
+            .iter()
+            .map(|(cache_key, _)| cache_key.to_string())
+            .collect())
+    }
+
+    // The entries are counted by moka, once the pending writes are applied,
+    // and their size is that of the serialized records
+    async fn stats(&self) -> Result<CacheStats> {
+        self.cache.run_pending_tasks().await;
+        Ok(CacheStats {
+            entries: usize::try_from(self.cache.entry_count())
+                .unwrap_or(usize::MAX),
+            bytes: self.cache.iter().map(|(_, value)| value.len() as u64).sum(),
+        })
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.cache.run_pending_tasks().await;
+        Ok(())
+    }
 }
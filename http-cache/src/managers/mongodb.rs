@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use mongodb::{
+    bson::{doc, spec::BinarySubtype, Binary, Bson, DateTime, Document},
+    options::{
+        CountOptions, FindOneOptions, FindOptions, IndexOptions, ReplaceOptions,
+    },
+    Collection, Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+// The serialized response without its body, and the policy
+const METADATA: &str = "metadata";
+// The body of the response
+const BODY: &str = "body";
+// When the TTL index lets MongoDB remove the document
+const EXPIRES_AT: &str = "expires_at";
+
+// MongoDB rejects documents larger than this
+const MAX_DOCUMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Implements [`CacheManager`] with [`MongoDB`](https://www.mongodb.com) as the backend,
+/// using the [official driver](https://github.com/mongodb/mongo-rust-driver).
+///
+/// Each entry is a document keyed by the cache key. The collection has a TTL
+/// index on `expires_at`, so MongoDB removes the documents of stale
+/// responses. Responses too large for a document aren't stored.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-mongodb")))]
+#[derive(Clone)]
+pub struct MongoManager {
+    /// The collection the entries are stored in
+    pub collection: Collection<Document>,
+    /// How long a stale response is kept, so it can still be revalidated,
+    /// before MongoDB may remove it. Zero by default.
+    pub keep_stale_for: Duration,
+}
+
+impl fmt::Debug for MongoManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MongoManager")
+            .field("collection", &self.collection.name())
+            .field("keep_stale_for", &self.keep_stale_for)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl MongoManager {
+    /// Create a new manager storing the entries in the `http_cache`
+    /// collection of the database, creating its TTL index if it's missing.
+    pub async fn new(database: &Database) -> Result<Self> {
+        Self::with_collection(database.collection("http_cache")).await
+    }
+    /// Create a new manager storing the entries in the collection, creating
+    /// its TTL index if it's missing.
+    pub async fn with_collection(
+        collection: Collection<Document>,
+    ) -> Result<Self> {
+        let manager = Self { collection, keep_stale_for: Duration::ZERO };
+        manager.create_indexes().await?;
+        Ok(manager)
+    }
+    /// Sets how long a stale response is kept before MongoDB may remove it
+    #[must_use]
+    pub fn keep_stale_for(mut self, keep_stale_for: Duration) -> Self {
+        self.keep_stale_for = keep_stale_for;
+        self
+    }
+    /// Creates the TTL index on `expires_at` if it doesn't exist.
+    pub async fn create_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { EXPIRES_AT: 1 })
+            .options(
+                IndexOptions::builder().expire_after(Duration::ZERO).build(),
+            )
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+    /// Clears out the entire cache, every document in the collection is removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.collection.delete_many(doc! {}, None).await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for MongoManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some(document) =
+            self.collection.find_one(doc! { "_id": cache_key }, None).await?
+        else {
+            return Ok(None);
+        };
+        let mut store: Store =
+            bincode::deserialize(document.get_binary_generic(METADATA)?)?;
+        store.response.body = document.get_binary_generic(BODY)?.clone();
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let options =
+            FindOneOptions::builder().projection(doc! { METADATA: 1 }).build();
+        let Some(document) = self
+            .collection
+            .find_one(doc! { "_id": cache_key }, options)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let store: Store =
+            bincode::deserialize(document.get_binary_generic(METADATA)?)?;
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let body = std::mem::take(&mut response.body);
+        let mut data = Store { response, policy };
+        let metadata = bincode::serialize(&data)?;
+        // Leaves room for the field names and the expiry
+        if cache_key.len() + metadata.len() + body.len() + 64
+            > MAX_DOCUMENT_SIZE
+        {
+            // Don't leave an older version of the response behind
+            CacheManager::delete(self, &cache_key).await?;
+            data.response.body = body;
+            return Ok(data.response);
+        }
+        let now = SystemTime::now();
+        let expires_at =
+            now + data.policy.time_to_live(now) + self.keep_stale_for;
+        let mut document = doc! {
+            "_id": &cache_key,
+            METADATA: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: metadata,
+            },
+            BODY: Binary { subtype: BinarySubtype::Generic, bytes: body },
+            EXPIRES_AT: DateTime::from_system_time(expires_at),
+        };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection
+            .replace_one(doc! { "_id": &cache_key }, &document, options)
+            .await?;
+        if let Some(Bson::Binary(body)) = document.remove(BODY) {
+            data.response.body = body.bytes;
+        }
+        Ok(data.response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let options = CountOptions::builder().limit(1).build();
+        let count = self
+            .collection
+            .count_documents(doc! { "_id": cache_key }, options)
+            .await?;
+        Ok(count > 0)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.collection.delete_one(doc! { "_id": cache_key }, None).await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        MongoManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let cache_keys: Vec<String> = self
+            .keys()
+            .await?
+            .into_iter()
+            .filter(|cache_key| predicate(cache_key))
+            .collect();
+        if !cache_keys.is_empty() {
+            self.collection
+                .delete_many(doc! { "_id": { "$in": cache_keys } }, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let options =
+            FindOptions::builder().projection(doc! { "_id": 1 }).build();
+        let mut cursor = self.collection.find(doc! {}, options).await?;
+        let mut cache_keys = Vec::new();
+        while cursor.advance().await? {
+            cache_keys.push(cursor.current().get_str("_id")?.to_owned());
+        }
+        Ok(cache_keys)
+    }
+}
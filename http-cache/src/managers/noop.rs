@@ -0,0 +1,66 @@
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+
+/// Implements [`CacheManager`] by storing nothing, so every lookup misses.
+///
+/// Swapping it in turns caching off while keeping the same middleware stack,
+/// such as when caching is toggled by configuration.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopManager;
+
+impl NoopManager {
+    /// Create a new manager
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for NoopManager {
+    async fn get(
+        &self,
+        _cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(None)
+    }
+
+    async fn put(
+        &self,
+        _cache_key: String,
+        response: HttpResponse,
+        _policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        Ok(response)
+    }
+
+    async fn get_meta(
+        &self,
+        _cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(None)
+    }
+
+    async fn contains(&self, _cache_key: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn delete(&self, _cache_key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn delete_matching(
+        &self,
+        _predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use object_store::{path::Path, ObjectStore};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+
+type Index = HashMap<String, (HttpResponse, CachePolicy)>;
+
+/// Implements [`CacheManager`] with an [`ObjectStore`](https://github.com/apache/arrow-rs/tree/master/object_store)
+/// as the backend, such as S3, Google Cloud Storage or Azure Blob Storage.
+///
+/// Each response is stored as one object under the prefix. The metadata of
+/// the responses this manager has seen is indexed in memory, so freshness
+/// checks don't download the body.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-object-store")))]
+#[derive(Clone)]
+pub struct ObjectStoreManager {
+    /// The store holding the responses
+    pub store: Arc<dyn ObjectStore>,
+    /// The directory the objects are stored in, `http-cache` by default
+    pub prefix: Path,
+    /// The most responses kept in the metadata index, 10,000 by default.
+    /// The index is emptied when it fills up.
+    pub index_capacity: usize,
+    index: Arc<RwLock<Index>>,
+}
+
+impl fmt::Debug for ObjectStoreManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ObjectStoreManager")
+            .field("store", &self.store.to_string())
+            .field("prefix", &self.prefix.as_ref())
+            .field("index_capacity", &self.index_capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl ObjectStoreManager {
+    /// Create a new manager from the store, the bucket is part of its configuration
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            store,
+            prefix: Path::from("http-cache"),
+            index_capacity: 10_000,
+            index: Default::default(),
+        }
+    }
+    /// Sets the directory the objects are stored in, so several caches can share a bucket
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<Path>) -> Self {
+        self.prefix = prefix.into();
+        self.index = Default::default();
+        self
+    }
+    /// Sets the most responses kept in the metadata index
+    #[must_use]
+    pub fn index_capacity(mut self, index_capacity: usize) -> Self {
+        self.index_capacity = index_capacity;
+        self
+    }
+    /// Clears out the entire cache, only the objects under the prefix are removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.delete_objects(&|_| true).await
+    }
+
+    // The cache key is percent-encoded into a single path segment
+    fn location(&self, cache_key: &str) -> Path {
+        self.prefix.child(cache_key)
+    }
+
+    // Indexes the metadata of a response, without the body
+    fn remember(
+        &self,
+        cache_key: &str,
+        response: &HttpResponse,
+        policy: &CachePolicy,
+    ) {
+        let meta = HttpResponse {
+            body: Vec::new(),
+            headers: response.headers.clone(),
+            status: response.status,
+            url: response.url.clone(),
+            version: response.version,
+        };
+        let mut index = self.index.write().unwrap();
+        if index.len() >= self.index_capacity {
+            index.clear();
+        }
+        index.insert(cache_key.into(), (meta, policy.clone()));
+    }
+
+    // Lists the objects under the prefix along with the cache keys they hold
+    async fn list_objects(&self) -> Result<Vec<(Path, String)>> {
+        let listed = self.store.list_with_delimiter(Some(&self.prefix)).await?;
+        let mut objects = Vec::new();
+        for object in listed.objects {
+            let Some(filename) = object.location.filename() else {
+                continue;
+            };
+            let cache_key =
+                percent_decode_str(filename).decode_utf8()?.into_owned();
+            objects.push((object.location, cache_key));
+        }
+        Ok(objects)
+    }
+
+    // Removes the objects under the prefix whose cache key matches the predicate
+    async fn delete_objects(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        for (location, cache_key) in self.list_objects().await? {
+            if predicate(&cache_key) {
+                ignore_not_found(self.store.delete(&location).await)?;
+            }
+        }
+        self.index
+            .write()
+            .unwrap()
+            .retain(|cache_key, _| !predicate(cache_key));
+        Ok(())
+    }
+}
+
+// A missing object is treated the same as an empty result
+fn ignore_not_found<T: Default>(result: object_store::Result<T>) -> Result<T> {
+    match result {
+        Err(object_store::Error::NotFound { .. }) => Ok(T::default()),
+        result => Ok(result?),
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for ObjectStoreManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let bytes = match self.store.get(&self.location(cache_key)).await {
+            Ok(result) => result.bytes().await?,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let store: Store = bincode::deserialize(&bytes)?;
+        self.remember(cache_key, &store.response, &store.policy);
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        if let Some(meta) = self.index.read().unwrap().get(cache_key) {
+            return Ok(Some(meta.clone()));
+        }
+        Ok(self.get(cache_key).await?.map(|(mut response, policy)| {
+            response.body = Vec::new();
+            (response, policy)
+        }))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        self.store.put(&self.location(&cache_key), bytes.into()).await?;
+        self.remember(&cache_key, &data.response, &data.policy);
+        Ok(data.response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        if self.index.read().unwrap().contains_key(cache_key) {
+            return Ok(true);
+        }
+        match self.store.head(&self.location(cache_key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.index.write().unwrap().remove(cache_key);
+        ignore_not_found(self.store.delete(&self.location(cache_key)).await)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        ObjectStoreManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.delete_objects(predicate).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let objects = self.list_objects().await?;
+        Ok(objects.into_iter().map(|(_, cache_key)| cache_key).collect())
+    }
+}
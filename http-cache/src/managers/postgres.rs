@@ -0,0 +1,403 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    error::UnsupportedSchema, CacheManager, CacheStats, HttpResponse, Result,
+};
+
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// Implements [`CacheManager`] with [`PostgreSQL`](https://www.postgresql.org) as the backend,
+/// using a [`sqlx`](https://github.com/launchbadge/sqlx) connection pool.
+///
+/// Each entry is a row of the table, keyed by the cache key. The body is kept
+/// in its own column, so looking up the metadata of a response doesn't read it.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-postgres")))]
+#[derive(Clone)]
+pub struct PostgresManager {
+    /// The pool the connections to the database are taken from
+    pub pool: PgPool,
+    table: String,
+    queries: Arc<Queries>,
+}
+
+impl fmt::Debug for PostgresManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PostgresManager")
+            .field("table", &self.table)
+            .finish_non_exhaustive()
+    }
+}
+
+// The version of the layout of the table and of the records stored in it,
+// kept in a table of its own. Changing either has to bump it, adding the
+// statements that bring a table of the previous version up to date.
+const SCHEMA_VERSION: i32 = 1;
+
+// The statements bringing the table from each version to the next, with `{t}`
+// standing for its name. Records that can't be converted are removed, as
+// they're only responses that will be fetched again.
+const MIGRATIONS: [&[&str]; SCHEMA_VERSION as usize] = [
+    // Tables created before versions were kept have the same layout
+    &[],
+];
+
+// Quoted, so any name can be used without being read as SQL
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// The statements are built once per table, so each connection of the pool
+// prepares them on first use and reuses them after that
+#[derive(Debug)]
+struct Queries {
+    create: String,
+    create_schema: String,
+    lock_schema: String,
+    version: String,
+    clear_version: String,
+    set_version: String,
+    get: String,
+    get_meta: String,
+    get_many: String,
+    put: String,
+    put_many: String,
+    put_if_absent: String,
+    contains: String,
+    delete: String,
+    delete_any: String,
+    delete_prefix: String,
+    keys: String,
+    stats: String,
+    clear: String,
+}
+
+impl Queries {
+    fn new(table: &str) -> Self {
+        let t = quote(table);
+        let s = quote(&format!("{table}_schema"));
+        Self {
+            create: format!(
+                "CREATE TABLE IF NOT EXISTS {t} (\
+                 cache_key TEXT PRIMARY KEY, \
+                 metadata BYTEA NOT NULL, \
+                 body BYTEA NOT NULL)"
+            ),
+            create_schema: format!(
+                "CREATE TABLE IF NOT EXISTS {s} (version INTEGER NOT NULL)"
+            ),
+            lock_schema: format!("LOCK TABLE {s} IN EXCLUSIVE MODE"),
+            version: format!("SELECT MAX(version) FROM {s}"),
+            clear_version: format!("DELETE FROM {s}"),
+            set_version: format!("INSERT INTO {s} (version) VALUES ($1)"),
+            get: format!("SELECT metadata, body FROM {t} WHERE cache_key = $1"),
+            get_meta: format!("SELECT metadata FROM {t} WHERE cache_key = $1"),
+            get_many: format!(
+                "SELECT cache_key, metadata, body FROM {t} \
+                 WHERE cache_key = ANY($1)"
+            ),
+            put: format!(
+                "INSERT INTO {t} (cache_key, metadata, body) \
+                 VALUES ($1, $2, $3) \
+                 ON CONFLICT (cache_key) DO UPDATE \
+                 SET metadata = EXCLUDED.metadata, body = EXCLUDED.body"
+            ),
+            put_many: format!(
+                "INSERT INTO {t} (cache_key, metadata, body) \
+                 SELECT * FROM UNNEST($1::TEXT[], $2::BYTEA[], $3::BYTEA[]) \
+                 ON CONFLICT (cache_key) DO UPDATE \
+                 SET metadata = EXCLUDED.metadata, body = EXCLUDED.body"
+            ),
+            put_if_absent: format!(
+                "INSERT INTO {t} (cache_key, metadata, body) \
+                 VALUES ($1, $2, $3) \
+                 ON CONFLICT (cache_key) DO NOTHING"
+            ),
+            contains: format!(
+                "SELECT EXISTS (SELECT 1 FROM {t} WHERE cache_key = $1)"
+            ),
+            delete: format!("DELETE FROM {t} WHERE cache_key = $1"),
+            delete_any: format!("DELETE FROM {t} WHERE cache_key = ANY($1)"),
+            delete_prefix: format!(
+                "DELETE FROM {t} WHERE cache_key LIKE $1 ESCAPE '\\'"
+            ),
+            keys: format!("SELECT cache_key FROM {t}"),
+            stats: format!(
+                "SELECT COUNT(*), COALESCE(SUM(\
+                 OCTET_LENGTH(metadata) + OCTET_LENGTH(body)), 0)::BIGINT \
+                 FROM {t}"
+            ),
+            clear: format!("DELETE FROM {t}"),
+        }
+    }
+}
+
+// The response is stored without its body, which has a column of its own
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl PostgresManager {
+    /// Create a new manager storing the entries in the `http_cache` table,
+    /// which is created if it's missing.
+    pub async fn new(pool: PgPool) -> Result<Self> {
+        Self::with_table(pool, "http_cache").await
+    }
+    /// Create a new manager storing the entries in the table, which is
+    /// created if it's missing. The version of its schema is kept in the
+    /// `<table>_schema` table.
+    pub async fn with_table(
+        pool: PgPool,
+        table: impl Into<String>,
+    ) -> Result<Self> {
+        let table = table.into();
+        let queries = Arc::new(Queries::new(&table));
+        let manager = Self { pool, table, queries };
+        manager.create_tables().await?;
+        Ok(manager)
+    }
+    /// Creates the table the entries are stored in if it doesn't exist, and
+    /// migrates a table created by an older version of the manager. A table
+    /// migrated by a newer version fails with an [`UnsupportedSchema`] error
+    /// rather than having its records misread.
+    pub async fn create_tables(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.queries.create).execute(&mut *tx).await?;
+        sqlx::query(&self.queries.create_schema).execute(&mut *tx).await?;
+        // Managers created at the same time wait for the first to migrate
+        sqlx::query(&self.queries.lock_schema).execute(&mut *tx).await?;
+        let version: Option<i32> = sqlx::query_scalar(&self.queries.version)
+            .fetch_one(&mut *tx)
+            .await?;
+        // Tables created before versions were kept are at version 0
+        let version = usize::try_from(version.unwrap_or(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(Box::new(UnsupportedSchema));
+        }
+        if version < MIGRATIONS.len() {
+            let t = quote(&self.table);
+            for statement in MIGRATIONS[version..].iter().copied().flatten() {
+                sqlx::query(&statement.replace("{t}", &t))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(&self.queries.clear_version).execute(&mut *tx).await?;
+            sqlx::query(&self.queries.set_version)
+                .bind(SCHEMA_VERSION)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+    /// Clears out the entire cache, every row of the table is removed.
+    pub async fn clear(&self) -> Result<()> {
+        sqlx::query(&self.queries.clear).execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for PostgresManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let row: Option<(Vec<u8>, Vec<u8>)> = sqlx::query_as(&self.queries.get)
+            .bind(cache_key)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some((metadata, body)) = row else {
+            return Ok(None);
+        };
+        let mut store: Store = bincode::deserialize(&metadata)?;
+        store.response.body = body;
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let metadata: Option<Vec<u8>> =
+            sqlx::query_scalar(&self.queries.get_meta)
+                .bind(cache_key)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(metadata) = metadata else {
+            return Ok(None);
+        };
+        let store: Store = bincode::deserialize(&metadata)?;
+        Ok(Some((store.response, store.policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let body = std::mem::take(&mut response.body);
+        let mut data = Store { response, policy };
+        let metadata = bincode::serialize(&data)?;
+        sqlx::query(&self.queries.put)
+            .bind(&cache_key)
+            .bind(&metadata)
+            .bind(&body)
+            .execute(&self.pool)
+            .await?;
+        data.response.body = body;
+        Ok(data.response)
+    }
+
+    // Read with a single query, then put back in the order of the keys
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let rows: Vec<(String, Vec<u8>, Vec<u8>)> =
+            sqlx::query_as(&self.queries.get_many)
+                .bind(cache_keys)
+                .fetch_all(&self.pool)
+                .await?;
+        let rows: HashMap<String, (Vec<u8>, Vec<u8>)> = rows
+            .into_iter()
+            .map(|(cache_key, metadata, body)| (cache_key, (metadata, body)))
+            .collect();
+        cache_keys
+            .iter()
+            .map(|cache_key| {
+                // A key given more than once gets the same row each time
+                let Some((metadata, body)) = rows.get(*cache_key) else {
+                    return Ok(None);
+                };
+                let mut store: Store = bincode::deserialize(metadata)?;
+                store.response.body = body.clone();
+                Ok(Some((store.response, store.policy)))
+            })
+            .collect()
+    }
+
+    // Written with a single statement, which can't update a row twice, so
+    // only the last response of a key given more than once is kept
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        let mut rows: HashMap<String, (Vec<u8>, Vec<u8>)> = HashMap::new();
+        for (cache_key, mut response, policy) in entries {
+            let body = std::mem::take(&mut response.body);
+            let metadata = bincode::serialize(&Store { response, policy })?;
+            rows.insert(cache_key, (metadata, body));
+        }
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut cache_keys = Vec::with_capacity(rows.len());
+        let mut metadata = Vec::with_capacity(rows.len());
+        let mut bodies = Vec::with_capacity(rows.len());
+        for (cache_key, (meta, body)) in rows {
+            cache_keys.push(cache_key);
+            metadata.push(meta);
+            bodies.push(body);
+        }
+        sqlx::query(&self.queries.put_many)
+            .bind(cache_keys)
+            .bind(metadata)
+            .bind(bodies)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let body = std::mem::take(&mut response.body);
+        let mut data = Store { response, policy };
+        let metadata = bincode::serialize(&data)?;
+        let inserted = sqlx::query(&self.queries.put_if_absent)
+            .bind(&cache_key)
+            .bind(&metadata)
+            .bind(&body)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        data.response.body = body;
+        Ok((data.response, inserted == 1))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(sqlx::query_scalar(&self.queries.contains)
+            .bind(cache_key)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        sqlx::query(&self.queries.delete)
+            .bind(cache_key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        PostgresManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let cache_keys: Vec<String> = self
+            .keys()
+            .await?
+            .into_iter()
+            .filter(|cache_key| predicate(cache_key))
+            .collect();
+        if !cache_keys.is_empty() {
+            sqlx::query(&self.queries.delete_any)
+                .bind(&cache_keys)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        // The prefix is matched as a LIKE pattern, so escape its wildcards
+        let mut pattern = String::new();
+        for c in prefix.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+        sqlx::query(&self.queries.delete_prefix)
+            .bind(pattern)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar(&self.queries.keys).fetch_all(&self.pool).await?)
+    }
+
+    async fn stats(&self) -> Result<CacheStats> {
+        let (entries, bytes): (i64, i64) =
+            sqlx::query_as(&self.queries.stats).fetch_one(&self.pool).await?;
+        Ok(CacheStats {
+            entries: usize::try_from(entries)?,
+            bytes: u64::try_from(bytes)?,
+        })
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    CacheEntry, CacheManager, CacheStats, HttpResponse, PruneReport, Result,
+};
+
+use http_cache_semantics::{CacheOptions, CachePolicy};
+
+/// Implements [`CacheManager`] by bounding the size of another manager,
+/// removing the least recently used entries when a limit is exceeded.
+///
+/// The size and last use of each entry is tracked in memory. Entries stored
+/// before the manager was created, such as in an earlier run of the program,
+/// are tracked once they're read.
+#[derive(Clone)]
+pub struct QuotaManager<T> {
+    /// The manager the entries are stored in
+    pub inner: T,
+    /// The most bytes of responses to keep, unbounded by default
+    pub max_bytes: Option<u64>,
+    /// The most entries to keep, unbounded by default
+    pub max_entries: Option<usize>,
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for QuotaManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QuotaManager")
+            .field("inner", &self.inner)
+            .field("max_bytes", &self.max_bytes)
+            .field("max_entries", &self.max_entries)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    // The size and last use of each tracked entry
+    entries: HashMap<String, (u64, u64)>,
+    // The tracked entries by their last use, least recent first
+    recency: BTreeMap<u64, String>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl Usage {
+    // Tracks the entry as the most recently used, with a new size if given
+    fn touch(&mut self, cache_key: &str, size: Option<u64>) {
+        self.clock += 1;
+        let size = match self.entries.get(cache_key) {
+            Some(&(old_size, last_used)) => {
+                self.recency.remove(&last_used);
+                self.bytes -= old_size;
+                size.unwrap_or(old_size)
+            }
+            None => match size {
+                Some(size) => size,
+                None => return,
+            },
+        };
+        self.bytes += size;
+        self.entries.insert(cache_key.to_string(), (size, self.clock));
+        self.recency.insert(self.clock, cache_key.to_string());
+    }
+
+    fn remove(&mut self, cache_key: &str) {
+        if let Some((size, last_used)) = self.entries.remove(cache_key) {
+            self.recency.remove(&last_used);
+            self.bytes -= size;
+        }
+    }
+}
+
+// Approximates the space an entry takes by the size of its response
+fn size(response: &HttpResponse) -> u64 {
+    let headers: usize = response
+        .headers
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+    (response.body.len() + headers + response.url.as_str().len()) as u64
+}
+
+impl<T: CacheManager> QuotaManager<T> {
+    /// Create a new manager tracking the entries of the inner manager, with
+    /// no limits until they're set
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            max_bytes: None,
+            max_entries: None,
+            usage: Arc::default(),
+        }
+    }
+    /// Sets the most bytes of responses to keep
+    #[must_use]
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    /// Sets the most entries to keep
+    #[must_use]
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+    /// Returns the bytes taken by the tracked entries
+    pub fn bytes(&self) -> u64 {
+        self.usage.lock().unwrap().bytes
+    }
+    /// Returns the number of tracked entries
+    pub fn len(&self) -> usize {
+        self.usage.lock().unwrap().entries.len()
+    }
+    /// Returns true if no entries are tracked
+    pub fn is_empty(&self) -> bool {
+        self.usage.lock().unwrap().entries.is_empty()
+    }
+
+    // Untracks the least recently used entries until the limits are met,
+    // returning them so they can be removed from the inner manager
+    fn over_quota(&self) -> Vec<String> {
+        let mut usage = self.usage.lock().unwrap();
+        let mut evicted = Vec::new();
+        while self.max_bytes.map_or(false, |max| usage.bytes > max)
+            || self.max_entries.map_or(false, |max| usage.entries.len() > max)
+        {
+            let Some((_, cache_key)) = usage.recency.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = usage.entries.remove(&cache_key) {
+                usage.bytes -= size;
+            }
+            evicted.push(cache_key);
+        }
+        evicted
+    }
+
+    // Tracks the stored entry as the most recently used, then removes the
+    // least recently used ones until the limits are met again
+    async fn track(&self, cache_key: &str, size: u64) -> Result<()> {
+        self.usage.lock().unwrap().touch(cache_key, Some(size));
+        self.evict().await
+    }
+
+    // Removes the least recently used entries until the limits are met
+    async fn evict(&self) -> Result<()> {
+        let mut result = Ok(());
+        for cache_key in self.over_quota() {
+            // Keep removing the others even if one fails
+            result = result.and(self.inner.delete(&cache_key).await);
+        }
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: CacheManager> CacheManager for QuotaManager<T> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let store = self.inner.get(cache_key).await?;
+        {
+            let mut usage = self.usage.lock().unwrap();
+            match &store {
+                Some((response, _)) => {
+                    usage.touch(cache_key, Some(size(response)))
+                }
+                // Removed by the inner manager, such as when it expired
+                None => usage.remove(cache_key),
+            }
+        }
+        for cache_key in self.over_quota() {
+            self.inner.delete(&cache_key).await?;
+        }
+        Ok(store)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let size = size(&response);
+        if self.max_bytes.map_or(false, |max| size > max) {
+            // Too large to ever fit, don't leave an older version behind
+            self.delete(&cache_key).await?;
+            return Ok(response);
+        }
+        let response =
+            self.inner.put(cache_key.clone(), response, policy).await?;
+        self.track(&cache_key, size).await.map(|_| response)
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let stores = self.inner.get_many(cache_keys).await?;
+        {
+            let mut usage = self.usage.lock().unwrap();
+            for (cache_key, store) in cache_keys.iter().zip(&stores) {
+                match store {
+                    Some((response, _)) => {
+                        usage.touch(cache_key, Some(size(response)))
+                    }
+                    None => usage.remove(cache_key),
+                }
+            }
+        }
+        self.evict().await?;
+        Ok(stores)
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        let mut stored = Vec::with_capacity(entries.len());
+        let mut sizes = Vec::with_capacity(entries.len());
+        for (cache_key, response, policy) in entries {
+            let size = size(&response);
+            if self.max_bytes.map_or(false, |max| size > max) {
+                // Too large to ever fit, don't leave an older version behind
+                self.delete(&cache_key).await?;
+                continue;
+            }
+            sizes.push((cache_key.clone(), size));
+            stored.push((cache_key, response, policy));
+        }
+        self.inner.put_many(stored).await?;
+        {
+            let mut usage = self.usage.lock().unwrap();
+            for (cache_key, size) in &sizes {
+                usage.touch(cache_key, Some(*size));
+            }
+        }
+        self.evict().await
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let size = size(&response);
+        if self.max_bytes.map_or(false, |max| size > max) {
+            return Ok((response, false));
+        }
+        let (response, stored) = self
+            .inner
+            .put_if_absent(cache_key.clone(), response, policy)
+            .await?;
+        if !stored {
+            return Ok((response, false));
+        }
+        self.track(&cache_key, size).await.map(|_| (response, true))
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let store = self.inner.get_meta(cache_key).await?;
+        let mut usage = self.usage.lock().unwrap();
+        match store {
+            // The body isn't loaded, so only a tracked entry's size is known
+            Some(_) => usage.touch(cache_key, None),
+            None => usage.remove(cache_key),
+        }
+        Ok(store)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        self.inner.contains(cache_key).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.usage.lock().unwrap().remove(cache_key);
+        self.inner.delete(cache_key).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        *self.usage.lock().unwrap() = Usage::default();
+        self.inner.clear().await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.inner.delete_matching(predicate).await?;
+        let mut usage = self.usage.lock().unwrap();
+        let cache_keys: Vec<String> = usage
+            .entries
+            .keys()
+            .filter(|cache_key| predicate(cache_key))
+            .cloned()
+            .collect();
+        for cache_key in cache_keys {
+            usage.remove(&cache_key);
+        }
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.inner.delete_prefix(prefix).await?;
+        let mut usage = self.usage.lock().unwrap();
+        let cache_keys: Vec<String> = usage
+            .entries
+            .keys()
+            .filter(|cache_key| cache_key.starts_with(prefix))
+            .cloned()
+            .collect();
+        for cache_key in cache_keys {
+            usage.remove(&cache_key);
+        }
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.inner.keys().await
+    }
+
+    // Passed straight on so listing the records doesn't count as using them
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        self.inner.entries().await
+    }
+
+    // Passed straight on so checking the records doesn't count as using them,
+    // the pruned records are then untracked
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let report = self.inner.prune_expired(grace).await?;
+        if report.entries > 0 {
+            let cache_keys: HashSet<String> =
+                self.inner.keys().await?.into_iter().collect();
+            let mut usage = self.usage.lock().unwrap();
+            let pruned: Vec<String> = usage
+                .entries
+                .keys()
+                .filter(|cache_key| !cache_keys.contains(*cache_key))
+                .cloned()
+                .collect();
+            for cache_key in pruned {
+                usage.remove(&cache_key);
+            }
+        }
+        Ok(report)
+    }
+
+    // Passed straight on, as the entries stored before the manager was
+    // created aren't all tracked
+    async fn stats(&self) -> Result<CacheStats> {
+        self.inner.stats().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    fn cache_options(&self) -> Option<CacheOptions> {
+        self.inner.cache_options()
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+/// Implements [`CacheManager`] with [`redis`](https://github.com/redis-rs/redis-rs) as the backend.
+///
+/// Requests share a single multiplexed connection that is reconnected when
+/// it drops, so cloning the manager is cheap and no pool is needed.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-redis")))]
+#[derive(Clone)]
+pub struct RedisManager {
+    /// The connection to the Redis server
+    pub connection: ConnectionManager,
+    /// Prepended to every cache key, `http-cache:` by default
+    pub prefix: String,
+}
+
+impl fmt::Debug for RedisManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisManager")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl RedisManager {
+    /// Connects to the Redis server at the url, e.g. `redis://127.0.0.1/`
+    pub async fn new(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self::with_connection(ConnectionManager::new(client).await?))
+    }
+    /// Create a new manager from an established connection
+    pub fn with_connection(connection: ConnectionManager) -> Self {
+        Self { connection, prefix: "http-cache:".into() }
+    }
+    /// Sets the prefix of the keys, so several caches can share a server
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+    /// Clears out the entire cache, only the keys with the prefix are removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.delete_keys("", &|_| true).await
+    }
+
+    fn key(&self, cache_key: &str) -> String {
+        format!("{}{}", self.prefix, cache_key)
+    }
+
+    // Lists the keys with the prefix whose cache key starts with the given
+    // prefix and matches the predicate
+    async fn scan_keys(
+        &self,
+        cache_key_prefix: &str,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<Vec<String>> {
+        // The prefixes are matched as a glob pattern, so escape their wildcards
+        let mut pattern = String::new();
+        for c in self.prefix.chars().chain(cache_key_prefix.chars()) {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+        let mut connection = self.connection.clone();
+        let mut iter: redis::AsyncIter<String> =
+            connection.scan_match(pattern).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            if predicate(&key[self.prefix.len()..]) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    // Removes the keys with the prefix whose cache key starts with the given
+    // prefix and matches the predicate
+    async fn delete_keys(
+        &self,
+        cache_key_prefix: &str,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let keys = self.scan_keys(cache_key_prefix, predicate).await?;
+        let mut connection = self.connection.clone();
+        // Remove the keys in batches so a large cache isn't one huge command
+        for keys in keys.chunks(512) {
+            connection.del::<_, ()>(keys).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for RedisManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let mut connection = self.connection.clone();
+        match connection.get::<_, Option<Vec<u8>>>(self.key(cache_key)).await? {
+            Some(d) => Ok(Some(super::meta::deserialize_store(&d)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Read with a single MGET
+    async fn get_many(
+        &self,
+        cache_keys: &[&str],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        if cache_keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> =
+            cache_keys.iter().map(|cache_key| self.key(cache_key)).collect();
+        let mut connection = self.connection.clone();
+        let values: Vec<Option<Vec<u8>>> =
+            redis::cmd("MGET").arg(keys).query_async(&mut connection).await?;
+        values
+            .into_iter()
+            .map(|value| {
+                let Some(d) = value else { return Ok(None) };
+                let store: Store = bincode::deserialize(&d)?;
+                Ok(Some((store.response, store.policy)))
+            })
+            .collect()
+    }
+
+    // Written with a single MSET
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut items = Vec::with_capacity(entries.len());
+        for (cache_key, response, policy) in entries {
+            let bytes = bincode::serialize(&Store { response, policy })?;
+            items.push((self.key(&cache_key), bytes));
+        }
+        let mut connection = self.connection.clone();
+        redis::cmd("MSET")
+            .arg(&items)
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let mut connection = self.connection.clone();
+        match connection.get::<_, Option<Vec<u8>>>(self.key(cache_key)).await? {
+            Some(d) => Ok(Some(super::meta::deserialize(&d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let mut connection = self.connection.clone();
+        connection.set::<_, _, ()>(self.key(&cache_key), bytes).await?;
+        Ok(data.response)
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let mut connection = self.connection.clone();
+        let stored = connection.set_nx(self.key(&cache_key), bytes).await?;
+        Ok((data.response, stored))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        Ok(connection.exists(self.key(cache_key)).await?)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        connection.del::<_, ()>(self.key(cache_key)).await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        RedisManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.delete_keys("", predicate).await
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        self.delete_keys(prefix, &|_| true).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let keys = self.scan_keys("", &|_| true).await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| key[self.prefix.len()..].to_string())
+            .collect())
+    }
+}
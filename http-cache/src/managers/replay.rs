@@ -0,0 +1,231 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    error::NotRecorded, CacheManager, CacheMode, HttpHeaders, HttpResponse,
+    HttpVersion, Result,
+};
+
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The modes of a [`ReplayManager`]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-replay")))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Stores the responses like any other cache and writes them to the
+    /// fixture, replacing what it held before.
+    Record,
+    /// Serves the responses in the fixture, failing with a [`NotRecorded`]
+    /// error for any other request. The fixture isn't changed.
+    Replay,
+    /// Stores and serves nothing, leaving the fixture untouched.
+    Passthrough,
+}
+
+impl ReplayMode {
+    /// Returns the [`CacheMode`] the cache should use in this mode. Recording
+    /// stores every successful response regardless of its headers, and
+    /// replaying serves them regardless of their age.
+    pub fn cache_mode(self) -> CacheMode {
+        match self {
+            Self::Record => CacheMode::IgnoreRules,
+            Self::Replay => CacheMode::OnlyIfCached,
+            Self::Passthrough => CacheMode::NoStore,
+        }
+    }
+}
+
+/// Implements [`CacheManager`] by recording responses to a fixture file and
+/// replaying them, for deterministic tests.
+///
+/// The fixture is a JSON object from the cache keys to the responses and
+/// their policies, written with sorted keys so it diffs cleanly when checked
+/// in. Bodies are written as text when they're valid UTF-8, and as an array
+/// of bytes otherwise.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-replay")))]
+#[derive(Clone)]
+pub struct ReplayManager {
+    /// Path of the fixture file
+    pub path: PathBuf,
+    /// Whether responses are recorded, replayed, or neither
+    pub mode: ReplayMode,
+    entries: Arc<RwLock<BTreeMap<String, (HttpResponse, CachePolicy)>>>,
+}
+
+impl fmt::Debug for ReplayManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReplayManager")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Fixture {
+    status: u16,
+    version: HttpVersion,
+    url: Url,
+    headers: HttpHeaders,
+    body: Body,
+    policy: CachePolicy,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Body {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl ReplayManager {
+    /// Create a new manager replaying the fixture file, or recording it if
+    /// it doesn't exist yet, so the first run records and the runs after it
+    /// replay.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mode =
+            if path.exists() { ReplayMode::Replay } else { ReplayMode::Record };
+        Self::with_mode(path, mode)
+    }
+    /// Create a new manager in the given mode. The fixture is read when
+    /// replaying, and must exist.
+    pub fn with_mode(
+        path: impl Into<PathBuf>,
+        mode: ReplayMode,
+    ) -> Result<Self> {
+        let path = path.into();
+        let mut entries = BTreeMap::new();
+        if mode == ReplayMode::Replay {
+            let fixtures: BTreeMap<String, Fixture> =
+                serde_json::from_slice(&fs::read(&path)?)?;
+            for (cache_key, fixture) in fixtures {
+                let body = match fixture.body {
+                    Body::Text(text) => text.into_bytes(),
+                    Body::Bytes(bytes) => bytes,
+                };
+                let response = HttpResponse {
+                    body,
+                    headers: fixture.headers,
+                    status: fixture.status,
+                    url: fixture.url,
+                    version: fixture.version,
+                };
+                entries.insert(cache_key, (response, fixture.policy));
+            }
+        }
+        Ok(Self { path, mode, entries: Arc::new(RwLock::new(entries)) })
+    }
+
+    // Applies a change to the recorded entries and writes them to the
+    // fixture, only when recording
+    fn record(
+        &self,
+        change: impl FnOnce(&mut BTreeMap<String, (HttpResponse, CachePolicy)>),
+    ) -> Result<()> {
+        if self.mode != ReplayMode::Record {
+            return Ok(());
+        }
+        // The lock is held while writing so the fixture ends up with the
+        // latest entries
+        let mut entries = self.entries.write().unwrap();
+        change(&mut entries);
+        let fixtures: BTreeMap<&String, Fixture> = entries
+            .iter()
+            .map(|(cache_key, (response, policy))| {
+                let body = match String::from_utf8(response.body.clone()) {
+                    Ok(text) => Body::Text(text),
+                    Err(e) => Body::Bytes(e.into_bytes()),
+                };
+                let fixture = Fixture {
+                    status: response.status,
+                    version: response.version,
+                    url: response.url.clone(),
+                    headers: response.headers.clone(),
+                    body,
+                    policy: policy.clone(),
+                };
+                (cache_key, fixture)
+            })
+            .collect();
+        // Going through a value sorts the keys of every object, including
+        // the headers and cache directives held in hash maps
+        let mut json =
+            serde_json::to_vec_pretty(&serde_json::to_value(fixtures)?)?;
+        json.push(b'\n');
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for ReplayManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let store = match self.mode {
+            ReplayMode::Passthrough => None,
+            _ => self.entries.read().unwrap().get(cache_key).cloned(),
+        };
+        if store.is_none() && self.mode == ReplayMode::Replay {
+            return Err(Box::new(NotRecorded));
+        }
+        Ok(store)
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.record(|entries| {
+            entries.insert(cache_key, (response.clone(), policy));
+        })?;
+        Ok(response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.mode != ReplayMode::Passthrough
+            && self.entries.read().unwrap().contains_key(cache_key))
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.record(|entries| {
+            entries.remove(cache_key);
+        })
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.record(|entries| entries.clear())
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.record(|entries| {
+            entries.retain(|cache_key, _| !predicate(cache_key))
+        })
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        if self.mode == ReplayMode::Passthrough {
+            return Ok(Vec::new());
+        }
+        Ok(self.entries.read().unwrap().keys().cloned().collect())
+    }
+}
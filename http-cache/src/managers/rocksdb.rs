@@ -0,0 +1,174 @@
+use std::{fmt, path::Path, sync::Arc, time::Duration};
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use rocksdb::{ColumnFamily, IteratorMode, Options, WriteBatch, DB};
+
+const RESPONSES: &str = "responses";
+const POLICIES: &str = "policies";
+
+/// Implements [`CacheManager`] with [`RocksDB`](https://github.com/rust-rocksdb/rust-rocksdb) as the backend.
+///
+/// The responses and their policies are kept in separate column families
+/// and written together in a single batch.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-rocksdb")))]
+#[derive(Clone)]
+pub struct RocksDbManager {
+    /// The database, opened with the `responses` and `policies` column families
+    pub db: Arc<DB>,
+}
+
+impl fmt::Debug for RocksDbManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RocksDbManager")
+            .field("path", &self.db.path())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RocksDbManager {
+    /// Opens the database in the directory, creating it if it's missing
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let db = DB::open_cf(&Self::options(), path, [RESPONSES, POLICIES])?;
+        Ok(Self { db: Arc::new(db) })
+    }
+    /// Opens the database in the directory, where compaction drops the
+    /// entries written longer ago than the ttl
+    pub fn with_ttl(path: impl AsRef<Path>, ttl: Duration) -> Result<Self> {
+        let db = DB::open_cf_with_ttl(
+            &Self::options(),
+            path,
+            [RESPONSES, POLICIES],
+            ttl,
+        )?;
+        Ok(Self { db: Arc::new(db) })
+    }
+    /// Clears out the entire cache.
+    pub async fn clear(&self) -> Result<()> {
+        self.delete_keys(&|_| true)
+    }
+
+    fn options() -> Options {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        options
+    }
+
+    fn column_family(&self, name: &str) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| format!("missing the {name} column family").into())
+    }
+
+    // Removes the entries whose cache key matches the predicate
+    fn delete_keys(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        // A ttl can drop one half of an entry before the other, so both
+        // column families are searched
+        for name in [RESPONSES, POLICIES] {
+            let column_family = self.column_family(name)?;
+            for entry in self.db.iterator_cf(column_family, IteratorMode::Start)
+            {
+                let (key, _) = entry?;
+                if std::str::from_utf8(&key).map_or(false, predicate) {
+                    batch.delete_cf(column_family, key);
+                }
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for RocksDbManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        // Read both halves from the same snapshot so they match
+        let snapshot = self.db.snapshot();
+        let response =
+            snapshot.get_cf(self.column_family(RESPONSES)?, cache_key)?;
+        let policy =
+            snapshot.get_cf(self.column_family(POLICIES)?, cache_key)?;
+        match (response, policy) {
+            (Some(response), Some(policy)) => Ok(Some((
+                bincode::deserialize(&response)?,
+                bincode::deserialize(&policy)?,
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let mut batch = WriteBatch::default();
+        batch.put_cf(
+            self.column_family(RESPONSES)?,
+            &cache_key,
+            bincode::serialize(&response)?,
+        );
+        batch.put_cf(
+            self.column_family(POLICIES)?,
+            &cache_key,
+            bincode::serialize(&policy)?,
+        );
+        self.db.write(batch)?;
+        Ok(response)
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        let snapshot = self.db.snapshot();
+        Ok(snapshot
+            .get_cf(self.column_family(RESPONSES)?, cache_key)?
+            .is_some()
+            && snapshot
+                .get_cf(self.column_family(POLICIES)?, cache_key)?
+                .is_some())
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(self.column_family(RESPONSES)?, cache_key);
+        batch.delete_cf(self.column_family(POLICIES)?, cache_key);
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        RocksDbManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        self.delete_keys(predicate)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let column_family = self.column_family(RESPONSES)?;
+        let mut cache_keys = Vec::new();
+        for entry in self.db.iterator_cf(column_family, IteratorMode::Start) {
+            let (key, _) = entry?;
+            cache_keys.push(String::from_utf8(key.into_vec())?);
+        }
+        Ok(cache_keys)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.db.flush_cf(self.column_family(RESPONSES)?)?;
+        self.db.flush_cf(self.column_family(POLICIES)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use crate::{CacheManager, HttpResponse, Result};
+
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+/// Implements [`CacheManager`] with [`sled`](https://github.com/spacejam/sled) as the backend.
+///
+/// The entries live in a single tree of the database, so the cache can share
+/// a database file with other data.
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-sled")))]
+#[derive(Debug, Clone)]
+pub struct SledManager {
+    /// The tree the entries are stored in
+    pub tree: sled::Tree,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Store {
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl SledManager {
+    /// Opens the database in the directory, storing the entries in its `http-cache` tree
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self::with_tree(db.open_tree("http-cache")?))
+    }
+    /// Create a new manager from a tree of an open database
+    pub fn with_tree(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+    /// Clears out the entire cache, only the entries in the tree are removed.
+    pub async fn clear(&self) -> Result<()> {
+        self.tree.clear()?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheManager for SledManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.tree.get(cache_key)? {
+            Some(d) => Ok(Some(super::meta::deserialize_store(&d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.tree.get(cache_key)? {
+            Some(d) => Ok(Some(super::meta::deserialize(&d)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        self.tree.insert(cache_key, bytes)?;
+        Ok(data.response)
+    }
+
+    async fn put_if_absent(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<(HttpResponse, bool)> {
+        let data = Store { response, policy };
+        let bytes = bincode::serialize(&data)?;
+        let absent: Option<&[u8]> = None;
+        let swapped =
+            self.tree.compare_and_swap(cache_key, absent, Some(bytes))?;
+        Ok((data.response, swapped.is_ok()))
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.tree.contains_key(cache_key)?)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.tree.remove(cache_key)?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        SledManager::clear(self).await
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        for key in self.tree.iter().keys() {
+            let key = key?;
+            if std::str::from_utf8(&key).map_or(false, predicate) {
+                self.tree.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        for key in self.tree.scan_prefix(prefix).keys() {
+            self.tree.remove(key?)?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let mut cache_keys = Vec::new();
+        for key in self.tree.iter().keys() {
+            cache_keys.push(String::from_utf8(key?.to_vec())?);
+        }
+        Ok(cache_keys)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::{CacheEntry, CacheManager, HttpResponse, PruneReport, Result};
+
+use http_cache_semantics::{CacheOptions, CachePolicy};
+
+/// Implements [`CacheManager`] by composing two managers, e.g. an in-memory
+/// manager in front of a persistent one. Reads check the first tier and fall
+/// back to the second, writes and removals go to both.
+#[derive(Debug, Clone)]
+pub struct TieredManager<A, B> {
+    /// The first tier, checked first on reads
+    pub first: A,
+    /// The second tier, checked when the first misses
+    pub second: B,
+    /// Copy responses found in the second tier to the first, enabled by default
+    pub promote: bool,
+}
+
+impl<A: CacheManager, B: CacheManager> TieredManager<A, B> {
+    /// Create a new manager with the first tier in front of the second
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second, promote: true }
+    }
+    /// Sets whether responses found in the second tier are copied to the first
+    #[must_use]
+    pub fn promote(mut self, promote: bool) -> Self {
+        self.promote = promote;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<A: CacheManager, B: CacheManager> CacheManager for TieredManager<A, B> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        if let Some(store) = self.first.get(cache_key).await? {
+            return Ok(Some(store));
+        }
+        match self.second.get(cache_key).await? {
+            Some((res, policy)) if self.promote => {
+                // The response was found, failing to promote it shouldn't
+                // fail the read
+                let _ = self
+                    .first
+                    .put(cache_key.to_string(), res.clone(), policy.clone())
+                    .await;
+                Ok(Some((res, policy)))
+            }
+            store => Ok(store),
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let response =
+            self.first.put(cache_key.clone(), response, policy.clone()).await?;
+        self.second.put(cache_key, response, policy).await
+    }
+
+    async fn get_meta(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.first.get_meta(cache_key).await? {
+            Some(store) => Ok(Some(store)),
+            None => self.second.get_meta(cache_key).await,
+        }
+    }
+
+    async fn contains(&self, cache_key: &str) -> Result<bool> {
+        Ok(self.first.contains(cache_key).await?
+            || self.second.contains(cache_key).await?)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        // Remove from both tiers even if the first fails
+        let first = self.first.delete(cache_key).await;
+        let second = self.second.delete(cache_key).await;
+        first.and(second)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let first = self.first.clear().await;
+        let second = self.second.clear().await;
+        first.and(second)
+    }
+
+    async fn delete_matching(
+        &self,
+        predicate: &(dyn for<'k> Fn(&'k str) -> bool + Send + Sync),
+    ) -> Result<()> {
+        let first = self.first.delete_matching(predicate).await;
+        let second = self.second.delete_matching(predicate).await;
+        first.and(second)
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        let first = self.first.delete_prefix(prefix).await;
+        let second = self.second.delete_prefix(prefix).await;
+        first.and(second)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let mut keys = self.first.keys().await?;
+        keys.extend(self.second.keys().await?);
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    // Listed from both tiers rather than read, so nothing is promoted. A
+    // record in both tiers is described by the first.
+    async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = self.first.entries().await?;
+        let listed: HashSet<String> =
+            entries.iter().map(|entry| entry.cache_key.clone()).collect();
+        entries.extend(
+            self.second
+                .entries()
+                .await?
+                .into_iter()
+                .filter(|entry| !listed.contains(&entry.cache_key)),
+        );
+        Ok(entries)
+    }
+
+    async fn prune_expired(&self, grace: Duration) -> Result<PruneReport> {
+        let first = self.first.prune_expired(grace).await?;
+        let second = self.second.prune_expired(grace).await?;
+        Ok(PruneReport {
+            entries: first.entries + second.entries,
+            bytes: first.bytes + second.bytes,
+        })
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let first = self.first.flush().await;
+        let second = self.second.flush().await;
+        first.and(second)
+    }
+
+    // Both tiers store the same policy, the first tier's options win
+    fn cache_options(&self) -> Option<CacheOptions> {
+        self.first.cache_options().or_else(|| self.second.cache_options())
+    }
+}